@@ -0,0 +1,24 @@
+const vscode = require("vscode");
+const { LanguageClient } = require("vscode-languageclient/node");
+
+let client;
+
+// Starts `gahl lsp` for `.gh` files, the binary comes from `gahl.server.path`.
+function activate(context) {
+  const command = vscode.workspace.getConfiguration("gahl").get("server.path") || "gahl";
+  const server = { command, args: ["lsp"] };
+
+  client = new LanguageClient(
+    "gahl",
+    "Gahl",
+    { run: server, debug: server },
+    { documentSelector: [{ scheme: "file", language: "gh" }] }
+  );
+  client.start();
+}
+
+function deactivate() {
+  return client ? client.stop() : undefined;
+}
+
+module.exports = { activate, deactivate };
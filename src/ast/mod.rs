@@ -3,6 +3,8 @@ use std::{collections::HashMap, ops::Range};
 
 use crate::parser::error::ParseError;
 
+pub mod pretty;
+
 #[derive(Debug)]
 pub enum Stmt {
     Expr(Expr, Vec<ParseError>),
@@ -14,6 +16,31 @@ pub enum Stmt {
 #[derive(Debug, Clone)]
 pub struct DocComment {
     pub md: String,
+    /// Row the first line of `md` is on.
+    pub row: usize,
+}
+
+impl DocComment {
+    /// From the literal of a `; ... ;` token starting on `row`, without the semicolons and
+    /// indentation.
+    pub fn new(literal: &str, row: usize) -> Self {
+        let inner = literal.strip_prefix(';').unwrap_or(literal);
+        let inner = inner.strip_suffix(';').unwrap_or(inner);
+        let leading_blank = inner.lines().take_while(|l| l.trim().is_empty()).count();
+
+        let md = inner
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join("\n")
+            .trim()
+            .to_string();
+
+        Self {
+            md,
+            row: row + leading_blank,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -40,6 +67,8 @@ pub struct Name {
     pub name: Vec<String>,
 
     pub location: Location,
+    /// Location of every segment in `name`, empty for synthesized names.
+    pub segments: Vec<Location>,
 }
 
 pub struct PhantomName {
@@ -50,7 +79,42 @@ pub struct PhantomName {
 
 impl Name {
     pub fn new(name: Vec<String>, location: Location) -> Self {
-        Self { name, location }
+        Self {
+            name,
+            location,
+            segments: vec![],
+        }
+    }
+
+    pub fn with_segments(name: Vec<String>, location: Location, segments: Vec<Location>) -> Self {
+        Self {
+            name,
+            location,
+            segments,
+        }
+    }
+
+    /// `self` followed by `other`, keeping the location of `other`.
+    pub fn join(&self, other: &Name) -> Self {
+        let mut name = self.name.clone();
+        name.extend(other.name.iter().cloned());
+
+        let mut segments = self.segments.clone();
+        segments.extend(other.segments.iter().cloned());
+
+        Self {
+            name,
+            location: other.location.clone(),
+            segments,
+        }
+    }
+
+    /// Location of the segment at `index`, falling back to the whole name.
+    pub fn segment_location(&self, index: usize) -> Location {
+        self.segments
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| self.location.clone())
     }
 
     pub fn from_path(path: &str) -> Self {
@@ -82,7 +146,7 @@ impl PhantomName {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum TypeValue {
     Undefined,
 
@@ -99,12 +163,16 @@ pub enum TypeValue {
     I32,
     I64,
     I128,
+    /// Pointer sized, its width depends on the target.
+    Isize,
 
     U8,
     U16,
     U32,
     U64,
     U128,
+    /// Pointer sized, its width depends on the target.
+    Usize,
 
     F32,
     F64,
@@ -162,8 +230,12 @@ impl FuncNode {
 
 #[derive(Debug)]
 pub enum Import {
-    ImportSingle(Name),
-    ImportGroup(Imports),
+    /// `a.b.c` or `a.b.c as d`.
+    ImportSingle(Name, Option<String>),
+    /// `a.b { ... }`, every import inside is prefixed with the name.
+    ImportGroup(Name, Imports),
+    /// `a.b.*`.
+    ImportAll(Name),
 }
 
 #[derive(Debug)]
@@ -249,12 +321,30 @@ impl Expr {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+/// `@name` or `@name("arg", ...)` in front of a declaration.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    /// String arguments, without their quotes.
+    pub args: Vec<String>,
+
+    pub location: Location,
+}
+
 #[derive(Debug)]
 pub struct Var {
     pub lhs: VarLhs,
     pub _type: Type,
     pub rhs: Expr,
     pub is_decl: bool,
+    pub visibility: Visibility,
+    pub attributes: Vec<Attribute>,
 
     pub location: Location,
     pub errors: Vec<ParseError>,
@@ -268,6 +358,8 @@ impl Var {
             _type: Type::default(),
             rhs: Expr::Void,
             is_decl: false,
+            visibility: Visibility::Private,
+            attributes: vec![],
             location: Location::default(),
             errors: vec![],
             doc_comments: vec![],
@@ -279,16 +371,46 @@ impl Var {
 pub enum ImportKey {
     Symbol(String),
     Module(String),
+    Wildcard(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportTarget {
+    /// Path of the module the import resolves to.
+    pub path: String,
+    /// Name of the item inside `path`, differs from the key when aliased.
+    pub symbol: Option<String>,
+
+    pub location: Location,
 }
 
 #[derive(Debug)]
 pub struct EnumDecl(pub Vec<(String, Vec<TypeValue>)>);
 
-#[derive(Debug)]
+/// An `extern fn` a module declares.
+#[derive(Debug, Clone)]
+pub struct ExternDecl {
+    pub params: Vec<(String, TypeValue)>,
+    pub return_type: TypeValue,
+    pub location: Location,
+}
+
+#[derive(Debug, Default)]
 pub struct Module {
     pub name: String,
-    pub imports: Option<HashMap<ImportKey, Option<String>>>,
-    pub fn_decls: HashMap<String, (Type, Location)>,
-    pub externs: HashMap<String, (Vec<(String, TypeValue)>, TypeValue)>,
+    /// Import path of the module, `std.io` is `["std", "io"]`.
+    pub path: Vec<String>,
+    /// Whether it's the entry module, the root of the import graph.
+    pub entry: bool,
+    pub imports: Option<HashMap<ImportKey, ImportTarget>>,
+    pub fn_decls: HashMap<String, (Type, Location, Visibility)>,
+    pub externs: HashMap<String, ExternDecl>,
     pub fn_defns: HashMap<String, (FuncNode, Location)>,
+    /// Attributes of the functions, from both their declaration and definition.
+    pub attributes: HashMap<String, Vec<Attribute>>,
+    /// Doc comments of the functions, from both their declaration and definition.
+    pub docs: HashMap<String, Vec<DocComment>>,
+    /// Doc comments that aren't directly above an item.
+    pub doc_comments: Vec<DocComment>,
+    pub errors: Vec<ParseError>,
 }
@@ -0,0 +1,191 @@
+use core::fmt;
+use std::fmt::Write;
+
+use super::{Expr, FuncNode, ImportKey, Module, Stmt, TypeValue, Visibility};
+
+impl fmt::Display for TypeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeValue::Undefined => write!(f, "?"),
+            TypeValue::Void => write!(f, "void"),
+            TypeValue::Ptr(inner) => write!(f, "*{inner}"),
+            TypeValue::Bool => write!(f, "bool"),
+            TypeValue::String => write!(f, "string"),
+            TypeValue::I8 => write!(f, "i8"),
+            TypeValue::I16 => write!(f, "i16"),
+            TypeValue::I32 => write!(f, "i32"),
+            TypeValue::I64 => write!(f, "i64"),
+            TypeValue::I128 => write!(f, "i128"),
+            TypeValue::Isize => write!(f, "isize"),
+            TypeValue::U8 => write!(f, "u8"),
+            TypeValue::U16 => write!(f, "u16"),
+            TypeValue::U32 => write!(f, "u32"),
+            TypeValue::U64 => write!(f, "u64"),
+            TypeValue::U128 => write!(f, "u128"),
+            TypeValue::Usize => write!(f, "usize"),
+            TypeValue::F32 => write!(f, "f32"),
+            TypeValue::F64 => write!(f, "f64"),
+            TypeValue::Array(inner) => write!(f, "[{inner}]"),
+            TypeValue::Generic(inner) => write!(f, "<{inner}>"),
+            TypeValue::Func(params, ret, _) => {
+                let params = params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                write!(f, "fn({}) {ret}", params.join(" "))
+            }
+            TypeValue::ExFunc((params, ret)) => {
+                let params = params
+                    .iter()
+                    .map(|(name, ty)| format!("{name}: {ty}"))
+                    .collect::<Vec<_>>();
+                write!(f, "extern fn({}) {ret}", params.join(", "))
+            }
+            TypeValue::Custom(name) => write!(f, "{name}"),
+            TypeValue::EnumVariant(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Indented tree of a parsed module, for `--emit=ast`. Everything is sorted by name so the
+/// output only changes when the source does.
+pub fn module_to_string(module: &Module) -> String {
+    let mut out = format!("module {}\n", module.name);
+
+    if let Some(imports) = &module.imports {
+        let mut imports = imports.iter().collect::<Vec<_>>();
+        imports.sort_by_key(|(key, _)| import_key_to_string(key));
+
+        for (key, target) in imports {
+            let item = match &target.symbol {
+                Some(symbol) => format!("{}.{symbol}", target.path),
+                None => target.path.clone(),
+            };
+            let _ = writeln!(out, "  import {} <- {item}", import_key_to_string(key));
+        }
+    }
+
+    let mut externs = module.externs.iter().collect::<Vec<_>>();
+    externs.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, decl) in externs {
+        let ty = TypeValue::ExFunc((decl.params.clone(), Box::new(decl.return_type.clone())));
+        let _ = writeln!(out, "  extern {name} : {ty}");
+    }
+
+    let mut decls = module.fn_decls.iter().collect::<Vec<_>>();
+    decls.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, (ty, _, visibility)) in decls {
+        let visibility = match visibility {
+            Visibility::Public => "pub ",
+            Visibility::Private => "",
+        };
+        let _ = writeln!(out, "  decl {visibility}{name} : {}", ty.type_value);
+    }
+
+    let mut defns = module.fn_defns.iter().collect::<Vec<_>>();
+    defns.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, (func, _)) in defns {
+        func_to_string(&mut out, name, func, 1);
+    }
+
+    for error in &module.errors {
+        let _ = writeln!(out, "  error {:?}", error.message);
+    }
+
+    out
+}
+
+/// Indented tree of a single statement, as the REPL shows it for `:ast`.
+pub fn stmt_tree(stmt: &Stmt) -> String {
+    let mut out = String::new();
+    stmt_to_string(&mut out, stmt, 0);
+    out
+}
+
+fn import_key_to_string(key: &ImportKey) -> String {
+    match key {
+        ImportKey::Symbol(symbol) => symbol.clone(),
+        ImportKey::Module(path) => format!("module {path}"),
+        ImportKey::Wildcard(path) => format!("{path}.*"),
+    }
+}
+
+fn indent(out: &mut String, depth: usize) {
+    out.push_str(&"  ".repeat(depth));
+}
+
+fn func_to_string(out: &mut String, name: &str, func: &FuncNode, depth: usize) {
+    indent(out, depth);
+    let _ = writeln!(out, "fn {name}({})", func.args.join(", "));
+
+    for stmt in &func.block {
+        stmt_to_string(out, stmt, depth + 1);
+    }
+}
+
+fn stmt_to_string(out: &mut String, stmt: &Stmt, depth: usize) {
+    match stmt {
+        Stmt::Expr(expr, _) => {
+            indent(out, depth);
+            out.push_str("expr\n");
+            expr_to_string(out, expr, depth + 1);
+        }
+        Stmt::Var(var) => {
+            indent(out, depth);
+            let lhs = var.lhs.name.join(", ");
+            let ty = match var._type.type_value {
+                TypeValue::Void => String::new(),
+                ref ty => format!(" : {ty}"),
+            };
+            let op = if var.is_decl { ":=" } else { "=" };
+            let _ = writeln!(out, "var {lhs}{ty} {op}");
+            expr_to_string(out, &var.rhs, depth + 1);
+        }
+        Stmt::Enum(decl) => {
+            indent(out, depth);
+            out.push_str("enum\n");
+
+            for (variant, fields) in &decl.0 {
+                indent(out, depth + 1);
+                let fields = fields.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+                let _ = writeln!(out, "{variant}({})", fields.join(", "));
+            }
+        }
+        Stmt::DocComment(doc) => {
+            indent(out, depth);
+            let _ = writeln!(out, "doc {:?}", doc.md);
+        }
+    }
+}
+
+fn expr_to_string(out: &mut String, expr: &Expr, depth: usize) {
+    indent(out, depth);
+
+    let (label, children): (String, Vec<&Expr>) = match expr {
+        Expr::Void => ("void".to_string(), vec![]),
+        Expr::String(value, _) => (format!("string {value}"), vec![]),
+        Expr::Int(value, _) => (format!("int {value}"), vec![]),
+        Expr::Uint(value, _) => (format!("uint {value}"), vec![]),
+        Expr::Float(value, _) => (format!("float {value}"), vec![]),
+        Expr::Neg(inner, _) => ("neg".to_string(), vec![inner]),
+        Expr::Identifier(name, _) => (format!("ident {name}"), vec![]),
+        Expr::FuncCall(name, args, _) => (format!("call {name}"), args.iter().collect()),
+        Expr::Func(func) => {
+            let _ = writeln!(out, "lambda");
+            func_to_string(out, &func.name.to_string(), func, depth + 1);
+            return;
+        }
+        Expr::Add(lhs, rhs, _) => ("add".to_string(), vec![lhs, rhs]),
+        Expr::Min(lhs, rhs, _) => ("min".to_string(), vec![lhs, rhs]),
+        Expr::Mul(lhs, rhs, _) => ("mul".to_string(), vec![lhs, rhs]),
+        Expr::Div(lhs, rhs, _) => ("div".to_string(), vec![lhs, rhs]),
+        Expr::Power(lhs, rhs, _) => ("pow".to_string(), vec![lhs, rhs]),
+        Expr::Paren(inner, _) => ("paren".to_string(), vec![inner]),
+    };
+
+    let _ = writeln!(out, "{label}");
+
+    for child in children {
+        expr_to_string(out, child, depth + 1);
+    }
+}
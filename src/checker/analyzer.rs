@@ -1,16 +1,68 @@
-use std::{
-    collections::HashMap,
-    sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
-        Arc, Mutex,
-    },
-    thread,
-};
+use std::{collections::HashMap, fs, sync::Arc, thread};
 
-use crate::ast::Module;
+use crate::{
+    ast::Module,
+    parser::{search::SearchPaths, Parser},
+};
 
 use super::{mdir::MiddleIR, CheckError, Checker};
 
+/// Modules by name, as the checker shares them.
+pub type Modules = Arc<HashMap<String, Arc<Module>>>;
+
+/// Parses the module `name` from `source` with everything it imports, then checks all of it with
+/// [`check_quietly`]. For code that's checked over and over like REPL input and doc-tests.
+pub fn analyze_source(
+    name: &str,
+    source: &str,
+    search_paths: SearchPaths,
+) -> Result<(Modules, HashMap<String, MiddleIR>), String> {
+    let mut parser = Parser::new(name, search_paths).with_source(source.to_string());
+    let modules = match parser.parse(name) {
+        Ok(m) => m,
+        Err(err) => return Err(format!("{}\n", err)),
+    };
+
+    check_quietly(modules, |module_name| match module_name == name {
+        true => source.to_string(),
+        false => fs::read_to_string(module_name).unwrap_or_default(),
+    })
+}
+
+/// Checks `modules` on the current thread and without progress output. Errors come back
+/// rendered, shown in the source `source_of` gives for their module.
+pub fn check_quietly(
+    modules: HashMap<String, Module>,
+    source_of: impl Fn(&str) -> String,
+) -> Result<(Modules, HashMap<String, MiddleIR>), String> {
+    let modules: Modules = Arc::new(modules.into_iter().map(|(n, m)| (n, Arc::new(m))).collect());
+
+    let mut errors = String::new();
+    let mut mdir_modules = HashMap::new();
+    for (module_name, module) in modules.iter() {
+        let mut checker = Checker::new(module, modules.clone());
+        let mdir = checker.types();
+
+        if !checker.errors().is_empty() {
+            errors += &checker.render_interrupts(&source_of(module_name));
+        }
+        mdir_modules.insert(module_name.clone(), mdir);
+    }
+
+    match errors.is_empty() {
+        true => Ok((modules, mdir_modules)),
+        false => Err(errors),
+    }
+}
+
+// Names of the modules `module` imports from.
+fn dependencies(module: &Module) -> impl Iterator<Item = &String> {
+    module
+        .imports
+        .iter()
+        .flat_map(|imports| imports.values().map(|target| &target.path))
+}
+
 pub struct Analyzer;
 
 impl Analyzer {
@@ -21,71 +73,112 @@ impl Analyzer {
     pub fn analyze(
         &mut self,
         modules: Arc<HashMap<String, Arc<Module>>>,
+        order: &[String],
     ) -> Result<HashMap<String, MiddleIR>, ()> {
-        let tasks = modules.len();
-        let task_counter = Arc::new(AtomicUsize::new(tasks));
-
-        let found_errors = Arc::new(AtomicBool::new(false));
-
-        // TODO: Create common error manager inface with context about where the error should be.
-        let modules = modules.clone();
-
-        // let done = task_counter.load(SeqCst) - tasks;
         println!(
             "\x1b[1m\x1b[32mStarting analysis of {} modules...\x1b[0m\n",
-            tasks
+            modules.len()
         );
 
-        let task_counter_c = task_counter.clone();
-        let found_errors_c = found_errors.clone();
-        let mdir_modules = thread::spawn(move || {
-            let mdir_modules = Arc::new(Mutex::new(HashMap::new()));
+        let mut mdir_modules = HashMap::new();
+        let mut found_errors = false;
+
+        let mut pending = order
+            .iter()
+            .filter(|name| modules.contains_key(*name))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        // Modules are checked in waves, each one after the modules it imports. The modules of a
+        // wave don't depend on each other and are checked concurrently.
+        while !pending.is_empty() {
+            let (mut wave, rest): (Vec<String>, Vec<String>) = pending
+                .iter()
+                .cloned()
+                .partition(|name| dependencies(&modules[name]).all(|dep| !pending.contains(dep)));
+            // Only a cycle leaves nothing ready, the parser rejects those before.
+            if wave.is_empty() {
+                wave = std::mem::take(&mut pending);
+            } else {
+                pending = rest;
+            }
 
-            let mdir_modules = mdir_modules.clone();
-            for (name, module) in modules.iter() {
-                let module_c = module.clone();
-                let name = name.clone();
+            let handles = wave
+                .into_iter()
+                .map(|name| {
+                    let module = modules[&name].clone();
+                    let modules = modules.clone();
 
-                let modules_c = modules.clone();
-                let mdir_modules_c = mdir_modules.clone();
-                let task_counter_c = task_counter_c.clone();
-                let found_errors = found_errors_c.clone();
+                    let handle = thread::spawn(move || {
+                        let mut checker = Checker::new(&module, modules);
+                        let mdir_module = checker.types();
 
-                thread::spawn(move || {
-                    let module = module_c.clone();
+                        if !checker.errors().is_empty() || !checker.warnings().is_empty() {
+                            checker.print_interrupts();
+                        }
 
-                    let mut checker = Checker::new(&module, modules_c);
+                        (mdir_module, checker.errors().is_empty())
+                    });
 
-                    let mdir_module = checker.types();
+                    (name, handle)
+                })
+                .collect::<Vec<_>>();
 
-                    if checker.errors().len() != 0 {
-                        checker.print_interrupts();
-                        found_errors.store(true, SeqCst);
+            for (name, handle) in handles {
+                match handle.join() {
+                    Ok((mdir_module, ok)) => {
+                        found_errors |= !ok;
+                        mdir_modules.insert(name, mdir_module);
                     }
+                    // The panic message is already printed by the thread.
+                    Err(_) => {
+                        eprintln!(
+                            "\x1b[31mError:\x1b[0m the checker crashed on `{name}`, this is a bug of gahl."
+                        );
+                        found_errors = true;
+                    }
+                }
+            }
+        }
 
-                    mdir_modules_c
-                        .lock()
-                        .unwrap()
-                        .insert(name.clone(), mdir_module);
+        match found_errors {
+            true => Err(()),
+            false => Ok(mdir_modules),
+        }
+    }
+}
 
-                    task_counter_c.fetch_sub(1, SeqCst);
-                });
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks `main` calling `take`, which has a `*i32` parameter, with `arg`.
+    fn check_call(arg: &str) -> Result<(), String> {
+        let source = format!(
+            "bytes : extern fn() *u8\n\
+             numbers : extern fn() *i32\n\
+             take : extern fn(p: *i32) i32\n\
+             \n\
+             main : fn() i32\n\
+             main = fn() {{\n  take({arg})\n}}\n"
+        );
 
-            mdir_modules
-        });
+        analyze_source("main.gh", &source, SearchPaths::default()).map(|_| ())
+    }
 
-        loop {
-            if task_counter.load(SeqCst) == 0 {
-                break;
-            }
-        }
+    #[test]
+    fn pointer_parameter_boxes_its_pointee() {
+        assert!(check_call("40").is_ok());
+    }
 
-        if found_errors.load(SeqCst) {
-            Err(())
-        } else {
-            let mdir_modules = mdir_modules.join().unwrap();
-            Ok(Arc::try_unwrap(mdir_modules).unwrap().into_inner().unwrap())
-        }
+    #[test]
+    fn pointer_parameter_takes_the_same_pointer() {
+        assert!(check_call("numbers()").is_ok());
+    }
+
+    #[test]
+    fn pointer_parameter_rejects_other_types() {
+        assert!(check_call("bytes()").is_err());
+        assert!(check_call("\"40\"").is_err());
     }
 }
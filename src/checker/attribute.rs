@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::ast::{Attribute, Location, Module, TypeValue};
+
+use super::{mangle, CheckError, Checker};
+
+/// What an attribute is put on. Struct fields aren't one, structs aren't parsed yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    /// A function declared and defined in Gahl.
+    Function,
+    Extern,
+}
+
+/// An attribute the compiler knows.
+struct Known {
+    name: &'static str,
+    targets: &'static [Target],
+    /// Whether the arguments are right, `usage` says what they should be when they aren't.
+    args: fn(&[String]) -> bool,
+    usage: &'static str,
+}
+
+const KNOWN: [Known; 5] = [
+    Known {
+        name: "export",
+        targets: &[Target::Function],
+        args: |args| matches!(args, [name] if !name.is_empty()),
+        usage: "`@export` takes exactly one symbol name, like `@export(\"name\")`.",
+    },
+    Known {
+        name: "test",
+        targets: &[Target::Function],
+        args: <[String]>::is_empty,
+        usage: "`@test` takes no arguments.",
+    },
+    Known {
+        name: "inline",
+        targets: &[Target::Function],
+        args: |args| matches!(args, [] | [_]) && args.iter().all(|a| a == "always" || a == "never"),
+        usage: "`@inline` takes no arguments, `\"always\"` or `\"never\"`.",
+    },
+    Known {
+        name: "cold",
+        targets: &[Target::Function],
+        args: <[String]>::is_empty,
+        usage: "`@cold` takes no arguments.",
+    },
+    Known {
+        name: "deprecated",
+        targets: &[Target::Function, Target::Extern],
+        args: |args| args.len() <= 1,
+        usage: "`@deprecated` takes at most a note, like `@deprecated(\"use `other`\")`.",
+    },
+];
+
+// The harness calls tests without arguments in place of `main`.
+fn is_test_function(module: &Module, name: &str) -> bool {
+    let is_unit_fn = matches!(
+        module.fn_decls.get(name),
+        Some((ty, _, _)) if ty.type_value == TypeValue::Func(vec![], Box::new(TypeValue::Void), false)
+    );
+
+    is_unit_fn && module.fn_defns.contains_key(name) && name != "main"
+}
+
+// What's wrong with `attribute` on the function `name`, `seen` are the attributes before it.
+fn attribute_error(
+    module: &Module,
+    name: &str,
+    attribute: &Attribute,
+    seen: &[&str],
+) -> Option<String> {
+    let known = match KNOWN.iter().find(|known| known.name == attribute.name) {
+        Some(known) => known,
+        None => return Some(format!("Unknown attribute `@{}`.", attribute.name)),
+    };
+
+    let target = match module.externs.contains_key(name) {
+        true => Target::Extern,
+        false => Target::Function,
+    };
+
+    if seen.contains(&known.name) {
+        return Some(format!("`@{}` is given twice.", known.name));
+    }
+    if !known.targets.contains(&target) {
+        return Some(format!(
+            "`@{}` only applies to functions defined in Gahl, `{}` is extern.",
+            known.name, name
+        ));
+    }
+    if !(known.args)(&attribute.args) {
+        return Some(known.usage.to_string());
+    }
+    if known.name == "test" && !is_test_function(module, name) {
+        return Some(format!(
+            "`{}` can't be a test, tests are defined functions of type `fn() void` named anything but `main`.",
+            name
+        ));
+    }
+
+    None
+}
+
+// Another `@export` of the program that claims `symbol` too, with the module it's in. The one on
+// the function `name` of `module` doesn't count.
+fn other_export<'m>(
+    modules: &'m HashMap<String, Arc<Module>>,
+    module: &Module,
+    name: &str,
+    symbol: &str,
+) -> Option<(&'m str, &'m Attribute)> {
+    let mut names = modules.keys().collect::<Vec<&String>>();
+    names.sort();
+
+    names.into_iter().find_map(|module_name| {
+        let other = &modules[module_name];
+
+        let mut functions = other.attributes.keys().collect::<Vec<&String>>();
+        functions.sort();
+        functions
+            .into_iter()
+            .filter(|function| !(other.name == module.name && *function == name))
+            .filter(|function| mangle::export_name(other, function) == Some(symbol))
+            .find_map(|function| {
+                other.attributes[function]
+                    .iter()
+                    .find(|attribute| attribute.name == "export")
+            })
+            .map(|attribute| (other.name.as_str(), attribute))
+    })
+}
+
+impl<'a> Checker<'a> {
+    // Attributes are only known to the compiler, anything else is most likely a typo.
+    pub(super) fn check_attributes(&mut self) {
+        let module = self.module;
+
+        for (name, attributes) in &module.attributes {
+            let mut seen = vec![];
+
+            for attribute in attributes {
+                if let Some(message) = attribute_error(module, name, attribute, &seen) {
+                    self.errors
+                        .push(CheckError::new(attribute.location.clone(), message));
+                }
+                seen.push(attribute.name.as_str());
+            }
+
+            // Both would be linked under the same name.
+            let export = attributes
+                .iter()
+                .find(|attribute| attribute.name == "export");
+            if let (Some(export), Some(symbol)) = (export, mangle::export_name(module, name)) {
+                if let Some((other, attribute)) = other_export(&self.modules, module, name, symbol)
+                {
+                    let error = CheckError::new(
+                        export.location.clone(),
+                        format!(
+                            "`@export(\"{}\")` is given to more than one function.",
+                            symbol
+                        ),
+                    )
+                    .with_note(
+                        other.to_string(),
+                        attribute.location.clone(),
+                        format!("`{}` is exported here too.", symbol),
+                    );
+                    self.errors.push(error);
+                }
+            }
+        }
+    }
+
+    /// Warns at `location` when the function `name` of `module` it calls is `@deprecated`.
+    pub(super) fn warn_deprecated(&mut self, module: &Module, name: &str, location: &Location) {
+        let deprecated = module
+            .attributes
+            .get(name)
+            .into_iter()
+            .flatten()
+            .find(|attribute| attribute.name == "deprecated");
+
+        if let Some(attribute) = deprecated {
+            let message = match attribute.args.first() {
+                Some(note) => format!("`{}` is deprecated: {}", name, note),
+                None => format!("`{}` is deprecated.", name),
+            };
+            self.warnings
+                .push(CheckError::new(location.clone(), message));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use crate::ast::{Expr, Location, TypeValue};
+
+use super::{
+    mdir::{shunting_yard_this_mf, Expression, ExternFunction, Literal},
+    CheckError, Checker,
+};
+
+/// Functions every module can call without importing them, unless it declares its own.
+pub const BUILTINS: [&str; 2] = ["assert", "assert_eq"];
+
+// Suffix of the runtime functions behind the builtins for values of type `ty`.
+fn runtime_suffix(ty: &TypeValue) -> Option<&'static str> {
+    let suffix = match ty {
+        TypeValue::I8 => "i8",
+        TypeValue::I16 => "i16",
+        TypeValue::I32 => "i32",
+        TypeValue::I64 => "i64",
+        TypeValue::Isize => "isize",
+        TypeValue::U8 => "u8",
+        TypeValue::U16 => "u16",
+        TypeValue::U32 => "u32",
+        TypeValue::U64 => "u64",
+        TypeValue::Usize => "usize",
+        TypeValue::String => "str",
+        _ => return None,
+    };
+
+    Some(suffix)
+}
+
+impl<'a> Checker<'a> {
+    /// `assert(n)` fails when the integer `n` is 0, `assert_eq(a, b)` when `a` and `b` differ.
+    /// Both become a call to the runtime, which is told where the call is to report it.
+    pub(super) fn builtin_call_ty(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        location: &Location,
+    ) -> (Vec<Expression>, TypeValue) {
+        let param_names: &[&str] = match name {
+            "assert" => &["value"],
+            _ => &["left", "right"],
+        };
+
+        if args.len() != param_names.len() {
+            let error = CheckError::new(
+                location.clone(),
+                format!(
+                    "`{}` takes {} argument(s) but {} were given.",
+                    name,
+                    param_names.len(),
+                    args.len()
+                ),
+            );
+            self.errors.push(error);
+            return (vec![], TypeValue::Void);
+        }
+
+        let mut mdir_params = vec![];
+        let mut types = vec![];
+        for arg in args {
+            let (out, ty) = self.expr_ty(arg);
+            mdir_params.push((shunting_yard_this_mf(out), ty.clone()));
+            types.push(ty);
+        }
+
+        // An undefined argument has already been reported where it was used.
+        if types.contains(&TypeValue::Undefined) {
+            return (vec![], TypeValue::Void);
+        }
+
+        let message = match runtime_suffix(&types[0]) {
+            _ if types.iter().any(|ty| *ty != types[0]) => Some(format!(
+                "`assert_eq` compares values of the same type, found `{:?}` and `{:?}`.",
+                types[0], types[1]
+            )),
+            Some("str") if name == "assert" => Some(format!(
+                "`assert` takes an integer, found `{:?}`.",
+                types[0]
+            )),
+            Some(_) => None,
+            None if name == "assert" => Some(format!(
+                "`assert` takes an integer of up to 64 bits, found `{:?}`.",
+                types[0]
+            )),
+            None => Some(format!(
+                "`assert_eq` compares integers of up to 64 bits or strings, found `{:?}`.",
+                types[0]
+            )),
+        };
+        if let Some(message) = message {
+            self.errors
+                .push(CheckError::new(args[0].get_location(), message));
+            return (vec![], TypeValue::Void);
+        }
+
+        let symbol = format!("gahl_rt_{}_{}", name, runtime_suffix(&types[0]).unwrap());
+        let position = format!("\"{}:{}\"", self.module.name, location.rows.0 + 1);
+        let position = Expression::Literal(Literal::String(position));
+        mdir_params.push((VecDeque::from([position]), TypeValue::String));
+
+        let mut params = param_names
+            .iter()
+            .zip(&types)
+            .map(|(param, ty)| (param.to_string(), ty.clone()))
+            .collect::<Vec<(String, TypeValue)>>();
+        params.push(("location".to_string(), TypeValue::String));
+
+        self.runtime_functions.insert(
+            symbol.clone(),
+            ExternFunction {
+                name: symbol.clone(),
+                params,
+                return_type: Box::new(TypeValue::Void),
+            },
+        );
+
+        let call = Expression::Literal(Literal::Call(TypeValue::Void, symbol, mdir_params));
+        (vec![call], TypeValue::Void)
+    }
+}
@@ -0,0 +1,44 @@
+use crate::ast::Module;
+
+/// Link name of the function `name` defined in `module`. `main` of the entry module stays as is
+/// for the C runtime to find, `@export("sym")` picks the name explicitly and everything else is
+/// mangled, so equally named functions of different modules don't collide.
+pub fn symbol(module: &Module, name: &str) -> String {
+    if let Some(export) = export_name(module, name) {
+        return export.to_string();
+    }
+
+    if name == "main" && module.entry {
+        return name.to_string();
+    }
+
+    mangle(&module.path, name)
+}
+
+/// The name given to `name` by `@export`, if any.
+pub fn export_name<'m>(module: &'m Module, name: &str) -> Option<&'m str> {
+    module
+        .attributes
+        .get(name)?
+        .iter()
+        .find(|attribute| attribute.name == "export")
+        .and_then(|attribute| attribute.args.first())
+        .map(String::as_str)
+}
+
+/// `_G` followed by every segment of the module path and then the name, each prefixed with its
+/// length: `std.io.println` is `_G3std2io7println`.
+pub fn mangle(path: &[String], name: &str) -> String {
+    let mut symbol = String::from("_G");
+
+    for segment in path.iter().map(String::as_str).chain(std::iter::once(name)) {
+        // File names can contain characters that aren't valid in an unquoted LLVM identifier.
+        let segment = segment
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        symbol += &format!("{}{segment}", segment.len());
+    }
+
+    symbol
+}
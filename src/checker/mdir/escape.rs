@@ -0,0 +1,77 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::ast::TypeValue;
+
+use super::{Expression, Function, Literal, Statement};
+
+/// Finds the locals of `function` whose address can outlive its frame.
+///
+/// The only way to take the address of a local is to pass it where a pointer is expected, at
+/// which point the callee may hold on to it. Every other local can live on the stack.
+pub fn escaping_locals(function: &Function) -> HashSet<String> {
+    let mut escapes = HashSet::new();
+
+    for stmt in &function.block {
+        match stmt {
+            Statement::Expr(expr, _) => expr_escapes(expr, &mut escapes),
+            Statement::Var(var) => expr_escapes(&var.rhs, &mut escapes),
+        }
+    }
+
+    escapes
+}
+
+fn expr_escapes(expr: &VecDeque<Expression>, escapes: &mut HashSet<String>) {
+    for e in expr {
+        if let Expression::Literal(Literal::Call(_, _, args)) = e {
+            for (arg, param_type) in args {
+                if let Some(name) = address_taken(arg, param_type) {
+                    escapes.insert(name.clone());
+                }
+
+                expr_escapes(arg, escapes);
+            }
+        }
+    }
+}
+
+/// The local whose address is passed, if `arg` is a bare local given to a pointer parameter.
+pub fn address_taken<'e>(
+    arg: &'e VecDeque<Expression>,
+    param_type: &TypeValue,
+) -> Option<&'e String> {
+    if !matches!(param_type, TypeValue::Ptr(_)) || arg.len() != 1 {
+        return None;
+    }
+
+    match &arg[0] {
+        Expression::Literal(Literal::Identifier(ty, name, false))
+            if !matches!(ty, TypeValue::Ptr(_)) =>
+        {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// The type of the value `arg` evaluates to, if it has to be put in a box of its own to be given
+/// to a pointer parameter, as the callee may keep the pointer. Bare locals aren't boxed here,
+/// their address is passed, see [`address_taken`].
+pub fn boxed_argument(arg: &VecDeque<Expression>, param_type: &TypeValue) -> Option<TypeValue> {
+    if !matches!(param_type, TypeValue::Ptr(_)) || address_taken(arg, param_type).is_some() {
+        return None;
+    }
+
+    let arg_type = arg
+        .iter()
+        .find_map(|e| match e {
+            Expression::Literal(literal) => Some(literal._type().clone()),
+            _ => None,
+        })
+        .unwrap_or(TypeValue::Void);
+
+    match arg_type {
+        TypeValue::Ptr(_) => None,
+        arg_type => Some(arg_type),
+    }
+}
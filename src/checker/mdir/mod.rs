@@ -1,10 +1,15 @@
 use core::fmt;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use crate::ast::{DocComment, TypeValue};
+use crate::ast::{Attribute, DocComment, Location, TypeValue, Visibility};
+
+pub mod escape;
+pub mod pretty;
 
 #[derive(Debug)]
 pub struct MiddleIR {
+    /// Names of the modules this one imports from, sorted.
+    imports: Vec<String>,
     imported_functions: HashMap<String, (Vec<(String, TypeValue)>, TypeValue)>,
     functions: HashMap<String, Function>,
     externs: Vec<ExternFunction>,
@@ -13,6 +18,7 @@ pub struct MiddleIR {
 impl MiddleIR {
     pub fn new() -> Self {
         Self {
+            imports: vec![],
             imported_functions: HashMap::new(),
             functions: HashMap::new(),
             externs: vec![],
@@ -23,6 +29,10 @@ impl MiddleIR {
         self.externs = externs;
     }
 
+    pub fn set_imports(&mut self, imports: Vec<String>) {
+        self.imports = imports;
+    }
+
     pub fn set_imported_functions(
         &mut self,
         imported_functions: HashMap<String, (Vec<(String, TypeValue)>, TypeValue)>,
@@ -38,10 +48,18 @@ impl MiddleIR {
         &self.externs
     }
 
+    pub fn imports(&self) -> &Vec<String> {
+        &self.imports
+    }
+
     pub fn imported_functions(&self) -> &HashMap<String, (Vec<(String, TypeValue)>, TypeValue)> {
         &self.imported_functions
     }
 
+    pub fn into_functions(self) -> HashMap<String, Function> {
+        self.functions
+    }
+
     pub fn insert_function(&mut self, function: Function) {
         self.functions.insert(function.name.clone(), function);
     }
@@ -50,22 +68,36 @@ impl MiddleIR {
 #[derive(Debug)]
 pub struct Function {
     pub name: String,
+    /// Name the function is linked by, see [`crate::checker::mangle::symbol`].
+    pub symbol: String,
     pub params: Vec<(String, TypeValue)>,
     pub return_type: TypeValue,
     pub vars: Vec<(String, TypeValue)>,
     pub block: Vec<Statement>,
     pub doc_comments: Vec<DocComment>,
+    /// Attributes of the declaration and definition, checked by the checker.
+    pub attributes: Vec<Attribute>,
+    pub visibility: Visibility,
+    /// Locals that have to be heap allocated, see [`escape::escaping_locals`].
+    pub escapes: HashSet<String>,
+    /// The definition in the source, for debug info.
+    pub location: Location,
 }
 
 impl Function {
     pub fn default() -> Self {
         Self {
             name: String::new(),
+            symbol: String::new(),
             params: vec![],
             return_type: TypeValue::Void,
             vars: vec![],
             block: vec![],
             doc_comments: vec![],
+            attributes: vec![],
+            visibility: Visibility::Private,
+            escapes: HashSet::new(),
+            location: Location::default(),
         }
     }
 }
@@ -79,15 +111,25 @@ pub struct ExternFunction {
 
 #[derive(Debug)]
 pub enum Statement {
-    Expr(VecDeque<Expression>),
+    Expr(VecDeque<Expression>, Location),
     Var(Var),
 }
 
+impl Statement {
+    pub fn location(&self) -> &Location {
+        match self {
+            Statement::Expr(_, location) => location,
+            Statement::Var(var) => &var.location,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Var {
     pub lhs: String,
     pub rhs: VecDeque<Expression>,
     pub ty: TypeValue,
+    pub location: Location,
 }
 
 pub struct Decl {
@@ -101,8 +143,13 @@ pub struct Assign {
 }
 
 impl Var {
-    pub fn new(lhs: String, rhs: VecDeque<Expression>, ty: TypeValue) -> Self {
-        Self { lhs, rhs, ty }
+    pub fn new(lhs: String, rhs: VecDeque<Expression>, ty: TypeValue, location: Location) -> Self {
+        Self {
+            lhs,
+            rhs,
+            ty,
+            location,
+        }
     }
 }
 
@@ -0,0 +1,109 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::ast::{TypeValue, Visibility};
+
+use super::{Expression, Function, Literal, MiddleIR, Statement};
+
+/// Textual MIR of a module, for `--emit=mir`. Expressions stay in the postfix order the checker
+/// produces them in, calls print their arguments the same way.
+pub fn mdir_to_string(name: &str, mdir: &MiddleIR) -> String {
+    let mut out = format!("module {name}\n");
+
+    let mut imported = mdir.imported_functions().iter().collect::<Vec<_>>();
+    imported.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, (params, ret)) in imported {
+        let _ = writeln!(out, "  import {name}({}) {ret}", params_to_string(params));
+    }
+
+    let mut externs = mdir.externs().iter().collect::<Vec<_>>();
+    externs.sort_by_key(|f| f.name.as_str());
+
+    for function in externs {
+        let params = params_to_string(&function.params);
+        let _ = writeln!(
+            out,
+            "  extern {}({params}) {}",
+            function.name, function.return_type
+        );
+    }
+
+    let mut functions = mdir.functions().values().collect::<Vec<_>>();
+    functions.sort_by_key(|f| f.name.as_str());
+
+    for function in functions {
+        function_to_string(&mut out, function);
+    }
+
+    out
+}
+
+fn params_to_string(params: &[(String, TypeValue)]) -> String {
+    params
+        .iter()
+        .map(|(name, ty)| format!("{name}: {ty}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn function_to_string(out: &mut String, function: &Function) {
+    let visibility = match function.visibility {
+        Visibility::Public => "pub ",
+        Visibility::Private => "",
+    };
+    let params = params_to_string(&function.params);
+    let _ = writeln!(
+        out,
+        "  {visibility}fn {}({params}) {} {{",
+        function.name, function.return_type
+    );
+
+    for (name, ty) in &function.vars {
+        let heap = if function.escapes.contains(name) {
+            " (heap)"
+        } else {
+            ""
+        };
+        let _ = writeln!(out, "    local {name}: {ty}{heap}");
+    }
+
+    for stmt in &function.block {
+        match stmt {
+            Statement::Expr(expr, _) => {
+                let _ = writeln!(out, "    {}", expr_to_string(expr));
+            }
+            Statement::Var(var) => {
+                let _ = writeln!(out, "    {} = {}", var.lhs, expr_to_string(&var.rhs));
+            }
+        }
+    }
+
+    out.push_str("  }\n");
+}
+
+fn expr_to_string(expr: &VecDeque<Expression>) -> String {
+    expr.iter()
+        .map(|e| match e {
+            Expression::Literal(literal) => literal_to_string(literal),
+            op => op.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn literal_to_string(literal: &Literal) -> String {
+    match literal {
+        Literal::Int(ty, value) => format!("{value}:{ty}"),
+        Literal::Identifier(_, name, true) => format!("%{name}"),
+        Literal::Identifier(_, name, false) => name.clone(),
+        Literal::String(value) => value.clone(),
+        Literal::Call(ret, name, args) => {
+            let args = args
+                .iter()
+                .map(|(arg, _)| expr_to_string(arg))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{name}({args}):{ret}")
+        }
+    }
+}
@@ -1,20 +1,26 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     fs,
     sync::Arc,
     vec,
 };
 
 pub mod analyzer;
+pub mod attribute;
+pub mod builtin;
+pub mod mangle;
 pub mod mdir;
 
 use mdir::{
-    shunting_yard_this_mf, Expression, ExternFunction, Function, Literal, MiddleIR, Statement,
+    escape, shunting_yard_this_mf, Expression, ExternFunction, Function, Literal, MiddleIR, Statement,
     Var as MdIrVar,
 };
 
 use crate::{
-    ast::{Expr, FuncNode, ImportKey, Location, Module, Name, Stmt, Type, TypeValue, Var},
+    ast::{
+        Expr, FuncNode, ImportKey, ImportTarget, Location, Module, Name, Stmt, Type, TypeValue, Var,
+        Visibility,
+    },
     parser::error::ParseError,
 };
 
@@ -22,23 +28,98 @@ use crate::{
 pub struct CheckError {
     location: Location,
     message: String,
+    notes: Vec<CheckNote>,
+}
+
+/// Extra context attached to an error, possibly pointing into another module.
+#[derive(Debug)]
+pub struct CheckNote {
+    file: String,
+    location: Location,
+    message: String,
 }
 
 impl CheckError {
     fn new(location: Location, message: String) -> Self {
-        Self { location, message }
+        Self {
+            location,
+            message,
+            notes: vec![],
+        }
     }
 
     fn from_parse_error(error: &ParseError) -> Self {
-        Self {
-            location: error.location.clone(),
-            message: error.message.clone(),
-        }
+        Self::new(error.location.clone(), error.message.clone())
     }
+
+    fn with_note(mut self, file: String, location: Location, message: String) -> Self {
+        self.notes.push(CheckNote {
+            file,
+            location,
+            message,
+        });
+        self
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Renders the line `location` starts on with the span underlined.
+fn render_location(
+    file_name: &str,
+    source: &str,
+    location: &Location,
+    title: &str,
+    color: &str,
+    message: &str,
+) -> Option<String> {
+    let row = location.rows.0;
+    let line = source.lines().nth(row)?;
+
+    let line_start: usize = source
+        .lines()
+        .take(row)
+        .map(|l| l.chars().count() + 1)
+        .sum();
+
+    let offset = location.span.start.saturating_sub(line_start);
+    let repeat = (location.span.end - location.span.start).max(1);
+    let message_offset = (row + 1).to_string().len();
+
+    Some(format!(
+        "{color}{title} in {}:{}:{}\x1b[0m\n\x1b[34m{} |\x1b[0m {}\n\x1b[34m-{}| {}{color}{}\n\x1b[34m{}\x1b[0m\n",
+        file_name,
+        row + 1,
+        offset,
+        row + 1,
+        line,
+        " ".repeat(message_offset),
+        " ".repeat(offset),
+        "~".repeat(repeat),
+        message,
+    ))
+}
+
+/// A function another module defines, as [`Checker::get_imported_function`] resolves it.
+struct ImportedFunction {
+    /// Name of the function inside its own module.
+    name: String,
+    /// Name it's linked by, see [`mangle::symbol`].
+    link_name: String,
+    params: Vec<(String, TypeValue)>,
+    return_type: TypeValue,
 }
 
 pub struct Checker<'a> {
     imported_functions: HashMap<String, (Vec<(String, TypeValue)>, TypeValue)>,
+    /// Functions of the C runtime the builtins are lowered to, declared like externs.
+    runtime_functions: BTreeMap<String, ExternFunction>,
     errors: Vec<CheckError>,
     warnings: Vec<CheckError>,
     modules: Arc<HashMap<String, Arc<Module>>>,
@@ -50,6 +131,7 @@ impl<'a> Checker<'a> {
     pub fn new(module: &'a Module, modules: Arc<HashMap<String, Arc<Module>>>) -> Self {
         Self {
             imported_functions: HashMap::new(),
+            runtime_functions: BTreeMap::new(),
             errors: vec![],
             warnings: vec![],
             modules,
@@ -62,6 +144,10 @@ impl<'a> Checker<'a> {
         &self.errors
     }
 
+    pub fn warnings(&self) -> &Vec<CheckError> {
+        &self.warnings
+    }
+
     pub fn push_stack(&mut self) {
         let symbols: HashMap<&String, (TypeValue, bool)> = HashMap::new();
         self.symbol_stack.push(symbols);
@@ -79,110 +165,271 @@ impl<'a> Checker<'a> {
         self.symbol_stack.last_mut().unwrap().insert(key, value);
     }
 
+    /// Makes the local `name` known in the current scope, for code checked outside of a function
+    /// body like REPL input.
+    pub fn define(&mut self, name: &'a String, type_value: TypeValue) {
+        self.insert_symbol(name, (type_value, false));
+    }
+
+    /// Reports errors the parser found in code that isn't part of the module.
+    pub fn push_parse_errors(&mut self, errors: &[ParseError]) {
+        for error in errors {
+            self.errors.push(CheckError::from_parse_error(error));
+        }
+    }
+
     pub fn print_interrupts(&self) {
         let file = fs::read_to_string(&self.module.name).unwrap();
+        self.print_interrupts_in(&file);
+    }
 
-        let mut summation = 0usize;
-        for (i, l) in file.lines().enumerate() {
-            let l_len = l.len();
-
-            for error in &self.errors {
-                if error.location.rows.0 == i {
-                    let start = error.location.span.start;
-                    let end = error.location.span.end;
-
-                    let offset = start - summation;
-                    let repeat = end - start;
-                    let message_offset = (i + 1).to_string().len();
-
-                    // TODO: Unfuck this
-                    let errstr = format!(
-                        "\x1b[31mError in {}:{}:{}\x1b[0m\n\x1b[34m{} |\x1b[0m {}\n\x1b[34m-{}| {}\x1b[31m{}\n\x1b[34m{}\x1b[0m\n",
-                        self.module.name,
-                        i+1,
-                        offset,
-                        i + 1,
-                        l,
-                        " ".repeat(message_offset),
-                        " ".repeat(offset),
-                        "~".repeat(repeat),
-                        error.message,
-                    );
+    /// Prints the errors and warnings with their locations in `file`, the source they were found
+    /// in.
+    pub fn print_interrupts_in(&self, file: &str) {
+        print!("{}", self.render_interrupts(file));
+    }
 
-                    println!("{}", errstr);
-                }
+    /// The errors and warnings as [`Checker::print_interrupts_in`] prints them.
+    pub fn render_interrupts(&self, file: &str) -> String {
+        let mut out = String::new();
+        let mut interrupts = self
+            .errors
+            .iter()
+            .map(|e| (e, "Error", "\x1b[31m"))
+            .chain(self.warnings.iter().map(|w| (w, "Warning", "\x1b[33m")))
+            .collect::<Vec<(&CheckError, &str, &str)>>();
+        interrupts.sort_by_key(|(e, _, _)| (e.location.rows.0, e.location.span.start));
+
+        for (error, title, color) in interrupts {
+            if let Some(errstr) = render_location(
+                &self.module.name,
+                file,
+                &error.location,
+                title,
+                color,
+                &error.message,
+            ) {
+                out += &format!("{}\n", errstr);
             }
 
-            summation += l_len + 1;
+            for note in &error.notes {
+                let source = match fs::read_to_string(&note.file) {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+
+                if let Some(notestr) = render_location(
+                    &note.file,
+                    &source,
+                    &note.location,
+                    "Note",
+                    "\x1b[36m",
+                    &note.message,
+                ) {
+                    out += &format!("{}\n", notestr);
+                }
+            }
         }
+
+        out
     }
 
-    // TODO: Break this up into multiple functions, cus holy moly!
-    // Actually, just do the funny match after match / ? after ? thingy.
+    /// Resolves a symbol imported from another module, reporting an error at `location` when
+    /// the import is missing and at the import itself when the target is unknown or private.
     fn get_imported_function(
         &mut self,
         symbol_name: String,
-    ) -> (Vec<(String, TypeValue)>, TypeValue) {
-        if let Some(imports) = &self.module.imports {
-            match imports.get(&ImportKey::Symbol(symbol_name.clone())) {
-                Some(&Some(ref path)) => match self.modules.clone().get(path) {
-                    Some(module) => {
-                        let module_c = module.clone();
-                        let (ty, _) = module_c.fn_decls.get(&symbol_name).unwrap();
-                        let (func_node, _) = module_c.fn_defns.get(&symbol_name).unwrap();
-
-                        if let TypeValue::Func(ref param_types, ref return_type, false) =
-                            ty.type_value
-                        {
-                            let param_names = func_node.args.clone();
-                            let mut final_params: Vec<(String, TypeValue)> = vec![];
-
-                            for i in 0..param_types.len() {
-                                let name = param_names[i].clone();
-                                let _type = param_types[i].clone();
-
-                                final_params.push((name, _type));
-                            }
-
-                            return (final_params, *return_type.clone());
-                        } else {
-                            panic!()
-                        }
-                    }
-                    None => {
-                        todo!()
-                    }
-                },
-                Some(None) => {
-                    todo!()
-                }
-                None => {
-                    // TODO: Report error.
-                    todo!()
-                }
-            };
+        location: &Location,
+    ) -> Option<ImportedFunction> {
+        let imported = self
+            .module
+            .imports
+            .as_ref()
+            .and_then(|imports| imports.get(&ImportKey::Symbol(symbol_name.clone())))
+            .map(|target| {
+                let symbol = target.symbol.clone().unwrap_or(symbol_name.clone());
+                (target.path.clone(), symbol, target.location.clone())
+            })
+            .map(Some)
+            .or_else(|| {
+                let target = self.find_wildcard_import(&symbol_name, location)?;
+                Some(target.map(|target| {
+                    (target.path.clone(), symbol_name.clone(), target.location.clone())
+                }))
+            });
+
+        let (path, symbol_name, import_location) = match imported {
+            Some(Some(imported)) => imported,
+            // Already reported.
+            Some(None) => return None,
+            None => {
+                let error = CheckError::new(
+                    location.clone(),
+                    format!("Function `{}` is neither defined nor imported.", symbol_name),
+                );
+                self.errors.push(error);
+                return None;
+            }
+        };
+
+        let module = match self.modules.get(&path) {
+            Some(module) => module.clone(),
+            None => {
+                let error = CheckError::new(
+                    import_location,
+                    format!("Module `{}` could not be found.", path),
+                );
+                self.errors.push(error);
+                return None;
+            }
+        };
+
+        let (ty, decl_location, visibility) = match module.fn_decls.get(&symbol_name) {
+            Some(decl) => decl,
+            None => {
+                let error = CheckError::new(
+                    import_location,
+                    format!("Module `{}` has no function named `{}`.", path, symbol_name),
+                );
+                self.errors.push(error);
+                return None;
+            }
+        };
+
+        if *visibility == Visibility::Private {
+            let error = CheckError::new(
+                import_location,
+                format!("`{}` is private to module `{}`.", symbol_name, path),
+            )
+            .with_note(
+                path.clone(),
+                decl_location.clone(),
+                format!("`{}` is declared here, mark it `pub` to export it.", symbol_name),
+            );
+            self.errors.push(error);
+            return None;
+        }
+
+        let (func_node, _) = match module.fn_defns.get(&symbol_name) {
+            Some(defn) => defn,
+            None => {
+                let error = CheckError::new(
+                    import_location,
+                    format!("`{}` is declared in `{}` but never defined.", symbol_name, path),
+                );
+                self.errors.push(error);
+                return None;
+            }
+        };
+
+        self.warn_deprecated(&module, &symbol_name, location);
+
+        if let TypeValue::Func(ref param_types, ref return_type, false) = ty.type_value {
+            let final_params = func_node
+                .args
+                .iter()
+                .cloned()
+                .zip(param_types.iter().cloned())
+                .collect::<Vec<(String, TypeValue)>>();
+
+            let link_name = mangle::symbol(&module, &symbol_name);
+
+            Some(ImportedFunction {
+                name: symbol_name,
+                link_name,
+                params: final_params,
+                return_type: *return_type.clone(),
+            })
         } else {
-            todo!()
+            let error = CheckError::new(
+                location.clone(),
+                format!("`{}` is not a function.", symbol_name),
+            );
+            self.errors.push(error);
+            None
+        }
+    }
+
+    /// The first `a.b.*` import whose module exports `symbol_name`.
+    // The wildcard import `symbol_name` comes from. It's `Some(None)` when more than one exports
+    // it, after reporting that at `location`.
+    fn find_wildcard_import(
+        &mut self,
+        symbol_name: &String,
+        location: &Location,
+    ) -> Option<Option<&'a ImportTarget>> {
+        let module: &'a Module = self.module;
+        let imports = module.imports.as_ref()?;
+
+        let mut wildcards = imports
+            .iter()
+            .filter_map(|(key, target)| match key {
+                ImportKey::Wildcard(_) => Some(target),
+                _ => None,
+            })
+            .filter(|target| {
+                self.modules
+                    .get(&target.path)
+                    .and_then(|m| m.fn_decls.get(symbol_name))
+                    .is_some_and(|(_, _, visibility)| *visibility == Visibility::Public)
+            })
+            .collect::<Vec<&ImportTarget>>();
+        wildcards.sort_by(|a, b| a.path.cmp(&b.path));
+
+        match wildcards[..] {
+            [] => None,
+            [target] => Some(Some(target)),
+            [first, second, ..] => {
+                let error = CheckError::new(
+                    location.clone(),
+                    format!(
+                        "`{}` is ambiguous, both `{}` and `{}` export it. Import it by name from one of them.",
+                        symbol_name, first.path, second.path
+                    ),
+                )
+                .with_note(
+                    module.name.clone(),
+                    first.location.clone(),
+                    format!("`{}` is imported here.", first.path),
+                )
+                .with_note(
+                    module.name.clone(),
+                    second.location.clone(),
+                    format!("`{}` is imported here.", second.path),
+                );
+                self.errors.push(error);
+                Some(None)
+            }
         }
     }
 
     pub fn types(&mut self) -> MiddleIR {
         let mut middle_ir = MiddleIR::new();
 
+        self.module.errors.iter().for_each(|error| {
+            let check_error = CheckError::from_parse_error(error);
+            self.errors.push(check_error);
+        });
+
+        self.check_attributes();
+
         for (name, (func_node, location)) in &self.module.fn_defns {
             match self.module.fn_decls.get(name) {
-                Some((_type, _)) => {
-                    let function = self.fn_ty(name, func_node, _type);
+                Some((_type, _, visibility)) => {
+                    let mut function = self.fn_ty(name, func_node, _type);
+                    function.symbol = mangle::symbol(self.module, name);
+                    function.visibility = *visibility;
+                    function.location = location.clone();
                     middle_ir.insert_function(function);
                 }
                 None => {
-                    let error = CheckError {
-                        location: location.clone(),
-                        message: format!(
+                    let error = CheckError::new(
+                        location.clone(),
+                        format!(
                             "No function declaration found for definition: `{}`",
                             name
                         ),
-                    };
+                    );
 
                     self.errors.push(error);
                 }
@@ -193,11 +440,12 @@ impl<'a> Checker<'a> {
             .module
             .externs
             .iter()
-            .map(|(name, (params, return_type))| ExternFunction {
+            .map(|(name, decl)| ExternFunction {
                 name: name.to_owned(),
-                params: params.clone(),
-                return_type: Box::new(return_type.clone()),
+                params: decl.params.clone(),
+                return_type: Box::new(decl.return_type.clone()),
             })
+            .chain(std::mem::take(&mut self.runtime_functions).into_values())
             .collect();
 
         middle_ir.set_externs(extern_functions);
@@ -207,6 +455,17 @@ impl<'a> Checker<'a> {
 
         middle_ir.set_imported_functions(imported_functions);
 
+        let mut imports = self
+            .module
+            .imports
+            .iter()
+            .flat_map(|imports| imports.values().map(|target| target.path.clone()))
+            .collect::<Vec<String>>();
+        imports.sort();
+        imports.dedup();
+
+        middle_ir.set_imports(imports);
+
         middle_ir
     }
 
@@ -252,7 +511,9 @@ impl<'a> Checker<'a> {
             function.block.push(stmt);
         });
 
-        function.doc_comments = func_node.doc_comments.clone();
+        function.doc_comments = self.module.docs.get(name).cloned().unwrap_or_default();
+        function.attributes = self.module.attributes.get(name).cloned().unwrap_or_default();
+        function.escapes = escape::escaping_locals(&function);
         self.pop_stack();
 
         function
@@ -260,11 +521,12 @@ impl<'a> Checker<'a> {
 
     pub fn stmt_ty(&mut self, stmt: &'a Stmt) -> Statement {
         match stmt {
-            Stmt::Expr(expr, _location) => {
+            Stmt::Expr(expr, _errors) => {
+                let location = expr.get_location();
                 let (expr, _) = self.expr_ty(expr);
                 let out = shunting_yard_this_mf(expr);
 
-                Statement::Expr(out)
+                Statement::Expr(out, location)
             }
             Stmt::Var(var) => Statement::Var(self.var_ty(var)),
             s => {
@@ -295,13 +557,13 @@ impl<'a> Checker<'a> {
                 }
 
                 if lhs_type != rhs_type {
-                    let error = CheckError {
-                        location: location.clone(),
-                        message: format!(
+                    let error = CheckError::new(
+                        location.clone(),
+                        format!(
                             "Cannot `{:?} + {:?}` as these types do not match.",
                             lhs_type, rhs_type
                         ),
-                    };
+                    );
 
                     self.errors.push(error);
 
@@ -318,14 +580,22 @@ impl<'a> Checker<'a> {
                 output.push(Expression::Min);
                 output.append(&mut rhs_expr);
 
+                if lhs_type == TypeValue::Undefined {
+                    return (vec![], rhs_type);
+                }
+
+                if rhs_type == TypeValue::Undefined {
+                    return (vec![], lhs_type);
+                }
+
                 if lhs_type != rhs_type {
-                    let error = CheckError {
-                        location: location.clone(),
-                        message: format!(
+                    let error = CheckError::new(
+                        location.clone(),
+                        format!(
                             "Cannot `{:?} - {:?}` as these types do not match.",
                             lhs_type, rhs_type
                         ),
-                    };
+                    );
 
                     self.errors.push(error);
 
@@ -342,14 +612,22 @@ impl<'a> Checker<'a> {
                 output.push(Expression::Mul);
                 output.append(&mut rhs_expr);
 
+                if lhs_type == TypeValue::Undefined {
+                    return (vec![], rhs_type);
+                }
+
+                if rhs_type == TypeValue::Undefined {
+                    return (vec![], lhs_type);
+                }
+
                 if lhs_type != rhs_type {
-                    let error = CheckError {
-                        location: location.clone(),
-                        message: format!(
+                    let error = CheckError::new(
+                        location.clone(),
+                        format!(
                             "Cannot `{:?} * {:?}` as these types do not match.",
                             lhs_type, rhs_type
                         ),
-                    };
+                    );
 
                     self.errors.push(error);
 
@@ -366,14 +644,22 @@ impl<'a> Checker<'a> {
                 output.push(Expression::Div);
                 output.append(&mut rhs_expr);
 
+                if lhs_type == TypeValue::Undefined {
+                    return (vec![], rhs_type);
+                }
+
+                if rhs_type == TypeValue::Undefined {
+                    return (vec![], lhs_type);
+                }
+
                 if lhs_type != rhs_type {
-                    let error = CheckError {
-                        location: location.clone(),
-                        message: format!(
+                    let error = CheckError::new(
+                        location.clone(),
+                        format!(
                             "Cannot `{:?} / {:?}` as these types do not match.",
                             lhs_type, rhs_type
                         ),
-                    };
+                    );
 
                     self.errors.push(error);
 
@@ -403,10 +689,10 @@ impl<'a> Checker<'a> {
                         (vec![expr], ty.clone())
                     }
                     None => {
-                        let error = CheckError {
-                            location: location.clone(),
-                            message: format!("Identifier `{}` is undefined at this point.", name),
-                        };
+                        let error = CheckError::new(
+                            location.clone(),
+                            format!("Identifier `{}` is undefined at this point.", name),
+                        );
 
                         self.errors.push(error);
 
@@ -426,28 +712,48 @@ impl<'a> Checker<'a> {
         &mut self,
         name: &Name,
         args: &Vec<Expr>,
-        _location: &Location,
+        location: &Location,
     ) -> (Vec<Expression>, TypeValue) {
         // TODO: Don't do this weird "Name" shit...
 
         // TODO: More than two would be an error!
         if name.name.len() == 2 {}
-        let tmp_name = name.name[0].clone();
+        let mut tmp_name = name.name[0].clone();
+        // Externs are linked by their own name.
+        let mut link_name = tmp_name.clone();
+
+        let module = self.module;
+        if module.fn_decls.contains_key(&tmp_name) || module.externs.contains_key(&tmp_name) {
+            self.warn_deprecated(module, &tmp_name, location);
+        }
 
         let (params, return_type) = match self.module.fn_decls.get(&tmp_name) {
             None => match self.module.externs.get(&tmp_name) {
+                None if builtin::BUILTINS.contains(&tmp_name.as_str()) => {
+                    return self.builtin_call_ty(&tmp_name, args, location);
+                }
                 // Couldn't find function in externs, trying imports.
                 None => {
-                    let func = self.get_imported_function(tmp_name.clone());
+                    let imported = match self.get_imported_function(tmp_name.clone(), location) {
+                        Some(func) => func,
+                        None => return (vec![], TypeValue::Undefined),
+                    };
+
+                    // Aliased imports are called by the name they have in their own module.
+                    tmp_name = imported.name;
+                    link_name = imported.link_name;
+                    let func = (imported.params, imported.return_type);
 
                     self.imported_functions
-                        .insert(tmp_name.clone(), func.clone());
+                        .insert(link_name.clone(), func.clone());
 
                     &func.clone()
                 }
-                Some(f) => f,
+                Some(decl) => &(decl.params.clone(), decl.return_type.clone()),
             },
-            Some((t, _)) => {
+            Some((t, _, _)) => {
+                link_name = mangle::symbol(self.module, &tmp_name);
+
                 &if let TypeValue::Func(ref param_types, ref return_type, false) = t.type_value {
                     let (func_node, _) = self.module.fn_defns.get(&tmp_name).unwrap();
                     let param_names = func_node.args.clone();
@@ -473,9 +779,13 @@ impl<'a> Checker<'a> {
             let (param_name, param_type) = &params[i];
             let (arg_expr, arg_type) = self.expr_ty(arg);
 
-            mdir_params.push((arg_expr.into(), param_type.clone()));
+            mdir_params.push((shunting_yard_this_mf(arg_expr), param_type.clone()));
 
             let doesnt_match = match (param_type, &arg_type) {
+                // A pointer is passed on as it is, see `escape::boxed_argument`.
+                (TypeValue::Ptr(_), TypeValue::Ptr(_)) => {
+                    param_type != &arg_type
+                },
                 (TypeValue::Ptr(a), b) => {
                     &**a != b
                 },
@@ -484,7 +794,8 @@ impl<'a> Checker<'a> {
                 },
             };
 
-            if doesnt_match {
+            // An undefined argument has already been reported where it was used.
+            if doesnt_match && arg_type != TypeValue::Undefined {
                 let error = CheckError::new(
                         arg.get_location(),
                         format!(
@@ -497,7 +808,7 @@ impl<'a> Checker<'a> {
             }
         }
 
-        let literal = Literal::Call(return_type.clone(), tmp_name.clone(), mdir_params);
+        let literal = Literal::Call(return_type.clone(), link_name, mdir_params);
         let call = Expression::Literal(literal);
         (vec![call], return_type.clone())
     }
@@ -524,7 +835,7 @@ impl<'a> Checker<'a> {
                 self.insert_symbol(var.lhs.name.last().unwrap(), (rhs_type.clone(), false));
 
                 let name = var.lhs.name.last().unwrap().clone();
-                MdIrVar::new(name, out, rhs_type)
+                MdIrVar::new(name, out, rhs_type, var.location.clone())
             }
             _ => {
                 let key = var.lhs.name.last().unwrap();
@@ -532,13 +843,13 @@ impl<'a> Checker<'a> {
 
                 match self.get_symbol(key) {
                     None => {
-                        let error = CheckError {
-                            location: var.lhs.location.clone(),
-                            message: format!(
+                        let error = CheckError::new(
+                            var.lhs.location.clone(),
+                            format!(
                                 "Attempted to assign to `{}`, but it was never decleared.",
                                 key
                             ),
-                        };
+                        );
 
                         self.errors.push(error);
                     }
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -10,11 +10,126 @@ pub struct Args {
 #[derive(Subcommand, Debug, Clone)]
 pub enum SubCommand {
     #[clap(about = "Builds the project.")]
-    Build,
+    Build {
+        /// Target triple to compile for, overrides `target` under `[build]`.
+        #[clap(long)]
+        target: Option<String>,
+        /// What to write to `build/`, comma separated.
+        #[clap(long, value_enum, value_delimiter = ',', default_value = "exe")]
+        emit: Vec<Emit>,
+        #[clap(flatten)]
+        profile: ProfileArgs,
+        #[clap(flatten)]
+        codegen: CodegenArgs,
+    },
     #[clap(about = "Runs the project.")]
-    Run,
-    #[clap(about = "Creates a new project.")]
-    New {
-        project_name: String,
+    Run {
+        #[clap(flatten)]
+        profile: ProfileArgs,
+        #[clap(flatten)]
+        codegen: CodegenArgs,
+        /// Run the MIR in the interpreter instead of building a binary, no clang needed.
+        #[clap(long)]
+        interp: bool,
+    },
+    #[clap(about = "Checks the project for errors without building it.")]
+    Check,
+    #[clap(about = "Writes the documentation of the project to `build/doc/`.")]
+    Doc,
+    #[clap(about = "Runs the tests of the project in the interpreter.")]
+    Test {
+        /// Only run the tests whose name contains this.
+        filter: Option<String>,
+        /// Only run the `gahl` code blocks of doc comments.
+        #[clap(long)]
+        doc: bool,
+        /// Run the single test of this name in this process, for `gahl test` itself.
+        #[clap(long, hide = true)]
+        harness: Option<String>,
+        #[clap(flatten)]
+        profile: ProfileArgs,
+    },
+    #[clap(about = "Runs a file with the interpreter, without compiling it.")]
+    Eval {
+        file: String,
+        #[clap(flatten)]
+        profile: ProfileArgs,
+        /// Passed on to the program, after `--`.
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    #[clap(about = "Starts an interactive session, evaluated by the interpreter.")]
+    Repl {
+        #[clap(flatten)]
+        profile: ProfileArgs,
     },
-}
\ No newline at end of file
+    #[clap(about = "Formats the source files of the project.")]
+    Fmt {
+        /// Files to format instead of the whole project, `-` formats stdin to stdout.
+        files: Vec<String>,
+        /// Only check that the files are formatted, exits with 1 when they aren't.
+        #[clap(long)]
+        check: bool,
+    },
+    #[clap(about = "Starts a language server on stdin and stdout.")]
+    Lsp,
+    #[clap(about = "Creates a new project.")]
+    New { project_name: String },
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct ProfileArgs {
+    /// Build with `[profile.release]` instead of `[profile.dev]`.
+    #[clap(long)]
+    pub release: bool,
+    /// Optimization level, overrides the one of the profile.
+    #[clap(short = 'O', value_parser = clap::value_parser!(u8).range(0..=3))]
+    pub opt_level: Option<u8>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct CodegenArgs {
+    /// Number of clang processes to run at once, defaults to the number of CPUs.
+    #[clap(short = 'j', long)]
+    pub jobs: Option<usize>,
+    /// How the modules are turned into machine code.
+    #[clap(long, value_enum, default_value = "clang")]
+    pub backend: Backend,
+}
+
+/// Code generators, everything after the MIR is backend specific.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// LLVM IR as text, compiled by clang.
+    Clang,
+    /// LLVM linked into the compiler, needs gahl built with `--features llvm`.
+    Llvm,
+    /// Portable C99, compiled by `$CC` (default `cc`).
+    C,
+}
+
+/// Outputs of the individual compiler stages, each module gets its own file in `build/`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// `<module>.tokens`
+    Tokens,
+    /// `<module>.ast`
+    Ast,
+    /// `<module>.mir`
+    Mir,
+    /// `<module>.ll`
+    LlvmIr,
+    /// `<module>.s`
+    Asm,
+    /// `<module>.o`
+    Obj,
+    /// The linked binary.
+    Exe,
+}
+
+impl Emit {
+    /// Whether LLVM IR has to be generated for this output.
+    pub fn needs_codegen(&self) -> bool {
+        matches!(self, Emit::LlvmIr | Emit::Asm | Emit::Obj | Emit::Exe)
+    }
+}
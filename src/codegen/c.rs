@@ -0,0 +1,479 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt::Write,
+    path::Path,
+};
+
+use crate::{
+    ast::{TypeValue, Visibility},
+    checker::mdir::{
+        escape::{address_taken, boxed_argument},
+        Expression, Function, Literal, MiddleIR, Statement,
+    },
+    config::Profile,
+};
+
+use super::{artifact_path, signed, type_value_size, BuildOptions};
+
+/// The C compiler to build and link with, `$CC` or `cc`.
+pub fn compiler() -> String {
+    std::env::var("CC").unwrap_or_else(|_| "cc".to_string())
+}
+
+// Names C reserves, locals and parameters called like this get a `_` appended.
+const KEYWORDS: [&str; 40] = [
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "_Bool",
+    "_Complex",
+    "_Imaginary",
+    "bool",
+    "true",
+    "false",
+];
+
+fn local_name(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+fn c_type(type_value: &TypeValue) -> Result<String, String> {
+    Ok(match type_value {
+        TypeValue::Void => "void".to_string(),
+        TypeValue::Bool => "bool".to_string(),
+        TypeValue::I8 => "int8_t".to_string(),
+        TypeValue::I16 => "int16_t".to_string(),
+        TypeValue::I32 => "int32_t".to_string(),
+        TypeValue::I64 => "int64_t".to_string(),
+        TypeValue::Isize => "intptr_t".to_string(),
+        TypeValue::U8 => "uint8_t".to_string(),
+        TypeValue::U16 => "uint16_t".to_string(),
+        TypeValue::U32 => "uint32_t".to_string(),
+        TypeValue::U64 => "uint64_t".to_string(),
+        TypeValue::Usize => "uintptr_t".to_string(),
+        TypeValue::F32 => "float".to_string(),
+        TypeValue::F64 => "double".to_string(),
+        TypeValue::String => "char *".to_string(),
+        TypeValue::Ptr(inner) => format!("{} *", c_type(inner)?),
+        other => return Err(format!("`{other}` has no C99 equivalent")),
+    })
+}
+
+// `int32_t n` or `char *s`, the pointer star sticks to the name.
+fn declaration(type_value: &TypeValue, name: &str) -> Result<String, String> {
+    let ty = c_type(type_value)?;
+
+    Ok(match ty.ends_with('*') {
+        true => format!("{ty}{name}"),
+        false => format!("{ty} {name}"),
+    })
+}
+
+fn prototype(
+    symbol: &str,
+    params: &[(String, TypeValue)],
+    return_type: &TypeValue,
+) -> Result<String, String> {
+    let params = match params.is_empty() {
+        true => "void".to_string(),
+        false => params
+            .iter()
+            .map(|(name, ty)| declaration(ty, &local_name(name)))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(", "),
+    };
+
+    declaration(return_type, &format!("{symbol}({params})"))
+}
+
+// `@inline` and `@cold` as GNU C attributes, other compilers only see the `inline`.
+fn c_attributes(function: &Function) -> String {
+    let mut gnu = vec![];
+    let mut inline = false;
+
+    for attribute in super::function_attributes(function) {
+        match attribute {
+            "alwaysinline" => {
+                gnu.push("always_inline");
+                inline = true;
+            }
+            "inlinehint" => inline = true,
+            "noinline" => gnu.push("noinline"),
+            "cold" => gnu.push("cold"),
+            _ => (),
+        }
+    }
+
+    let mut prefix = String::new();
+    if !gnu.is_empty() {
+        prefix = format!(
+            "#if defined(__GNUC__)\n__attribute__(({}))\n#endif\n",
+            gnu.join(", ")
+        );
+    }
+    if inline {
+        prefix += "inline ";
+    }
+
+    prefix
+}
+
+// A C string literal, everything outside printable ASCII is written as an octal escape.
+fn c_string(string: &str) -> String {
+    let mut result = String::from("\"");
+
+    for b in string.bytes() {
+        match b {
+            b'"' | b'\\' => result += &format!("\\{}", b as char),
+            b'\n' => result += "\\n",
+            0x20..=0x7e => result.push(b as char),
+            _ => result += &format!("\\{:03o}", b),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+// File name of the header of the module `name`, next to its source in `build/`.
+fn header_file(name: &str) -> String {
+    let path = artifact_path(name, "h");
+
+    Path::new(&path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or(path)
+}
+
+/// The header declaring the public functions of the module and the C99 source defining all of
+/// them, for `build/<module>.h` and `build/<module>.c`.
+pub fn module_to_c(
+    mdir: &MiddleIR,
+    name: &str,
+    options: &BuildOptions,
+) -> Result<(String, String), String> {
+    let banner = format!("/* Generated by gahl from `{name}`, do not edit. */\n");
+
+    let mut functions = mdir.functions().values().collect::<Vec<_>>();
+    functions.sort_by_key(|f| f.name.as_str());
+
+    let guard = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect::<String>();
+
+    let mut header = banner.clone();
+    let _ = write!(
+        header,
+        "#ifndef GAHL_{guard}_H\n#define GAHL_{guard}_H\n\n#include <stdbool.h>\n#include <stdint.h>\n\n"
+    );
+    for function in &functions {
+        if function.visibility == Visibility::Public && function.symbol != "main" {
+            let proto = prototype(&function.symbol, &function.params, &function.return_type)?;
+            let _ = writeln!(header, "{proto};");
+        }
+    }
+    header += "\n#endif\n";
+
+    let header_name = header_file(name);
+
+    let memory = options.profile.memory;
+    let mut source = banner;
+    let _ = writeln!(source, "#include \"{header_name}\"");
+    // Imported functions are declared by the headers of their modules.
+    for import in mdir.imports() {
+        let _ = writeln!(source, "#include \"{}\"", header_file(import));
+    }
+    source += "\n";
+    let _ = writeln!(source, "void *{}(int64_t size);", memory.alloc_fn());
+    if let Some(init) = memory.init_fn() {
+        let _ = writeln!(source, "void {init}(void);");
+    }
+
+    let mut externs = mdir.externs().iter().collect::<Vec<_>>();
+    externs.sort_by_key(|f| f.name.as_str());
+    for function in externs {
+        let proto = prototype(&function.name, &function.params, &function.return_type)?;
+        let _ = writeln!(source, "{proto};");
+    }
+
+    // Private functions aren't in the header, declare them so the order of definitions is free.
+    for function in &functions {
+        if function.visibility == Visibility::Private && function.symbol != "main" {
+            let proto = prototype(&function.symbol, &function.params, &function.return_type)?;
+            let _ = writeln!(source, "{proto};");
+        }
+    }
+
+    let mut emitter = Emitter {
+        profile: &options.profile,
+        target: &options.target,
+        helpers: BTreeMap::new(),
+        temps: 0,
+    };
+    let mut definitions = String::new();
+    for function in &functions {
+        definitions += "\n";
+        definitions += &emitter.function(function)?;
+    }
+
+    for helper in emitter.helpers.values() {
+        source += "\n";
+        source += helper;
+    }
+    source += &definitions;
+
+    Ok((header, source))
+}
+
+struct Emitter<'o> {
+    profile: &'o Profile,
+    target: &'o super::target::Target,
+    /// Overflow checked arithmetic the module uses, by function name.
+    helpers: BTreeMap<String, String>,
+    /// Boxes made for arguments, for unique names.
+    temps: u32,
+}
+
+impl Emitter<'_> {
+    fn function(&mut self, function: &Function) -> Result<String, String> {
+        let is_main = function.symbol == "main";
+        // C wants `int main`, a `void` one exits with 0.
+        let void_main = is_main && function.return_type == TypeValue::Void;
+
+        let signature = match void_main {
+            true => "int main(void)".to_string(),
+            false => prototype(&function.symbol, &function.params, &function.return_type)?,
+        };
+        // Every other function has a plain prototype, so an `inline` definition is still an
+        // external one.
+        let signature = match is_main {
+            true => signature,
+            false => c_attributes(function) + &signature,
+        };
+        let mut body = String::new();
+
+        // Escaping locals are pointers to their box, everything else is a plain C local.
+        let mut declared = HashSet::new();
+        for (name, ty) in &function.vars {
+            if !declared.insert(name) {
+                continue;
+            }
+
+            let local = local_name(name);
+            let decl = match function.escapes.contains(name) {
+                true => declaration(&TypeValue::Ptr(Box::new(ty.clone())), &local)?,
+                false => declaration(ty, &local)?,
+            };
+            let _ = writeln!(body, "    {decl};");
+        }
+
+        if is_main {
+            if let Some(init) = self.profile.memory.init_fn() {
+                let _ = writeln!(body, "    {init}();");
+            }
+        }
+
+        for (i, stmt) in function.block.iter().enumerate() {
+            let is_last = i == function.block.len() - 1;
+            let mut pre = String::new();
+
+            match stmt {
+                Statement::Expr(expr, _) => {
+                    let value = self.expr(&mut pre, function, expr)?;
+                    body += &pre;
+
+                    let Some(value) = value else { continue };
+                    let is_call =
+                        matches!(expr.back(), Some(Expression::Literal(Literal::Call(..))));
+
+                    if is_last && function.return_type != TypeValue::Void {
+                        let _ = writeln!(body, "    return {value};");
+                    } else if is_call {
+                        let _ = writeln!(body, "    {value};");
+                    } else {
+                        let _ = writeln!(body, "    (void)({value});");
+                    }
+                }
+                Statement::Var(var) => {
+                    let value = self
+                        .expr(&mut pre, function, &var.rhs)?
+                        .ok_or_else(|| format!("`{}` is assigned nothing", var.lhs))?;
+                    body += &pre;
+
+                    let local = local_name(&var.lhs);
+                    if function.escapes.contains(&var.lhs) {
+                        let alloc = self.alloc(&var.ty)?;
+                        let _ = writeln!(body, "    {local} = {alloc};");
+                        let _ = writeln!(body, "    *{local} = {value};");
+                    } else {
+                        let _ = writeln!(body, "    {local} = {value};");
+                    }
+                }
+            }
+        }
+
+        if void_main {
+            body += "    return 0;\n";
+        }
+
+        Ok(format!("{signature} {{\n{body}}}\n"))
+    }
+
+    // A fresh box for a value of `type_value` from the allocator of the memory mode.
+    fn alloc(&self, type_value: &TypeValue) -> Result<String, String> {
+        let size = type_value_size(type_value, self.target);
+        let ty = c_type(&TypeValue::Ptr(Box::new(type_value.clone())))?;
+
+        Ok(format!("({ty}){}({size})", self.profile.memory.alloc_fn()))
+    }
+
+    // The postfix `expr` as one C expression, statements it needs first go to `pre`.
+    fn expr(
+        &mut self,
+        pre: &mut String,
+        function: &Function,
+        expr: &VecDeque<Expression>,
+    ) -> Result<Option<String>, String> {
+        let mut stack: Vec<(String, TypeValue)> = vec![];
+
+        for e in expr {
+            match e {
+                Expression::Literal(literal) => {
+                    let value = self.literal(pre, function, literal)?;
+                    stack.push((value, literal._type().clone()));
+                }
+                op if op.is_op() => {
+                    let (Some((rhs, _)), Some((lhs, ty))) = (stack.pop(), stack.pop()) else {
+                        return Err(format!("`{op}` is missing an operand"));
+                    };
+                    let value = self.binary_op(op, &ty, &lhs, &rhs)?;
+                    stack.push((value, ty));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(stack.pop().map(|(value, _)| value))
+    }
+
+    fn literal(
+        &mut self,
+        pre: &mut String,
+        function: &Function,
+        literal: &Literal,
+    ) -> Result<String, String> {
+        Ok(match literal {
+            Literal::Int(ty, value) if signed(ty) => value.clone(),
+            Literal::Int(_, value) => format!("{value}u"),
+            Literal::Identifier(_, name, true) => local_name(name),
+            Literal::Identifier(_, name, false) if function.escapes.contains(name) => {
+                format!("(*{})", local_name(name))
+            }
+            Literal::Identifier(_, name, false) => local_name(name),
+            Literal::String(string) => c_string(&string[1..string.len() - 1]),
+            Literal::Call(_, symbol, args) => {
+                let mut values = vec![];
+
+                for (arg, param_type) in args {
+                    if let Some(name) = address_taken(arg, param_type) {
+                        values.push(match function.escapes.contains(name) {
+                            true => local_name(name),
+                            false => format!("&{}", local_name(name)),
+                        });
+                        continue;
+                    }
+
+                    let value = self
+                        .expr(pre, function, arg)?
+                        .ok_or_else(|| format!("argument to `{symbol}` has no value"))?;
+
+                    if let Some(arg_type) = boxed_argument(arg, param_type) {
+                        let temp = format!("gahl_box_{}", self.temps);
+                        self.temps += 1;
+
+                        let ptr_type = TypeValue::Ptr(Box::new(arg_type.clone()));
+                        let alloc = self.alloc(&arg_type)?;
+                        let _ = writeln!(pre, "    {} = {alloc};", declaration(&ptr_type, &temp)?);
+                        let _ = writeln!(pre, "    *{temp} = {value};");
+                        values.push(temp);
+                        continue;
+                    }
+
+                    values.push(value);
+                }
+
+                format!("{symbol}({})", values.join(", "))
+            }
+        })
+    }
+
+    // `(lhs <op> rhs)`, through a trapping helper when the profile has `overflow_checks` on.
+    fn binary_op(
+        &mut self,
+        op: &Expression,
+        type_value: &TypeValue,
+        lhs: &str,
+        rhs: &str,
+    ) -> Result<String, String> {
+        let (operator, checked) = match op {
+            Expression::Add => ("+", Some("add")),
+            Expression::Min => ("-", Some("sub")),
+            Expression::Mul => ("*", Some("mul")),
+            Expression::Div => ("/", None),
+            op => return Err(format!("`{op}` is not implemented yet")),
+        };
+
+        let Some(checked) = checked.filter(|_| self.profile.overflow_checks) else {
+            return Ok(format!("({lhs} {operator} {rhs})"));
+        };
+
+        let ty = c_type(type_value)?;
+        let helper = format!("gahl_{checked}_{}", ty.trim_end_matches("_t"));
+        self.helpers.entry(helper.clone()).or_insert_with(|| {
+            format!(
+                "static {ty} {helper}({ty} a, {ty} b) {{\n#if defined(__GNUC__) && !defined(__TINYC__)\n    {ty} r;\n    if (__builtin_{checked}_overflow(a, b, &r)) __builtin_trap();\n    return r;\n#else\n    return a {operator} b;\n#endif\n}}\n"
+            )
+        });
+
+        Ok(format!("{helper}({lhs}, {rhs})"))
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fmt,
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Mutex,
+    },
+    thread,
+};
+
+/// A build step that didn't succeed.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The compiler couldn't be started at all.
+    Spawn {
+        command: String,
+        error: String,
+    },
+    /// The compiler ran and exited unsuccessfully, with what it printed to stderr.
+    Failed {
+        command: String,
+        status: String,
+        stderr: String,
+    },
+    /// Generating the IR of a module panicked.
+    Codegen {
+        module: String,
+    },
+    /// The backend can't express something the module uses.
+    Lowering {
+        module: String,
+        message: String,
+    },
+    /// LLVM rejected a module, only with the in-process backend.
+    Llvm {
+        module: String,
+        message: String,
+    },
+    Io {
+        path: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Spawn { command, error } => {
+                write!(
+                    f,
+                    "\x1b[31mError:\x1b[0m could not run `{command}`: {error}"
+                )
+            }
+            BuildError::Failed {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(f, "\x1b[31mError:\x1b[0m `{command}` failed ({status})")?;
+                if !stderr.trim().is_empty() {
+                    write!(f, ":\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            BuildError::Codegen { module } => {
+                write!(
+                    f,
+                    "\x1b[31mError:\x1b[0m generating code for `{module}` failed"
+                )
+            }
+            BuildError::Lowering { module, message } => {
+                write!(
+                    f,
+                    "\x1b[31mError:\x1b[0m can't generate code for `{module}`: {message}"
+                )
+            }
+            BuildError::Llvm { module, message } => {
+                write!(
+                    f,
+                    "\x1b[31mError:\x1b[0m LLVM failed on `{module}`: {message}"
+                )
+            }
+            BuildError::Io { path, error } => {
+                write!(f, "\x1b[31mError:\x1b[0m could not write `{path}`: {error}")
+            }
+        }
+    }
+}
+
+/// Runs the compiler `program` with `flags` followed by `args`. Warnings are printed in one piece
+/// once it's done, so the output of parallel runs doesn't interleave.
+pub fn run_tool(program: &str, flags: &[String], args: &[String]) -> Result<(), BuildError> {
+    let command = std::iter::once(program)
+        .chain(flags.iter().map(String::as_str))
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let output = Command::new(program)
+        .args(flags)
+        .args(args)
+        .output()
+        .map_err(|error| BuildError::Spawn {
+            command: command.clone(),
+            error: error.to_string(),
+        })?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    if !output.status.success() {
+        return Err(BuildError::Failed {
+            command,
+            status: output.status.to_string(),
+            stderr,
+        });
+    }
+
+    if !stderr.is_empty() {
+        eprint!("{stderr}");
+    }
+
+    Ok(())
+}
+
+/// Runs every task on at most `jobs` threads, tasks are picked up in order. All tasks run even
+/// when some fail, so every error gets reported at once.
+pub fn run_jobs<T, F>(jobs: usize, tasks: &[T], run: F) -> Vec<BuildError>
+where
+    T: Sync,
+    F: Fn(&T) -> Result<(), BuildError> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let errors = Mutex::new(Vec::<BuildError>::new());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, tasks.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(task) = tasks.get(next.fetch_add(1, SeqCst)) {
+                    if let Err(error) = run(task) {
+                        errors.lock().unwrap().push(error);
+                    }
+                }
+            });
+        }
+    });
+
+    errors.into_inner().unwrap()
+}
+
+/// Number of parallel jobs when `-j` isn't given.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
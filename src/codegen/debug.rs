@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Location, TypeValue},
+    checker::mdir::Function,
+};
+
+use super::target::Target;
+
+/// DWARF metadata of a module, emitted when the profile has `debug` on. Metadata nodes are
+/// numbered in the order they are created and written after all the code.
+pub struct DebugInfo {
+    nodes: Vec<String>,
+    file: u32,
+    compile_unit: u32,
+    pointer_width: u32,
+    /// Character index of the start of every line of the source.
+    line_starts: Vec<usize>,
+    types: HashMap<TypeValue, String>,
+    locations: HashMap<(usize, usize, u32), u32>,
+}
+
+impl DebugInfo {
+    pub fn new(module: &str, target: &Target) -> Self {
+        let source = std::fs::read_to_string(module).unwrap_or_default();
+        let line_starts = std::iter::once(0)
+            .chain(
+                source
+                    .chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c == '\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+
+        let directory = std::env::current_dir()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut info = Self {
+            nodes: vec![],
+            file: 0,
+            compile_unit: 0,
+            pointer_width: target.pointer_width,
+            line_starts,
+            types: HashMap::new(),
+            locations: HashMap::new(),
+        };
+
+        info.file = info.node(format!(
+            "!DIFile(filename: {:?}, directory: {:?})",
+            module, directory
+        ));
+        info.compile_unit = info.node(format!(
+            "distinct !DICompileUnit(language: DW_LANG_C, file: !{}, producer: \"gahl {}\", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)",
+            info.file,
+            env!("CARGO_PKG_VERSION")
+        ));
+
+        info
+    }
+
+    fn node(&mut self, node: String) -> u32 {
+        self.nodes.push(node);
+        self.nodes.len() as u32 - 1
+    }
+
+    // 1-based line and column of a location.
+    fn line_col(&self, location: &Location) -> (usize, usize) {
+        let start = location.span.start;
+        let line = match self.line_starts.binary_search(&start) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+
+        (line + 1, start - self.line_starts[line] + 1)
+    }
+
+    // `!N` of the type, `null` for the ones without a DWARF equivalent yet.
+    fn type_node(&mut self, type_value: &TypeValue) -> String {
+        if let Some(node) = self.types.get(type_value) {
+            return node.clone();
+        }
+
+        let basic = |name: &str, size: u32, encoding: &str| {
+            format!("!DIBasicType(name: \"{name}\", size: {size}, encoding: {encoding})")
+        };
+
+        let node = match type_value {
+            TypeValue::Bool => basic("bool", 8, "DW_ATE_boolean"),
+            TypeValue::I8 => basic("i8", 8, "DW_ATE_signed"),
+            TypeValue::I16 => basic("i16", 16, "DW_ATE_signed"),
+            TypeValue::I32 => basic("i32", 32, "DW_ATE_signed"),
+            TypeValue::I64 => basic("i64", 64, "DW_ATE_signed"),
+            TypeValue::I128 => basic("i128", 128, "DW_ATE_signed"),
+            TypeValue::Isize => basic("isize", self.pointer_width, "DW_ATE_signed"),
+            TypeValue::U8 => basic("u8", 8, "DW_ATE_unsigned"),
+            TypeValue::U16 => basic("u16", 16, "DW_ATE_unsigned"),
+            TypeValue::U32 => basic("u32", 32, "DW_ATE_unsigned"),
+            TypeValue::U64 => basic("u64", 64, "DW_ATE_unsigned"),
+            TypeValue::U128 => basic("u128", 128, "DW_ATE_unsigned"),
+            TypeValue::Usize => basic("usize", self.pointer_width, "DW_ATE_unsigned"),
+            TypeValue::F32 => basic("f32", 32, "DW_ATE_float"),
+            TypeValue::F64 => basic("f64", 64, "DW_ATE_float"),
+            TypeValue::String => {
+                let char = self.node(basic("char", 8, "DW_ATE_unsigned_char"));
+                format!(
+                    "!DIDerivedType(tag: DW_TAG_pointer_type, name: \"string\", baseType: !{char}, size: {})",
+                    self.pointer_width
+                )
+            }
+            TypeValue::Ptr(inner) => {
+                let inner = self.type_node(inner);
+                format!(
+                    "!DIDerivedType(tag: DW_TAG_pointer_type, baseType: {inner}, size: {})",
+                    self.pointer_width
+                )
+            }
+            _ => return "null".to_string(),
+        };
+
+        let node = format!("!{}", self.node(node));
+        self.types.insert(type_value.clone(), node.clone());
+
+        node
+    }
+
+    /// The `DISubprogram` of `function`, emitted as `symbol`.
+    pub fn subprogram(&mut self, function: &Function, symbol: &str) -> u32 {
+        let mut types = vec![match function.return_type {
+            TypeValue::Void => "null".to_string(),
+            ref ty => self.type_node(ty),
+        }];
+        for (_, ty) in &function.params {
+            types.push(self.type_node(ty));
+        }
+
+        let types = self.node(format!("!{{{}}}", types.join(", ")));
+        let subroutine = self.node(format!("!DISubroutineType(types: !{types})"));
+        let (line, _) = self.line_col(&function.location);
+
+        self.node(format!(
+            "distinct !DISubprogram(name: {:?}, linkageName: {:?}, scope: !{file}, file: !{file}, line: {line}, type: !{subroutine}, scopeLine: {line}, spFlags: DISPFlagDefinition, unit: !{})",
+            function.name, symbol, self.compile_unit, file = self.file
+        ))
+    }
+
+    /// `!N` of the `DILocation` for `location` inside `scope`.
+    pub fn location(&mut self, scope: u32, location: &Location) -> u32 {
+        let (line, column) = self.line_col(location);
+
+        if let Some(id) = self.locations.get(&(line, column, scope)) {
+            return *id;
+        }
+
+        let id = self.node(format!(
+            "!DILocation(line: {line}, column: {column}, scope: !{scope})"
+        ));
+        self.locations.insert((line, column, scope), id);
+
+        id
+    }
+
+    /// A local, `arg` is the 1-based position for parameters.
+    pub fn local_variable(
+        &mut self,
+        scope: u32,
+        name: &str,
+        type_value: &TypeValue,
+        location: &Location,
+        arg: Option<usize>,
+    ) -> u32 {
+        let (line, _) = self.line_col(location);
+        let ty = self.type_node(type_value);
+        let arg = arg.map(|arg| format!("arg: {arg}, ")).unwrap_or_default();
+
+        self.node(format!(
+            "!DILocalVariable(name: {:?}, {arg}scope: !{scope}, file: !{}, line: {line}, type: {ty})",
+            name, self.file
+        ))
+    }
+
+    pub fn to_llvm_ir(&self) -> String {
+        let flags = self.nodes.len();
+        let mut result = format!(
+            "!llvm.dbg.cu = !{{!{}}}\n!llvm.module.flags = !{{!{}, !{}}}\n",
+            self.compile_unit,
+            flags,
+            flags + 1
+        );
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            result += &format!("!{i} = {node}\n");
+        }
+
+        result += &format!("!{flags} = !{{i32 7, !\"Dwarf Version\", i32 5}}\n");
+        result += &format!(
+            "!{} = !{{i32 2, !\"Debug Info Version\", i32 3}}\n",
+            flags + 1
+        );
+
+        result
+    }
+}
+
+/// The function being lowered, with its `DISubprogram`.
+pub struct Scope<'d> {
+    pub info: &'d mut DebugInfo,
+    pub subprogram: u32,
+}
+
+impl Scope<'_> {
+    /// Tags every instruction in `ir` with `location`, labels and comments are left alone.
+    pub fn attach(&mut self, ir: &str, location: &Location) -> String {
+        let id = self.info.location(self.subprogram, location);
+
+        ir.lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if line.starts_with("    ") && !trimmed.starts_with(';') {
+                    format!("{line}, !dbg !{id}\n")
+                } else {
+                    format!("{line}\n")
+                }
+            })
+            .collect()
+    }
+
+    /// Describes the stack slot `%{slot}` as the local `name`. Boxed locals hold a pointer to
+    /// their value instead.
+    pub fn declare_local(
+        &mut self,
+        slot: u32,
+        name: &str,
+        type_value: &TypeValue,
+        location: &Location,
+        boxed: bool,
+    ) -> String {
+        let variable = self
+            .info
+            .local_variable(self.subprogram, name, type_value, location, None);
+        let expression = if boxed {
+            "!DIExpression(DW_OP_deref)"
+        } else {
+            "!DIExpression()"
+        };
+
+        format!(
+            "    call void @llvm.dbg.declare(metadata ptr %{slot}, metadata !{variable}, metadata {expression})\n"
+        )
+    }
+
+    /// Describes the parameter `%{name}`, they are SSA values and have no slot.
+    pub fn declare_param(
+        &mut self,
+        index: usize,
+        name: &str,
+        ty: &str,
+        type_value: &TypeValue,
+        location: &Location,
+    ) -> String {
+        let variable =
+            self.info
+                .local_variable(self.subprogram, name, type_value, location, Some(index + 1));
+
+        format!(
+            "    call void @llvm.dbg.value(metadata {ty} %{name}, metadata !{variable}, metadata !DIExpression())\n"
+        )
+    }
+}
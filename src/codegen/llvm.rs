@@ -0,0 +1,542 @@
+//! In-process backend, builds the module through LLVM's API instead of as text. Only compiled
+//! with the `llvm` feature, it targets the LLVM 14 the text backend is tested against.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    builder::{Builder, BuilderError},
+    context::Context,
+    intrinsics::Intrinsic,
+    module::Module,
+    passes::{PassManager, PassManagerBuilder},
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target as LlvmTarget, TargetTriple,
+    },
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, StringRadix},
+    values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, PointerValue},
+    AddressSpace, OptimizationLevel,
+};
+
+use crate::{
+    ast::TypeValue,
+    checker::mdir::{
+        escape::{address_taken, boxed_argument},
+        Expression, Function, Literal, MiddleIR, Statement,
+    },
+    cli::Emit,
+};
+
+use super::{
+    artifact_path, clang::BuildError, function_attributes, signed, type_value_size, BuildOptions,
+};
+
+/// Builds, verifies and writes the artifacts of one module: `.ll` always, `.s` and `.o` when
+/// asked for.
+pub fn compile_module(
+    mdir: &MiddleIR,
+    name: &str,
+    options: &BuildOptions,
+) -> Result<(), BuildError> {
+    let error = |message: String| BuildError::Llvm {
+        module: name.to_string(),
+        message,
+    };
+
+    LlvmTarget::initialize_all(&InitializationConfig::default());
+
+    let triple = TargetTriple::create(&options.target.triple);
+    let target = LlvmTarget::from_triple(&triple).map_err(|e| error(e.to_string()))?;
+    let opt_level = match options.profile.opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    };
+    let machine = target
+        .create_target_machine(
+            &triple,
+            "generic",
+            "",
+            opt_level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| error(format!("no target machine for `{}`", options.target.triple)))?;
+
+    let context = Context::create();
+    let lowering = Lowering::new(&context, name, options);
+    lowering.module.set_triple(&triple);
+    lowering
+        .module
+        .set_data_layout(&machine.get_target_data().get_data_layout());
+
+    lowering.lower(mdir).map_err(|e| error(e.0))?;
+
+    let module = &lowering.module;
+    if let Err(message) = module.verify() {
+        return Err(error(message.to_string()));
+    }
+
+    let passes = PassManagerBuilder::create();
+    passes.set_optimization_level(opt_level);
+    let manager = PassManager::create(());
+    passes.populate_module_pass_manager(&manager);
+    manager.run_on(module);
+
+    let ll = artifact_path(name, "ll");
+    module
+        .print_to_file(&ll)
+        .map_err(|e| error(e.to_string()))?;
+
+    if options.emits(Emit::Asm) {
+        let asm = artifact_path(name, "s");
+        machine
+            .write_to_file(module, FileType::Assembly, Path::new(&asm))
+            .map_err(|e| error(e.to_string()))?;
+    }
+
+    if options.emits(Emit::Obj) || options.emits(Emit::Exe) {
+        let obj = artifact_path(name, "o");
+        machine
+            .write_to_file(module, FileType::Object, Path::new(&obj))
+            .map_err(|e| error(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+struct LowerError(String);
+
+impl From<BuilderError> for LowerError {
+    fn from(error: BuilderError) -> Self {
+        LowerError(error.to_string())
+    }
+}
+
+type Lowered<T> = Result<T, LowerError>;
+
+struct Lowering<'ctx, 'o> {
+    context: &'ctx Context,
+    module: Module<'ctx>,
+    builder: Builder<'ctx>,
+    options: &'o BuildOptions,
+}
+
+/// What the function being lowered knows about its names.
+struct Frame<'ctx> {
+    function: FunctionValue<'ctx>,
+    /// Stack slot of every local, holding the pointer to its box when it escapes.
+    slots: HashMap<String, PointerValue<'ctx>>,
+    params: HashMap<String, BasicValueEnum<'ctx>>,
+    escapes: HashSet<String>,
+}
+
+impl<'ctx, 'o> Lowering<'ctx, 'o> {
+    fn new(context: &'ctx Context, name: &str, options: &'o BuildOptions) -> Self {
+        Self {
+            context,
+            module: context.create_module(name),
+            builder: context.create_builder(),
+            options,
+        }
+    }
+
+    fn lower(&self, mdir: &MiddleIR) -> Lowered<()> {
+        let memory = self.options.profile.memory;
+        let i8_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let alloc_type = i8_ptr.fn_type(&[self.context.i64_type().into()], false);
+        self.module
+            .add_function(memory.alloc_fn(), alloc_type, None);
+        if let Some(init) = memory.init_fn() {
+            let init_type = self.context.void_type().fn_type(&[], false);
+            self.module.add_function(init, init_type, None);
+        }
+
+        for function in mdir.externs() {
+            self.declare(&function.name, &function.params, &function.return_type)?;
+        }
+        for (symbol, (params, return_type)) in mdir.imported_functions() {
+            self.declare(symbol, params, return_type)?;
+        }
+        // Everything is declared up front, so calls don't depend on the order of definitions.
+        for function in mdir.functions().values() {
+            self.declare(&function.symbol, &function.params, &function.return_type)?;
+        }
+
+        for function in mdir.functions().values() {
+            let value = self.function(function)?;
+
+            if !value.verify(false) {
+                return Err(LowerError(format!(
+                    "`{}` failed verification:\n{}",
+                    function.name,
+                    self.module
+                        .verify()
+                        .err()
+                        .map(|e| e.to_string())
+                        .unwrap_or_default()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn basic_type(&self, type_value: &TypeValue) -> Lowered<BasicTypeEnum<'ctx>> {
+        let context = self.context;
+
+        Ok(match type_value {
+            TypeValue::Bool => context.bool_type().into(),
+            TypeValue::I8 | TypeValue::U8 => context.i8_type().into(),
+            TypeValue::I16 | TypeValue::U16 => context.i16_type().into(),
+            TypeValue::I32 | TypeValue::U32 => context.i32_type().into(),
+            TypeValue::I64 | TypeValue::U64 => context.i64_type().into(),
+            TypeValue::I128 | TypeValue::U128 => context.i128_type().into(),
+            TypeValue::Isize | TypeValue::Usize => context
+                .custom_width_int_type(self.options.target.pointer_width)
+                .into(),
+            TypeValue::F32 => context.f32_type().into(),
+            TypeValue::F64 => context.f64_type().into(),
+            TypeValue::String => context.i8_type().ptr_type(AddressSpace::default()).into(),
+            TypeValue::Ptr(inner) => self
+                .basic_type(inner)?
+                .ptr_type(AddressSpace::default())
+                .into(),
+            other => return Err(LowerError(format!("`{other}` has no LLVM type yet"))),
+        })
+    }
+
+    fn declare(
+        &self,
+        symbol: &str,
+        params: &[(String, TypeValue)],
+        return_type: &TypeValue,
+    ) -> Lowered<FunctionValue<'ctx>> {
+        if let Some(function) = self.module.get_function(symbol) {
+            return Ok(function);
+        }
+
+        let params = params
+            .iter()
+            .map(|(_, ty)| self.basic_type(ty).map(BasicMetadataTypeEnum::from))
+            .collect::<Lowered<Vec<_>>>()?;
+        let fn_type = match return_type {
+            TypeValue::Void => self.context.void_type().fn_type(&params, false),
+            ty => self.basic_type(ty)?.fn_type(&params, false),
+        };
+
+        Ok(self.module.add_function(symbol, fn_type, None))
+    }
+
+    fn function(&self, function: &Function) -> Lowered<FunctionValue<'ctx>> {
+        let value = self.module.get_function(&function.symbol).unwrap();
+        for name in function_attributes(function) {
+            let kind = Attribute::get_named_enum_kind_id(name);
+            let attribute = self.context.create_enum_attribute(kind, 0);
+            value.add_attribute(AttributeLoc::Function, attribute);
+        }
+
+        let entry = self.context.append_basic_block(value, "entry");
+        self.builder.position_at_end(entry);
+
+        let mut frame = Frame {
+            function: value,
+            slots: HashMap::new(),
+            params: HashMap::new(),
+            escapes: function.escapes.clone(),
+        };
+
+        for ((name, _), param) in function.params.iter().zip(value.get_param_iter()) {
+            param.set_name(name);
+            frame.params.insert(name.clone(), param);
+        }
+
+        for (name, ty) in &function.vars {
+            if frame.slots.contains_key(name) {
+                continue;
+            }
+
+            let ty = self.basic_type(ty)?;
+            let slot = if frame.escapes.contains(name) {
+                self.builder
+                    .build_alloca(ty.ptr_type(AddressSpace::default()), name)?
+            } else {
+                self.builder.build_alloca(ty, name)?
+            };
+            frame.slots.insert(name.clone(), slot);
+        }
+
+        if function.symbol == "main" {
+            if let Some(init) = self.options.profile.memory.init_fn() {
+                let init = self.module.get_function(init).unwrap();
+                self.builder.build_call(init, &[], "")?;
+            }
+        }
+
+        for (i, stmt) in function.block.iter().enumerate() {
+            let is_last = i == function.block.len() - 1;
+
+            match stmt {
+                Statement::Expr(expr, _) => {
+                    let value = self.expr(&frame, expr)?;
+
+                    if is_last && function.return_type != TypeValue::Void {
+                        let value = value.ok_or_else(|| {
+                            LowerError(format!("`{}` doesn't return a value", function.name))
+                        })?;
+                        let value = self.coerce(value, &function.return_type)?;
+                        self.builder.build_return(Some(&value))?;
+                    }
+                }
+                Statement::Var(var) => {
+                    let value = self
+                        .expr(&frame, &var.rhs)?
+                        .ok_or_else(|| LowerError(format!("`{}` is assigned nothing", var.lhs)))?;
+                    let value = self.coerce(value, &var.ty)?;
+                    let slot = frame.slots[&var.lhs];
+
+                    if frame.escapes.contains(&var.lhs) {
+                        let ty = self.basic_type(&var.ty)?;
+                        let size = type_value_size(&var.ty, &self.options.target);
+                        let heap_box = self.alloc(size, ty)?;
+                        self.builder.build_store(slot, heap_box)?;
+                        self.builder.build_store(heap_box, value)?;
+                    } else {
+                        self.builder.build_store(slot, value)?;
+                    }
+                }
+            }
+        }
+
+        if function.return_type == TypeValue::Void || function.block.is_empty() {
+            self.builder.build_return(None)?;
+        }
+
+        Ok(value)
+    }
+
+    // Integer constants take the type they're used as, like `0` returned from a `usize` function.
+    fn coerce(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        type_value: &TypeValue,
+    ) -> Lowered<BasicValueEnum<'ctx>> {
+        match (value, self.basic_type(type_value)?) {
+            (BasicValueEnum::IntValue(int), BasicTypeEnum::IntType(ty))
+                if int.is_const() && int.get_type() != ty =>
+            {
+                Ok(int.const_cast(ty, signed(type_value)).into())
+            }
+            _ => Ok(value),
+        }
+    }
+
+    // Heap box for a value of `ty`, from the allocator of the memory mode.
+    fn alloc(&self, size: u32, ty: BasicTypeEnum<'ctx>) -> Lowered<PointerValue<'ctx>> {
+        let alloc = self
+            .module
+            .get_function(self.options.profile.memory.alloc_fn())
+            .unwrap();
+        let size = self.context.i64_type().const_int(size as u64, false);
+        let raw = self
+            .builder
+            .build_call(alloc, &[size.into()], "box")?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+
+        Ok(self
+            .builder
+            .build_pointer_cast(raw, ty.ptr_type(AddressSpace::default()), "")?)
+    }
+
+    // Evaluates the postfix `expr` on a stack, calls without a value push nothing.
+    fn expr(
+        &self,
+        frame: &Frame<'ctx>,
+        expr: &VecDeque<Expression>,
+    ) -> Lowered<Option<BasicValueEnum<'ctx>>> {
+        let mut stack: Vec<(BasicValueEnum<'ctx>, TypeValue)> = vec![];
+
+        for e in expr {
+            match e {
+                Expression::Literal(literal) => {
+                    if let Some(value) = self.literal(frame, literal)? {
+                        stack.push((value, literal._type().clone()));
+                    }
+                }
+                op if op.is_op() => {
+                    let (Some((rhs, _)), Some((lhs, ty))) = (stack.pop(), stack.pop()) else {
+                        return Err(LowerError(format!("`{op}` is missing an operand")));
+                    };
+                    let value = self.binary_op(frame, op, &ty, lhs, rhs)?;
+                    stack.push((value, ty));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(stack.pop().map(|(value, _)| value))
+    }
+
+    fn literal(
+        &self,
+        frame: &Frame<'ctx>,
+        literal: &Literal,
+    ) -> Lowered<Option<BasicValueEnum<'ctx>>> {
+        let value: BasicValueEnum<'ctx> = match literal {
+            Literal::Int(ty, value) => match self.basic_type(ty)? {
+                BasicTypeEnum::IntType(int) => int
+                    .const_int_from_string(value, StringRadix::Decimal)
+                    .ok_or_else(|| LowerError(format!("`{value}` is not a valid `{ty}`")))?
+                    .into(),
+                _ => return Err(LowerError(format!("`{value}` is not a valid `{ty}`"))),
+            },
+            Literal::Identifier(_, name, true) => frame.params[name],
+            Literal::Identifier(_, name, false) => {
+                let slot = self.value_slot(frame, name)?;
+                self.builder.build_load(slot, name)?
+            }
+            Literal::String(string) => {
+                let inner = &string[1..string.len() - 1];
+                self.builder
+                    .build_global_string_ptr(inner, ".str")?
+                    .as_pointer_value()
+                    .into()
+            }
+            Literal::Call(_, symbol, args) => return self.call(frame, symbol, args),
+        };
+
+        Ok(Some(value))
+    }
+
+    // Where the value of the local `name` lives, behind its box when it escapes.
+    fn value_slot(&self, frame: &Frame<'ctx>, name: &String) -> Lowered<PointerValue<'ctx>> {
+        let slot = frame.slots[name];
+
+        if frame.escapes.contains(name) {
+            Ok(self.builder.build_load(slot, "")?.into_pointer_value())
+        } else {
+            Ok(slot)
+        }
+    }
+
+    fn call(
+        &self,
+        frame: &Frame<'ctx>,
+        symbol: &str,
+        args: &[(VecDeque<Expression>, TypeValue)],
+    ) -> Lowered<Option<BasicValueEnum<'ctx>>> {
+        let callee = self
+            .module
+            .get_function(symbol)
+            .ok_or_else(|| LowerError(format!("`{symbol}` is not declared")))?;
+
+        let mut values: Vec<BasicMetadataValueEnum<'ctx>> = vec![];
+
+        for (arg, param_type) in args {
+            if let Some(name) = address_taken(arg, param_type) {
+                values.push(self.value_slot(frame, name)?.into());
+                continue;
+            }
+
+            let value = self
+                .expr(frame, arg)?
+                .ok_or_else(|| LowerError(format!("argument to `{symbol}` has no value")))?;
+
+            if let Some(arg_type) = boxed_argument(arg, param_type) {
+                let size = type_value_size(&arg_type, &self.options.target);
+                let heap_box = self.alloc(size, self.basic_type(&arg_type)?)?;
+                self.builder.build_store(heap_box, value)?;
+                values.push(heap_box.into());
+                continue;
+            }
+
+            values.push(self.coerce(value, param_type)?.into());
+        }
+
+        let call = self.builder.build_call(callee, &values, "")?;
+
+        Ok(call.try_as_basic_value().left())
+    }
+
+    // `lhs <op> rhs`, trapping on overflow when the profile has `overflow_checks` on.
+    fn binary_op(
+        &self,
+        frame: &Frame<'ctx>,
+        op: &Expression,
+        type_value: &TypeValue,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> Lowered<BasicValueEnum<'ctx>> {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (BasicValueEnum::IntValue(lhs), BasicValueEnum::IntValue(rhs)) => (lhs, rhs),
+            _ => {
+                return Err(LowerError(format!(
+                    "`{op}` is only implemented for integers"
+                )))
+            }
+        };
+        let signed = signed(type_value);
+
+        let checked = match op {
+            Expression::Add => "add",
+            Expression::Min => "sub",
+            Expression::Mul => "mul",
+            Expression::Div if signed => {
+                return Ok(self.builder.build_int_signed_div(lhs, rhs, "")?.into())
+            }
+            Expression::Div => {
+                return Ok(self.builder.build_int_unsigned_div(lhs, rhs, "")?.into())
+            }
+            op => return Err(LowerError(format!("`{op}` is not implemented yet"))),
+        };
+
+        if !self.options.profile.overflow_checks {
+            let value = match checked {
+                "add" => self.builder.build_int_add(lhs, rhs, "")?,
+                "sub" => self.builder.build_int_sub(lhs, rhs, "")?,
+                _ => self.builder.build_int_mul(lhs, rhs, "")?,
+            };
+            return Ok(value.into());
+        }
+
+        let sign = if signed { "s" } else { "u" };
+        let intrinsic = Intrinsic::find(&format!("llvm.{sign}{checked}.with.overflow"))
+            .and_then(|i| i.get_declaration(&self.module, &[lhs.get_type().into()]))
+            .ok_or_else(|| LowerError(format!("no overflow intrinsic for `{op}`")))?;
+        let trap = Intrinsic::find("llvm.trap")
+            .and_then(|i| i.get_declaration(&self.module, &[]))
+            .ok_or_else(|| LowerError("no `llvm.trap` intrinsic".to_string()))?;
+
+        let result = self
+            .builder
+            .build_call(intrinsic, &[lhs.into(), rhs.into()], "ov")?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, "")?;
+        let flag = self
+            .builder
+            .build_extract_value(result, 1, "ov.flag")?
+            .into_int_value();
+
+        let overflow = self.context.append_basic_block(frame.function, "overflow");
+        let ok = self.context.append_basic_block(frame.function, "ok");
+        self.builder.build_conditional_branch(flag, overflow, ok)?;
+
+        self.builder.position_at_end(overflow);
+        self.builder.build_call(trap, &[], "")?;
+        self.builder.build_unreachable()?;
+
+        self.builder.position_at_end(ok);
+
+        Ok(value)
+    }
+}
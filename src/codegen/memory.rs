@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::MemoryMode;
+
+impl MemoryMode {
+    /// The function the compiler calls to allocate `size` bytes on the heap.
+    pub fn alloc_fn(&self) -> &'static str {
+        match self {
+            MemoryMode::Gc => "GC_malloc",
+            MemoryMode::Arena => "gahl_arena_alloc",
+            MemoryMode::Manual => "gahl_heap_alloc",
+        }
+    }
+
+    /// Called at the start of `main`, before anything is allocated.
+    pub fn init_fn(&self) -> Option<&'static str> {
+        match self {
+            MemoryMode::Gc => Some("GC_init"),
+            MemoryMode::Arena => Some("gahl_arena_init"),
+            MemoryMode::Manual => None,
+        }
+    }
+
+    /// Declarations of the allocator runtime, emitted atop every module.
+    pub fn declarations(&self) -> String {
+        let mut result = format!("declare ptr @{}(i64)\n", self.alloc_fn());
+
+        if let Some(init) = self.init_fn() {
+            result += &format!("declare void @{init}()\n");
+        }
+
+        result
+    }
+
+    /// Allocation of `size` bytes into `%{id}`.
+    pub fn alloc_to_llvm_ir(&self, id: u32, size: u32) -> String {
+        format!(
+            "    %{id} = call noalias ptr @{}(i64 noundef {size})\n",
+            self.alloc_fn()
+        )
+    }
+
+    /// The bundled allocator sources to compile into the binary, found in the std root.
+    pub fn runtime(&self, std: &Path) -> Option<PathBuf> {
+        match self {
+            MemoryMode::Gc => None,
+            MemoryMode::Arena | MemoryMode::Manual => {
+                Some(std.join("alloc.c")).filter(|p| p.is_file())
+            }
+        }
+    }
+
+    /// What to hand the linker for the allocator.
+    pub fn link_flags(&self) -> Vec<String> {
+        match self {
+            MemoryMode::Gc => vec!["-lgc".to_string()],
+            MemoryMode::Arena | MemoryMode::Manual => vec![],
+        }
+    }
+}
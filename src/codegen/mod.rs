@@ -1,23 +1,69 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    fs::File,
-    io::Write,
-    process::Command,
-    sync::{
-        atomic::{AtomicUsize, Ordering::SeqCst},
-        Arc, Mutex,
-    },
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fs,
+    path::PathBuf,
     thread,
 };
 
 use crate::{
     ast::TypeValue,
-    checker::mdir::{Expression, ExternFunction, Function, Literal, MiddleIR, Statement},
+    checker::mdir::{
+        escape::{address_taken, boxed_argument},
+        Expression, ExternFunction, Function, Literal, MiddleIR, Statement,
+    },
+    cli::{Backend, Emit},
+    config::{MemoryMode, Profile},
 };
 
+use self::{
+    clang::{run_jobs, run_tool, BuildError},
+    debug::{DebugInfo, Scope},
+    target::Target,
+};
+
+pub mod c;
+pub mod clang;
+pub mod debug;
+#[cfg(feature = "llvm")]
+pub mod llvm;
+pub mod memory;
+pub mod target;
+pub mod wasm;
+
+/// How the modules are compiled and linked, from `[build]` and the command line.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub profile: Profile,
+    pub target: Target,
+    pub emit: Vec<Emit>,
+    /// How many clang processes may run at once.
+    pub jobs: usize,
+    pub backend: Backend,
+}
+
+impl BuildOptions {
+    pub fn emits(&self, emit: Emit) -> bool {
+        self.emit.contains(&emit)
+    }
+}
+
+/// Where the output of a stage for `module` is written.
+pub fn artifact_path(module: &str, extension: &str) -> String {
+    format!("build/{}.{extension}", module.replace('/', ""))
+}
+
 struct VarCounter {
     count: u32,
     var_mapping: HashMap<String, u32>,
+    /// Locals living behind a heap allocated box instead of directly in their stack slot.
+    escapes: HashSet<String>,
+    options: BuildOptions,
+    /// Module level constants (string literals) created while lowering the function.
+    constants: Vec<String>,
+    /// Intrinsics the function calls, declared once per module.
+    intrinsics: BTreeSet<String>,
+    /// Number of overflow checks so far, for unique block labels.
+    checks: u32,
 }
 
 impl VarCounter {
@@ -27,10 +73,15 @@ impl VarCounter {
         result
     }
 
-    fn new(count: u32) -> Self {
+    fn new(count: u32, escapes: HashSet<String>, options: BuildOptions) -> Self {
         VarCounter {
             count,
             var_mapping: HashMap::new(),
+            escapes,
+            options,
+            constants: vec![],
+            intrinsics: BTreeSet::new(),
+            checks: 0,
         }
     }
 
@@ -41,102 +92,233 @@ impl VarCounter {
     fn get(&self, name: &String) -> u32 {
         self.var_mapping.get(name).unwrap().clone()
     }
+
+    fn escapes(&self, name: &String) -> bool {
+        self.escapes.contains(name)
+    }
+
+    fn memory(&self) -> MemoryMode {
+        self.options.profile.memory
+    }
 }
 
-pub fn compile(modules: HashMap<String, MiddleIR>, mut libs: Vec<String>, project_name: &String) {
-    let results = thread::spawn(move || {
-        let results = Arc::new(Mutex::new(Vec::<(String, String)>::new()));
-        let task_counter = Arc::new(AtomicUsize::new(modules.len()));
-
-        modules.into_iter().for_each(|(name, module)| {
-            let task_counter_c = task_counter.clone();
-            let results_c = results.clone();
-            thread::spawn(move || {
-                let mut codegen = CodeGen::new(module, name);
-                codegen.compile();
-
-                results_c
-                    .lock()
-                    .unwrap()
-                    .push((codegen.name, codegen.llvm_ir));
-
-                task_counter_c.fetch_sub(1, SeqCst)
-            });
-        });
+pub fn compile(
+    modules: HashMap<String, MiddleIR>,
+    mut libs: Vec<String>,
+    runtimes: Vec<PathBuf>,
+    options: BuildOptions,
+    project_name: &String,
+) -> Result<(), Vec<BuildError>> {
+    // WebAssembly has a backend of its own and needs no external compiler.
+    if options.target.is_wasm() {
+        return wasm::compile(modules, &options, project_name);
+    }
 
-        loop {
-            if task_counter.load(SeqCst) == 0 {
-                break;
-            }
+    // Shared by every compiler invocation, so objects and the link agree on target and profile.
+    // C compilers other than clang are cross compilers of their own instead of taking a target.
+    let (compiler, mut flags) = match options.backend {
+        Backend::C => (c::compiler(), vec![]),
+        _ => ("clang".to_string(), vec![options.target.clang_flag()]),
+    };
+    flags.append(&mut options.profile.clang_flags());
+    let mut link_flags = options.profile.memory.link_flags();
+    let jobs = options.jobs;
+    let emit_asm = options.emits(Emit::Asm);
+    let emit_obj = options.emits(Emit::Obj) || options.emits(Emit::Exe);
+    let emit_exe = options.emits(Emit::Exe);
+
+    fs::create_dir_all("build").map_err(|error| {
+        vec![BuildError::Io {
+            path: "build".to_string(),
+            error: error.to_string(),
+        }]
+    })?;
+
+    let handles = modules
+        .into_iter()
+        .map(|(name, module)| {
+            let options = options.clone();
+            let module_name = name.clone();
+            let handle = thread::spawn(move || generate(module, module_name, options));
+
+            (name, handle)
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = vec![];
+    let mut errors = vec![];
+
+    for (name, handle) in handles {
+        match handle.join() {
+            Ok(Ok(source)) => results.push((name, source)),
+            Ok(Err(error)) => errors.push(error),
+            Err(_) => errors.push(BuildError::Codegen { module: name }),
         }
+    }
 
-        results
-    });
-
-    let results = Arc::try_unwrap(results.join().unwrap())
-        .unwrap()
-        .into_inner()
-        .unwrap();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
+    let source_flags = match options.backend {
+        Backend::C => vec!["-std=c99".to_string()],
+        _ => vec![],
+    };
+    let mut tasks: Vec<Vec<String>> = vec![];
     let mut frags = vec![];
 
-    results.iter().for_each(|(name, llvm_ir)| {
-        let object_file_path = format!("build/{}.o", name.replace('/', ""));
+    for (name, source) in &results {
+        // Compiled in process already, only left to link.
+        let Some(source) = source else {
+            if emit_obj {
+                frags.push(artifact_path(name, "o"));
+            }
+            continue;
+        };
+
+        if emit_asm {
+            let asm_file_path = artifact_path(name, "s");
+            let mut args = source_flags.clone();
+            args.extend(["-S".into(), source.clone(), "-o".into(), asm_file_path]);
+            tasks.push(args);
+        }
 
-        frags.push(object_file_path.clone());
+        if emit_obj {
+            let object_file_path = artifact_path(name, "o");
+            let mut args = source_flags.clone();
+            args.extend([
+                "-c".into(),
+                source.clone(),
+                "-o".into(),
+                object_file_path.clone(),
+            ]);
+            tasks.push(args);
+
+            frags.push(object_file_path);
+        }
+    }
 
-        let output_path = format!("build/{}.ll", name.replace('/', ""));
-        let mut file = File::create(&output_path).unwrap();
-        file.write_all(llvm_ir.as_bytes()).unwrap();
+    // The C side of the standard library and the allocator.
+    if emit_exe {
+        for runtime in runtimes {
+            let stem = runtime.file_stem().unwrap().to_string_lossy();
+            let object_file_path = format!("build/gahl_{stem}.o");
+            tasks.push(vec![
+                "-c".into(),
+                runtime.to_string_lossy().to_string(),
+                "-o".into(),
+                object_file_path.clone(),
+            ]);
+
+            frags.push(object_file_path);
+        }
+    }
 
-        let args = ["-c", output_path.as_str(), "-o", object_file_path.as_str()];
+    let errors = run_jobs(jobs, &tasks, |args| run_tool(&compiler, &flags, args));
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
-        Command::new("clang").args(args).status().unwrap();
-    });
+    if !emit_exe {
+        return Ok(());
+    }
 
     frags.append(&mut libs);
+    frags.append(&mut link_flags);
 
-    let out_path = format!("build/{project_name}");
-    let mut args: Vec<String> = vec!["-o", &out_path]
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-
+    let mut args = vec!["-o".to_string(), format!("build/{project_name}")];
     args.append(&mut frags);
 
-    Command::new("clang").args(args).status().unwrap();
+    run_tool(&compiler, &flags, &args).map_err(|error| vec![error])
+}
+
+// Lowers `module` with the selected backend and writes the result. Returns the source the
+// external compiler still has to build, nothing when the backend wrote the object itself.
+fn generate(
+    module: MiddleIR,
+    name: String,
+    options: BuildOptions,
+) -> Result<Option<String>, BuildError> {
+    let write = |path: String, contents: &str| {
+        fs::write(&path, contents)
+            .map(|_| path.clone())
+            .map_err(|error| BuildError::Io {
+                path,
+                error: error.to_string(),
+            })
+    };
+
+    match options.backend {
+        Backend::Clang => {
+            let mut codegen = CodeGen::new(module, name.clone(), options);
+            codegen.compile();
+
+            // The IR is always written, clang compiles everything else from it.
+            write(artifact_path(&name, "ll"), &codegen.llvm_ir).map(Some)
+        }
+        Backend::C => {
+            let (header, source) = c::module_to_c(&module, &name, &options).map_err(|message| {
+                BuildError::Lowering {
+                    module: name.clone(),
+                    message,
+                }
+            })?;
+
+            write(artifact_path(&name, "h"), &header)?;
+            write(artifact_path(&name, "c"), &source).map(Some)
+        }
+        #[cfg(feature = "llvm")]
+        Backend::Llvm => llvm::compile_module(&module, &name, &options).map(|_| None),
+        #[cfg(not(feature = "llvm"))]
+        Backend::Llvm => unreachable!("rejected before analysis"),
+    }
 }
 
 pub struct CodeGen {
     name: String,
     mdir: MiddleIR,
+    options: BuildOptions,
     llvm_ir: String,
 }
 
 impl CodeGen {
-    pub fn new(mdir: MiddleIR, name: String) -> Self {
+    pub fn new(mdir: MiddleIR, name: String, options: BuildOptions) -> Self {
         Self {
             name,
             mdir,
+            options,
             llvm_ir: String::new(),
         }
     }
 
     pub fn compile(&mut self) {
-        self.llvm_ir += "target triple = \"x86_64-pc-linux-gnu\"\n";
-        self.llvm_ir += "declare ptr @GC_malloc(i64)\n";
-        self.llvm_ir += "declare void @GC_init()\n";
+        let target = &self.options.target;
+
+        self.llvm_ir += &target.to_llvm_ir();
+        self.llvm_ir += &self.options.profile.memory.declarations();
 
         self.mdir.externs().iter().for_each(|f| {
-            self.llvm_ir += &extern_to_llvm_ir(f);
+            self.llvm_ir += &extern_to_llvm_ir(f, target);
         });
         self.mdir
             .imported_functions()
             .iter()
-            .for_each(|(n, f)| self.llvm_ir += &imported_function_to_llvm_ir(n, f));
+            .for_each(|(n, f)| self.llvm_ir += &imported_function_to_llvm_ir(n, f, target));
+        let mut intrinsics = BTreeSet::new();
+        let mut debug = self
+            .options
+            .profile
+            .debug
+            .then(|| DebugInfo::new(&self.name, target));
         self.mdir.functions().iter().for_each(|(_, function)| {
-            self.llvm_ir += &function_to_llvm_ir(function);
+            self.llvm_ir +=
+                &function_to_llvm_ir(function, &self.options, &mut intrinsics, debug.as_mut());
         });
+        self.llvm_ir += &intrinsics.into_iter().collect::<String>();
+
+        if let Some(debug) = debug {
+            self.llvm_ir += &debug.to_llvm_ir();
+        }
     }
 
     pub fn llvm_ir(&self) -> &String {
@@ -147,32 +329,74 @@ impl CodeGen {
 fn imported_function_to_llvm_ir(
     name: &String,
     imported_function: &(Vec<(String, TypeValue)>, TypeValue),
+    target: &Target,
 ) -> String {
     let (params, return_type) = imported_function;
-    let return_type = type_value_to_llvm_ir(return_type);
-    let params = function_params_to_llvm_ir(params);
+    let return_type = type_value_to_llvm_ir(return_type, target);
+    let params = function_params_to_llvm_ir(params, target);
     format!("declare {return_type} @{name}({params})\n")
 }
 
-fn extern_to_llvm_ir(function: &ExternFunction) -> String {
-    let return_type = type_value_to_llvm_ir(&function.return_type);
-    let params = function_params_to_llvm_ir(&function.params);
+fn extern_to_llvm_ir(function: &ExternFunction, target: &Target) -> String {
+    let return_type = type_value_to_llvm_ir(&function.return_type, target);
+    let params = function_params_to_llvm_ir(&function.params, target);
     let name = &function.name;
 
     format!("declare {return_type} @{name}({params})\n")
 }
 
-fn function_to_llvm_ir(function: &Function) -> String {
-    let return_type = type_value_to_llvm_ir(&function.return_type);
-    let name = &function.name;
-    let params = function_params_to_llvm_ir(&function.params);
-    let block =
-        function_block_to_llvm_ir(name, &function.vars, &function.block, &function.return_type);
+/// LLVM function attributes for what `@inline` and `@cold` ask of `function`.
+pub fn function_attributes(function: &Function) -> Vec<&'static str> {
+    let mut attributes = vec![];
+
+    for attribute in &function.attributes {
+        let first = attribute.args.first().map(String::as_str);
+
+        match (attribute.name.as_str(), first) {
+            ("inline", Some("always")) => attributes.push("alwaysinline"),
+            ("inline", Some("never")) => attributes.push("noinline"),
+            ("inline", _) => attributes.push("inlinehint"),
+            ("cold", _) => attributes.push("cold"),
+            _ => (),
+        }
+    }
+
+    attributes
+}
+
+fn function_to_llvm_ir(
+    function: &Function,
+    options: &BuildOptions,
+    intrinsics: &mut BTreeSet<String>,
+    debug: Option<&mut DebugInfo>,
+) -> String {
+    let return_type = type_value_to_llvm_ir(&function.return_type, &options.target);
+    let name = &function.symbol;
+    let params = function_params_to_llvm_ir(&function.params, &options.target);
+
+    let mut scope = debug.map(|info| {
+        let subprogram = info.subprogram(function, name);
+        Scope { info, subprogram }
+    });
+    let dbg = match &scope {
+        Some(scope) => format!(" !dbg !{}", scope.subprogram),
+        None => String::new(),
+    };
+    let attributes = function_attributes(function)
+        .iter()
+        .map(|attribute| format!(" {attribute}"))
+        .collect::<String>();
+
+    let mut var_counter = VarCounter::new(0, function.escapes.clone(), options.clone());
+    let block = function_block_to_llvm_ir(function, &mut var_counter, scope.as_mut());
+
+    let constants = var_counter.constants.concat();
+    intrinsics.append(&mut var_counter.intrinsics);
 
-    format!("define {return_type} @{name}({params}) {{\n{block}}}\n")
+    format!("{constants}define {return_type} @{name}({params}){attributes}{dbg} {{\n{block}}}\n")
 }
 
-fn function_params_to_llvm_ir(params: &Vec<(String, TypeValue)>) -> String {
+fn function_params_to_llvm_ir(params: &Vec<(String, TypeValue)>, target: &Target) -> String {
     let mut result = String::new();
 
     let mut is_multi_param = false;
@@ -182,7 +406,7 @@ fn function_params_to_llvm_ir(params: &Vec<(String, TypeValue)>) -> String {
             result += ", "
         }
 
-        let param_type = type_value_to_llvm_ir(ty);
+        let param_type = type_value_to_llvm_ir(ty, target);
 
         result += &format!("{param_type} %{name}");
 
@@ -193,43 +417,89 @@ fn function_params_to_llvm_ir(params: &Vec<(String, TypeValue)>) -> String {
 }
 
 fn function_block_to_llvm_ir(
-    context: &String,
-    vars: &Vec<(String, TypeValue)>,
-    block: &Vec<Statement>,
-    return_type: &TypeValue,
+    function: &Function,
+    var_counter: &mut VarCounter,
+    mut debug: Option<&mut Scope>,
 ) -> String {
-    let mut result = String::from("entry:\n");
+    let context = &function.name;
+    let vars = &function.vars;
+    let block = &function.block;
+    let return_type = &function.return_type;
 
-    let mut var_counter = VarCounter::new(0);
+    let mut result = String::from("entry:\n");
 
     if block.len() == 0 {
         return "    ret void\n".to_string();
     }
 
-    for (name, _ty) in vars {
+    // Locals that don't escape live in a stack slot of their own type, which LLVM promotes to
+    // registers. Escaping ones get a slot holding the pointer to their heap box.
+    for (name, ty) in vars {
+        if var_counter.var_mapping.contains_key(name) {
+            continue;
+        }
+
         let var_id = var_counter.use_c();
         var_counter.insert(name.clone(), var_id);
-        result += &format!("    %{var_id} = alloca ptr, align 8\n");
+
+        let boxed = var_counter.escapes(name);
+        if boxed {
+            result += &format!("    %{var_id} = alloca ptr\n");
+        } else {
+            let ty = type_value_to_llvm_ir(ty, &var_counter.options.target);
+            result += &format!("    %{var_id} = alloca {ty}\n");
+        }
+
+        if let Some(debug) = debug.as_mut() {
+            let location = block
+                .iter()
+                .find_map(|stmt| match stmt {
+                    Statement::Var(var) if &var.lhs == name => Some(&var.location),
+                    _ => None,
+                })
+                .unwrap_or(&function.location);
+            result += &debug.declare_local(var_id, name, ty, location, boxed);
+            var_counter
+                .intrinsics
+                .insert("declare void @llvm.dbg.declare(metadata, metadata, metadata)\n".into());
+        }
+    }
+
+    if let Some(debug) = debug.as_mut() {
+        for (index, (name, ty)) in function.params.iter().enumerate() {
+            let ty_ir = type_value_to_llvm_ir(ty, &var_counter.options.target);
+            result += &debug.declare_param(index, name, &ty_ir, ty, &function.location);
+        }
+        if !function.params.is_empty() {
+            var_counter
+                .intrinsics
+                .insert("declare void @llvm.dbg.value(metadata, metadata, metadata)\n".into());
+        }
     }
 
-    if context == "main" {
-        result += "    call void @GC_init()\n"
+    if function.symbol == "main" {
+        if let Some(init) = var_counter.memory().init_fn() {
+            result += &format!("    call void @{init}()\n");
+        }
+    }
+
+    if let Some(debug) = debug.as_mut() {
+        result = String::from("entry:\n")
+            + &debug.attach(&result["entry:\n".len()..], &function.location);
     }
 
     for (i, stmt) in block.iter().enumerate() {
+        let start = result.len();
+
         match stmt {
-            Statement::Expr(expr) => {
-                let (expr_ir, name, _type) =
-                    &expr_to_llvm_ir(expr, context, i, false, None, &mut var_counter);
+            Statement::Expr(expr, _) => {
+                let (expr_ir, name, _type) = &expr_to_llvm_ir(expr, context, i, var_counter);
 
-                let ty = type_value_to_llvm_ir(return_type);
+                let ty = type_value_to_llvm_ir(return_type, &var_counter.options.target);
 
                 if return_type == &TypeValue::Void && i == block.len() - 1 {
                     result += expr_ir;
-                    break;
-                }
-
-                if i == block.len() - 1 {
+                } else if i == block.len() - 1 {
                     match name {
                         Some(name) => {
                             result += expr_ir;
@@ -244,47 +514,48 @@ fn function_block_to_llvm_ir(
                 }
             }
             Statement::Var(var) => {
-                let ty = type_value_to_llvm_ir(&var.ty);
+                let ty = type_value_to_llvm_ir(&var.ty, &var_counter.options.target);
 
                 result += "    ; var begin\n";
 
                 let var_id = var_counter.get(&var.lhs);
 
-                let var_alloca_ptr_id = var_counter.use_c();
-                result += &format!("    %{var_alloca_ptr_id} = call noalias ptr @GC_malloc(i64 noundef 4)\n");
-                let var_id_load = var_counter.use_c();
-                // result += &format!("    %{var_id_load} = load ptr, ptr %{var_id}, align 8\n");
-                result += &format!("    store ptr %{var_alloca_ptr_id}, ptr %{var_id}\n");
-
-                let var_alloca_load_id = var_counter.use_c();
-                result += &format!("    %{var_alloca_load_id} = load ptr, ptr %{var_id}, align 8\n");
+                let (expr_ir, name, _type) = &expr_to_llvm_ir(&var.rhs, context, i, var_counter);
 
-                let (expr_ir, name, _type) =
-                    &expr_to_llvm_ir(&var.rhs, context, i, true, None, &mut var_counter);
-                
-                match name {
+                let value = match name {
                     Some(name) => {
-                        if &var.ty == &TypeValue::String {
-                            result += &format!("    %{var_id} = {expr_ir}\n");
-                            result += &format!("    store {name}, ptr %{var_id}\n");
-                        } else {
-                            result += expr_ir;
-                            result += &format!("    store {ty} {name}, ptr %{var_alloca_load_id}\n")
-                        }
-                    }
-                    None => {
-                        // result += &format!("    %{var_id} = alloca {ty}\n");
-                        result += &format!("    store {ty} {expr_ir}, {ty}* %{var_id}\n");
+                        result += expr_ir;
+                        name.clone()
                     }
+                    None => expr_ir.clone(),
+                };
+
+                if var_counter.escapes(&var.lhs) {
+                    let size = type_value_size(&var.ty, &var_counter.options.target);
+                    let box_id = var_counter.use_c();
+                    result += &var_counter.memory().alloc_to_llvm_ir(box_id, size);
+                    result += &format!("    store ptr %{box_id}, ptr %{var_id}\n");
+                    result += &format!("    store {ty} {value}, ptr %{box_id}\n");
+                } else {
+                    result += &format!("    store {ty} {value}, ptr %{var_id}\n");
                 }
 
                 result += "    ; var finished\n";
             }
         }
+
+        if let Some(debug) = debug.as_mut() {
+            let stmt_ir = result.split_off(start);
+            result += &debug.attach(&stmt_ir, stmt.location());
+        }
     }
     if return_type == &TypeValue::Void {
         result += &format!("    ; Automatic void return\n");
-        result += &format!("    ret void\n");
+        let ret = "    ret void\n";
+        match (debug.as_mut(), block.last()) {
+            (Some(debug), Some(last)) => result += &debug.attach(ret, last.location()),
+            _ => result += ret,
+        }
     };
 
     result
@@ -296,7 +567,6 @@ fn literal_to_llvm_ir(
     context: &String,
     i: usize,
     _type: &TypeValue,
-    is_var: bool,
     var_counter: &mut VarCounter,
 ) -> (String, String, bool) {
     let mut result = String::new();
@@ -305,93 +575,86 @@ fn literal_to_llvm_ir(
 
     match literal {
         Literal::Int(_ty, int) => {
-            let _ty = type_value_to_llvm_ir(_type);
+            let _ty = type_value_to_llvm_ir(_type, &var_counter.options.target);
             ir += &format!("{int}");
             is_final = true;
         }
-        Literal::Identifier(ty, value, false) => match ty {
-            TypeValue::String => {
-                // let ty = type_value_to_llvm_ir(ty);
-                let param_name = format!("%{}", var_counter.use_c());
-                result += &format!("    {param_name} = getelementptr inbounds [14 x i8], [14 x i8]* %{value}, i32 0, i32 0\n");
-                ir = param_name;
-                is_final = true;
-            }
-            TypeValue::Ptr(_) => {
-                let var_id = var_counter.get(value);
-                let loaded_ptr = var_counter.use_c();
-                result += &format!("    %{loaded_ptr} = load ptr, ptr %{var_id}, align 8\n");
-                ir = format!("%{loaded_ptr}");
-                is_final = true;
-            }
-            _ => {
-                result += &format!("    ; cloning {value}\n");
-                
-                let var_clone_ptr_id = var_counter.use_c();
-                result += &format!("    %{var_clone_ptr_id} = call noalias ptr @GC_malloc(i64 noundef 4)\n");
-
-                let var_id = var_counter.get(value);
-                let var_id_load = var_counter.use_c();
-                result += &format!("    %{var_id_load} = load ptr, ptr %{var_id}, align 8\n");
-                result += &format!("    call void @llvm.memcpy.p0.p0.i64(ptr align 4 %{var_clone_ptr_id}, ptr align 4 %{var_id_load}, i64 4, i1 false)\n");
-
-                let value_clone_load_to_value_id = var_counter.use_c();
-                result += &format!(
-                    "    %{value_clone_load_to_value_id} = load i32, ptr %{var_clone_ptr_id}, align 4\n"
-                );
-
-                result += "    ; finished cloning\n";
-                ir = format!("%{value_clone_load_to_value_id}");
-                is_final = true;
-            }
-        },
+        Literal::Identifier(ty, value, false) => {
+            let ty = type_value_to_llvm_ir(ty, &var_counter.options.target);
+            let var_id = var_counter.get(value);
+
+            let slot = if var_counter.escapes(value) {
+                let box_id = var_counter.use_c();
+                result += &format!("    %{box_id} = load ptr, ptr %{var_id}\n");
+                box_id
+            } else {
+                var_id
+            };
+
+            let value_id = var_counter.use_c();
+            result += &format!("    %{value_id} = load {ty}, ptr %{slot}\n");
+            ir = format!("%{value_id}");
+            is_final = true;
+        }
         Literal::Identifier(_ty, value, true) => {
             ir = format!("%{}", value);
             is_final = true;
         }
         Literal::String(string) => {
-            let null_terminated = format!(
-                "{}\0{}",
-                &string[..string.len() - 1],
-                &string[string.len() - 1..]
-            );
-
-            let length = null_terminated.len() - 2;
-
-            if is_var {
-                result += &format!("alloca [{length} x i8]");
-                ir = format!("[{length} x i8] c{null_terminated}",);
-                is_final = true;
-            } else {
-                let string_name = format!("%{}", var_counter.use_c());
-                result += &format!("    {string_name} = alloca [{length} x i8]\n");
-                result += &format!(
-                    "    store [{length} x i8] c{null_terminated}, [{length} x i8]* {string_name}\n"
-                );
-                let string_name_ptr_id = var_counter.use_c();
-                result += &format!("    %{string_name_ptr_id} = getelementptr inbounds [{length} x i8], [{length} x i8]* {string_name}, i32 0, i32 0\n");
-                ir = format!("%{string_name_ptr_id}");
-                is_final = true;
-            }
+            let inner = &string[1..string.len() - 1];
+            let null_terminated = format!("\"{}\\00\"", escape_llvm_string(inner));
+
+            let length = inner.len() + 1;
+
+            // Literals are constants, so they can be handed out past the end of the frame.
+            let string_name = format!("@.str.{}.{}", context, var_counter.constants.len());
+            var_counter.constants.push(format!(
+                "{string_name} = private unnamed_addr constant [{length} x i8] c{null_terminated}\n"
+            ));
+
+            ir = string_name;
+            is_final = true;
         }
         Literal::Call(ret_ty, func_name, args) => {
-            let ret_ty_ir = type_value_to_llvm_ir(ret_ty);
+            let ret_ty_ir = type_value_to_llvm_ir(ret_ty, &var_counter.options.target);
 
             let args_ir = args
                 .iter()
                 .map(|(arg, arg_type_value)| {
+                    if let Some(name) = address_taken(arg, arg_type_value) {
+                        let (arg_ir, address) = address_of_local(name, var_counter);
+                        result += &arg_ir;
+
+                        return format!("ptr {address}");
+                    }
+
                     let (arg_ir, arg_name, arg_type) =
-                        expr_to_llvm_ir(arg, context, i, false, Some(arg_type_value), var_counter);
+                        expr_to_llvm_ir(arg, context, i, var_counter);
                     result += &arg_ir;
 
-                    format!("{arg_type} {}", arg_name.unwrap())
+                    let arg_name = arg_name.unwrap();
+
+                    if let Some(arg_mdir_type) = boxed_argument(arg, arg_type_value) {
+                        let size = type_value_size(&arg_mdir_type, &var_counter.options.target);
+                        let box_id = var_counter.use_c();
+                        result += &var_counter.memory().alloc_to_llvm_ir(box_id, size);
+                        result += &format!("    store {arg_type} {arg_name}, ptr %{box_id}\n");
+
+                        return format!("ptr %{box_id}");
+                    }
+
+                    format!("{arg_type} {arg_name}")
                 })
                 .collect::<Vec<String>>()
                 .join(", ");
 
-            let call_id = var_counter.use_c();
-            result += &format!("    %{call_id} = call {ret_ty_ir} @{func_name}({args_ir})\n");
-            ir = format!("%{call_id}");
+            if ret_ty == &TypeValue::Void {
+                result += &format!("    call void @{func_name}({args_ir})\n");
+            } else {
+                let call_id = var_counter.use_c();
+                result += &format!("    %{call_id} = call {ret_ty_ir} @{func_name}({args_ir})\n");
+                ir = format!("%{call_id}");
+            }
             is_final = true;
         }
     }
@@ -403,8 +666,6 @@ fn expr_to_llvm_ir(
     expr: &VecDeque<Expression>,
     context: &String,
     i: usize,
-    is_var: bool,
-    expected_type: Option<&TypeValue>,
     var_counter: &mut VarCounter,
 ) -> (String, Option<String>, String) {
     let mut result = String::new();
@@ -416,17 +677,18 @@ fn expr_to_llvm_ir(
 
     if expr.len() == 1 {
         if let Expression::Literal(rhs) = &expr[0] {
-            let final_name = format!("%{}", var_counter.use_c());
-
-            let _type = type_value_to_llvm_ir(rhs._type()).to_string();
+            let _type = type_value_to_llvm_ir(rhs._type(), &var_counter.options.target).to_string();
 
             let (in_ir, expr_ir, is_final) =
-                literal_to_llvm_ir(rhs, context, i + 1, rhs._type(), is_var, var_counter);
+                literal_to_llvm_ir(rhs, context, i + 1, rhs._type(), var_counter);
 
             result += &in_ir;
             if is_final {
                 return (result, Some(expr_ir), _type);
             }
+
+            // Numbered only once needed, LLVM rejects gaps in the numbering.
+            let final_name = format!("%{}", var_counter.use_c());
             result += &format!("    {} = {}\n", final_name, expr_ir,);
             prev_name = Some(final_name);
 
@@ -448,21 +710,25 @@ fn expr_to_llvm_ir(
                     let rhs_type = rhs._type();
 
                     let (in_ir, rhs_ir, _is_final) =
-                        literal_to_llvm_ir(rhs, context, i, rhs_type, is_var, var_counter);
+                        literal_to_llvm_ir(rhs, context, i, rhs_type, var_counter);
                     result += &in_ir;
 
                     let (in_ir, lhs_ir, _is_final) =
-                        literal_to_llvm_ir(lhs, context, i, lhs_type, is_var, var_counter);
+                        literal_to_llvm_ir(lhs, context, i, lhs_type, var_counter);
                     result += &in_ir;
 
                     let final_name = format!("%{}", var_counter.use_c());
                     i += 1;
-                    let _type = type_value_to_llvm_ir(lhs._type());
+                    let _type = type_value_to_llvm_ir(lhs._type(), &var_counter.options.target);
                     final_type = lhs._type();
 
-                    result += &format!(
-                        "    {} = {e} {} {}, {}\n",
-                        final_name, _type, lhs_ir, rhs_ir
+                    result += &binary_op_to_llvm_ir(
+                        e,
+                        lhs._type(),
+                        &final_name,
+                        &lhs_ir,
+                        &rhs_ir,
+                        var_counter,
                     );
                     prev_name = Some(final_name);
                 }
@@ -476,18 +742,19 @@ fn expr_to_llvm_ir(
                     i += 1;
                     let rhs_type = rhs._type();
                     final_type = rhs._type();
-                    let _type = type_value_to_llvm_ir(rhs_type);
+                    let _type = type_value_to_llvm_ir(rhs_type, &var_counter.options.target);
 
                     let (in_ir, rhs_ir, _is_final) =
-                        literal_to_llvm_ir(rhs, context, i, rhs_type, is_var, var_counter);
+                        literal_to_llvm_ir(rhs, context, i, rhs_type, var_counter);
                     result += &in_ir;
 
-                    result += &format!(
-                        "    {} = {} i32 {}, {}\n",
-                        final_name,
-                        e.to_string(),
-                        prev_name.unwrap(),
-                        rhs_ir,
+                    result += &binary_op_to_llvm_ir(
+                        e,
+                        rhs_type,
+                        &final_name,
+                        &prev_name.unwrap(),
+                        &rhs_ir,
+                        var_counter,
                     );
                     prev_name = Some(final_name);
                 }
@@ -497,15 +764,34 @@ fn expr_to_llvm_ir(
         }
     }
 
-    let _type = type_value_to_llvm_ir(final_type).to_string();
+    let _type = type_value_to_llvm_ir(final_type, &var_counter.options.target).to_string();
 
     (result, prev_name, _type)
 }
 
-fn type_value_to_llvm_ir(type_value: &TypeValue) -> String {
+// Escapes everything LLVM's `c"..."` constants can't hold verbatim as `\XX`.
+fn escape_llvm_string(string: &str) -> String {
+    string
+        .bytes()
+        .map(|b| match b {
+            0x20..=0x7e if b != b'"' && b != b'\\' => (b as char).to_string(),
+            _ => format!("\\{:02X}", b),
+        })
+        .collect()
+}
+
+fn type_value_to_llvm_ir(type_value: &TypeValue, target: &Target) -> String {
     match type_value {
         TypeValue::Void => "void".to_string(),
-        TypeValue::I32 => "i32".to_string(),
+        TypeValue::Bool => "i1".to_string(),
+        TypeValue::I8 | TypeValue::U8 => "i8".to_string(),
+        TypeValue::I16 | TypeValue::U16 => "i16".to_string(),
+        TypeValue::I32 | TypeValue::U32 => "i32".to_string(),
+        TypeValue::I64 | TypeValue::U64 => "i64".to_string(),
+        TypeValue::I128 | TypeValue::U128 => "i128".to_string(),
+        TypeValue::Isize | TypeValue::Usize => format!("i{}", target.pointer_width),
+        TypeValue::F32 => "float".to_string(),
+        TypeValue::F64 => "double".to_string(),
         TypeValue::String => "i8*".to_string(),
         TypeValue::Ptr(_inner_ty) => {
             // let inner_ty = type_value_to_llvm_ir(inner_ty);
@@ -519,20 +805,99 @@ fn type_value_to_llvm_ir(type_value: &TypeValue) -> String {
     }
 }
 
-fn type_and_expr_to_size(expr: &Expression, type_value: &TypeValue) -> u32 {
+// `{final_name} = lhs <op> rhs`, checked for overflow when the profile asks for it.
+fn binary_op_to_llvm_ir(
+    op: &Expression,
+    type_value: &TypeValue,
+    final_name: &String,
+    lhs: &String,
+    rhs: &String,
+    var_counter: &mut VarCounter,
+) -> String {
+    let ty = type_value_to_llvm_ir(type_value, &var_counter.options.target);
+    let signed = signed(type_value);
+
+    let (instruction, checked) = match op {
+        Expression::Add => ("add", Some("add")),
+        Expression::Min => ("sub", Some("sub")),
+        Expression::Mul => ("mul", Some("mul")),
+        Expression::Div if signed => ("sdiv", None),
+        Expression::Div => ("udiv", None),
+        op => {
+            println!("Unhandled operator: `{}`", op);
+            todo!()
+        }
+    };
+
+    let checked = checked.filter(|_| var_counter.options.profile.overflow_checks);
+    let Some(checked) = checked else {
+        return format!("    {final_name} = {instruction} {ty} {lhs}, {rhs}\n");
+    };
+
+    let sign = if signed { "s" } else { "u" };
+    let intrinsic = format!("llvm.{sign}{checked}.with.overflow.{ty}");
+    var_counter
+        .intrinsics
+        .insert(format!("declare {{{ty}, i1}} @{intrinsic}({ty}, {ty})\n"));
+    var_counter
+        .intrinsics
+        .insert("declare void @llvm.trap()\n".to_string());
+
+    let check = var_counter.checks;
+    var_counter.checks += 1;
+
+    // Named values and labels, unnamed ones have to be numbered without gaps.
+    let mut result = String::new();
+    result +=
+        &format!("    %ov.{check} = call {{{ty}, i1}} @{intrinsic}({ty} {lhs}, {ty} {rhs})\n");
+    result += &format!("    {final_name} = extractvalue {{{ty}, i1}} %ov.{check}, 0\n");
+    result += &format!("    %ov.{check}.flag = extractvalue {{{ty}, i1}} %ov.{check}, 1\n");
+    result += &format!("    br i1 %ov.{check}.flag, label %overflow.{check}, label %ok.{check}\n");
+    result += &format!("overflow.{check}:\n");
+    result += "    call void @llvm.trap()\n";
+    result += "    unreachable\n";
+    result += &format!("ok.{check}:\n");
+
+    result
+}
+
+// Whether arithmetic on `type_value` is signed.
+fn signed(type_value: &TypeValue) -> bool {
+    !matches!(
+        type_value,
+        TypeValue::U8
+            | TypeValue::U16
+            | TypeValue::U32
+            | TypeValue::U64
+            | TypeValue::U128
+            | TypeValue::Usize
+    )
+}
+
+// Size in bytes of a value of `type_value`, for heap boxes.
+fn type_value_size(type_value: &TypeValue, target: &Target) -> u32 {
     match type_value {
-        TypeValue::I8 => 1,
-        TypeValue::I16 => 2,
-        TypeValue::I32 => 4,
-        TypeValue::I64 => 8,
-        TypeValue::String => {
-            if let Expression::Literal(literal) = expr {
-                if let Literal::String(string) = literal {
-                    return string.len() as u32;
-                }
-            }
-            0
+        TypeValue::Bool | TypeValue::I8 | TypeValue::U8 => 1,
+        TypeValue::I16 | TypeValue::U16 => 2,
+        TypeValue::I32 | TypeValue::U32 | TypeValue::F32 => 4,
+        TypeValue::I64 | TypeValue::U64 | TypeValue::F64 => 8,
+        TypeValue::I128 | TypeValue::U128 => 16,
+        TypeValue::String | TypeValue::Ptr(_) | TypeValue::Isize | TypeValue::Usize => {
+            target.pointer_bytes()
         }
         _ => todo!(),
     }
 }
+
+// Address to pass for a local whose address is taken, it lives in a heap box by now.
+fn address_of_local(name: &String, var_counter: &mut VarCounter) -> (String, String) {
+    let var_id = var_counter.get(name);
+
+    if var_counter.escapes(name) {
+        let box_id = var_counter.use_c();
+        let ir = format!("    %{box_id} = load ptr, ptr %{var_id}\n");
+        (ir, format!("%{box_id}"))
+    } else {
+        (String::new(), format!("%{var_id}"))
+    }
+}
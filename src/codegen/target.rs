@@ -0,0 +1,114 @@
+use core::fmt;
+
+/// The machine the emitted code is for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub triple: String,
+    /// Width of a pointer, and so of `usize` and `isize`, in bits.
+    pub pointer_width: u32,
+    pub datalayout: &'static str,
+}
+
+#[derive(Debug)]
+pub struct TargetError {
+    pub triple: String,
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unsupported target `{}`, the architecture has to be one of: {}",
+            self.triple,
+            ARCHS
+                .iter()
+                .map(|(arch, _, _)| *arch)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+// Architecture, pointer width and the datalayout clang uses for it on Linux.
+const ARCHS: [(&str, u32, &str); 7] = [
+    (
+        "x86_64",
+        64,
+        "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128",
+    ),
+    (
+        "aarch64",
+        64,
+        "e-m:e-i8:8:32-i16:16:32-i64:64-i128:128-n32:64-S128",
+    ),
+    ("riscv64", 64, "e-m:e-p:64:64-i64:64-i128:128-n32:64-S128"),
+    (
+        "i686",
+        32,
+        "e-m:e-p:32:32-p270:32:32-p271:32:32-p272:64:64-i128:128-f80:32-n8:16:32-S128",
+    ),
+    (
+        "armv7",
+        32,
+        "e-m:e-p:32:32-Fi8-i64:64-v128:64:128-a:0:32-n32-S64",
+    ),
+    ("riscv32", 32, "e-m:e-p:32:32-i64:64-n32-S128"),
+    ("wasm32", 32, "e-m:e-p:32:32-i64:64-n32:64-S128"),
+];
+
+impl Target {
+    pub fn from_triple(triple: &str) -> Result<Self, TargetError> {
+        let arch = triple.split('-').next().unwrap_or_default();
+
+        match ARCHS.iter().find(|(name, _, _)| *name == arch) {
+            Some((_, pointer_width, datalayout)) => Ok(Self {
+                triple: triple.to_string(),
+                pointer_width: *pointer_width,
+                datalayout,
+            }),
+            None => Err(TargetError {
+                triple: triple.to_string(),
+            }),
+        }
+    }
+
+    /// The machine `gahl` itself runs on.
+    pub fn host() -> Self {
+        let triple = match std::env::consts::ARCH {
+            "x86_64" => "x86_64-pc-linux-gnu".to_string(),
+            arch => format!("{arch}-unknown-linux-gnu"),
+        };
+
+        // Previously the only supported target.
+        Self::from_triple(&triple)
+            .unwrap_or_else(|_| Self::from_triple("x86_64-pc-linux-gnu").unwrap())
+    }
+
+    /// WebAssembly is built by `codegen::wasm` instead of clang.
+    pub fn is_wasm(&self) -> bool {
+        self.triple.starts_with("wasm32")
+    }
+
+    pub fn pointer_bytes(&self) -> u32 {
+        self.pointer_width / 8
+    }
+
+    /// The header every module starts with.
+    pub fn to_llvm_ir(&self) -> String {
+        format!(
+            "target datalayout = \"{}\"\ntarget triple = \"{}\"\n",
+            self.datalayout, self.triple
+        )
+    }
+
+    /// Passed to every clang invocation.
+    pub fn clang_flag(&self) -> String {
+        format!("--target={}", self.triple)
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::host()
+    }
+}
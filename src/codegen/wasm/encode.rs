@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use super::{mem_op, op, FuncType, Instr, Module, ValType, DATA_BASE};
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend(name.as_bytes());
+}
+
+fn val_type(value: &ValType) -> u8 {
+    match value {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+    }
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: Vec<u8>) {
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+// A vector of `items`, prefixed with their count.
+fn vector<T>(items: &[T], mut item: impl FnMut(&mut Vec<u8>, &T)) -> Vec<u8> {
+    let mut out = vec![];
+    unsigned(&mut out, items.len() as u64);
+
+    for i in items {
+        item(&mut out, i);
+    }
+
+    out
+}
+
+/// The binary encoding of `module`, as loaded by wasm runtimes.
+pub fn module(module: &Module) -> Vec<u8> {
+    let (addresses, heap_base) = module.layout();
+    let indices = module.function_indices();
+
+    // Every distinct signature once, functions refer to them by index.
+    let mut types: Vec<&FuncType> = vec![];
+    let signatures = module
+        .imports
+        .iter()
+        .map(|i| &i.ty)
+        .chain(module.funcs.iter().map(|f| &f.ty))
+        .map(|ty| match types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                types.push(ty);
+                types.len() as u32 - 1
+            }
+        })
+        .collect::<Vec<_>>();
+    let (import_types, func_types) = signatures.split_at(module.imports.len());
+
+    let mut out = b"\0asm".to_vec();
+    out.extend(1u32.to_le_bytes());
+
+    section(
+        &mut out,
+        1,
+        vector(&types, |out, ty| {
+            out.push(0x60);
+            out.extend(vector(&ty.params, |out, v| out.push(val_type(v))));
+            out.extend(vector(&ty.results, |out, v| out.push(val_type(v))));
+        }),
+    );
+
+    let imports = module
+        .imports
+        .iter()
+        .zip(import_types.iter().copied())
+        .collect::<Vec<_>>();
+    section(
+        &mut out,
+        2,
+        vector(&imports, |out, (import, ty)| {
+            name(out, &import.module);
+            name(out, &import.name);
+            out.push(0x00);
+            unsigned(out, *ty as u64);
+        }),
+    );
+
+    section(
+        &mut out,
+        3,
+        vector(func_types, |out, ty| unsigned(out, *ty as u64)),
+    );
+
+    section(
+        &mut out,
+        5,
+        vector(&[module.pages()], |out, pages| {
+            out.push(0x00);
+            unsigned(out, *pages as u64);
+        }),
+    );
+
+    section(
+        &mut out,
+        6,
+        vector(&[heap_base], |out, heap_base| {
+            out.extend([val_type(&ValType::I32), 0x01, 0x41]);
+            signed(out, *heap_base as i64);
+            out.push(0x0b);
+        }),
+    );
+
+    let mut exports = module
+        .funcs
+        .iter()
+        .filter_map(|f| Some((f.export.as_deref()?, 0x00, indices[f.name.as_str()])))
+        .collect::<Vec<_>>();
+    exports.push(("memory", 0x02, 0));
+    section(
+        &mut out,
+        7,
+        vector(&exports, |out, (export, kind, index)| {
+            name(out, export);
+            out.push(*kind);
+            unsigned(out, *index as u64);
+        }),
+    );
+
+    section(
+        &mut out,
+        10,
+        vector(&module.funcs, |out, func| {
+            let mut body = vec![];
+
+            // Runs of equally typed locals are declared together.
+            let mut groups: Vec<(u32, ValType)> = vec![];
+            for local in &func.locals {
+                match groups.last_mut() {
+                    Some((count, ty)) if ty == local => *count += 1,
+                    _ => groups.push((1, *local)),
+                }
+            }
+            body.extend(vector(&groups, |out, (count, ty)| {
+                unsigned(out, *count as u64);
+                out.push(val_type(ty));
+            }));
+
+            for instr in &func.body {
+                instruction(&mut body, instr, &indices, &addresses);
+            }
+            body.push(0x0b);
+
+            unsigned(out, body.len() as u64);
+            out.extend(body);
+        }),
+    );
+
+    if !module.strings.is_empty() {
+        section(
+            &mut out,
+            11,
+            vector(&[module.data()], |out, data| {
+                out.extend([0x00, 0x41]);
+                signed(out, DATA_BASE as i64);
+                out.push(0x0b);
+                out.extend(vector(data, |out, b| out.push(*b)));
+            }),
+        );
+    }
+
+    // The `name` section, so traps and debuggers show the Gahl symbols.
+    let mut names = vec![];
+    name(&mut names, "name");
+    let functions = module
+        .imports
+        .iter()
+        .map(|i| &i.name)
+        .chain(module.funcs.iter().map(|f| &f.name))
+        .enumerate()
+        .collect::<Vec<_>>();
+    let function_names = vector(&functions, |out, (index, function)| {
+        unsigned(out, *index as u64);
+        name(out, function);
+    });
+    names.push(0x01);
+    unsigned(&mut names, function_names.len() as u64);
+    names.extend(function_names);
+    section(&mut out, 0, names);
+
+    out
+}
+
+fn instruction(
+    out: &mut Vec<u8>,
+    instr: &Instr,
+    indices: &HashMap<&str, u32>,
+    addresses: &HashMap<&str, u32>,
+) {
+    match instr {
+        Instr::Op(name) => {
+            let (code, _, _) = op(name).unwrap();
+            out.push(code);
+
+            // The memory index, always the first.
+            if matches!(*name, "memory.size" | "memory.grow") {
+                out.push(0x00);
+            }
+        }
+        Instr::Mem(name) => {
+            let (code, align, _, _) = mem_op(name).unwrap();
+            out.push(code);
+            unsigned(out, align as u64);
+            unsigned(out, 0);
+        }
+        Instr::I32Const(value) => {
+            out.push(0x41);
+            signed(out, *value as i64);
+        }
+        Instr::I64Const(value) => {
+            out.push(0x42);
+            signed(out, *value);
+        }
+        Instr::F32Const(value) => {
+            out.push(0x43);
+            out.extend(value.to_le_bytes());
+        }
+        Instr::F64Const(value) => {
+            out.push(0x44);
+            out.extend(value.to_le_bytes());
+        }
+        Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) => {
+            out.push(match instr {
+                Instr::LocalGet(_) => 0x20,
+                Instr::LocalSet(_) => 0x21,
+                _ => 0x22,
+            });
+            unsigned(out, *index as u64);
+        }
+        Instr::HeapGet => out.extend([0x23, 0x00]),
+        Instr::HeapSet => out.extend([0x24, 0x00]),
+        Instr::Call(name) => {
+            out.push(0x10);
+            unsigned(out, indices[name.as_str()] as u64);
+        }
+        Instr::Str(s) => {
+            out.push(0x41);
+            signed(out, addresses[s.as_str()] as i64);
+        }
+        Instr::If => out.extend([0x04, 0x40]),
+        Instr::End => out.push(0x0b),
+    }
+}
@@ -0,0 +1,572 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    ast::TypeValue,
+    checker::mdir::{
+        escape::{address_taken, boxed_argument},
+        Expression, Function, Literal, MiddleIR, Statement,
+    },
+};
+
+use super::{
+    super::{signed, type_value_size, BuildOptions},
+    Func, FuncType, Import, Instr, Module, ValType, ALLOC, GAHL_IMPORT, HOST_IMPORT,
+};
+
+fn val_type(type_value: &TypeValue) -> Result<Option<ValType>, String> {
+    Ok(Some(match type_value {
+        TypeValue::Void => return Ok(None),
+        TypeValue::Bool
+        | TypeValue::I8
+        | TypeValue::I16
+        | TypeValue::I32
+        | TypeValue::Isize
+        | TypeValue::U8
+        | TypeValue::U16
+        | TypeValue::U32
+        | TypeValue::Usize
+        | TypeValue::String
+        | TypeValue::Ptr(_) => ValType::I32,
+        TypeValue::I64 | TypeValue::U64 => ValType::I64,
+        TypeValue::F32 => ValType::F32,
+        TypeValue::F64 => ValType::F64,
+        other => return Err(format!("`{other}` has no wasm32 equivalent")),
+    }))
+}
+
+fn func_type(params: &[(String, TypeValue)], return_type: &TypeValue) -> Result<FuncType, String> {
+    Ok(FuncType {
+        params: params
+            .iter()
+            .map(|(_, ty)| val_type(ty)?.ok_or_else(|| "a `void` parameter".to_string()))
+            .collect::<Result<_, _>>()?,
+        results: val_type(return_type)?.into_iter().collect(),
+    })
+}
+
+// Width in bits of the integer types narrower than their wasm value.
+fn narrow_bits(type_value: &TypeValue) -> Option<u32> {
+    match type_value {
+        TypeValue::I8 | TypeValue::U8 => Some(8),
+        TypeValue::I16 | TypeValue::U16 => Some(16),
+        _ => None,
+    }
+}
+
+fn load(type_value: &TypeValue) -> &'static str {
+    match type_value {
+        TypeValue::I64 | TypeValue::U64 => "i64.load",
+        TypeValue::F32 => "f32.load",
+        TypeValue::F64 => "f64.load",
+        TypeValue::I8 => "i32.load8_s",
+        TypeValue::U8 | TypeValue::Bool => "i32.load8_u",
+        TypeValue::I16 => "i32.load16_s",
+        TypeValue::U16 => "i32.load16_u",
+        _ => "i32.load",
+    }
+}
+
+fn store(type_value: &TypeValue) -> &'static str {
+    match type_value {
+        TypeValue::I64 | TypeValue::U64 => "i64.store",
+        TypeValue::F32 => "f32.store",
+        TypeValue::F64 => "f64.store",
+        TypeValue::I8 | TypeValue::U8 | TypeValue::Bool => "i32.store8",
+        TypeValue::I16 | TypeValue::U16 => "i32.store16",
+        _ => "i32.store",
+    }
+}
+
+/// Lowers every function of `mdir`. `extern` functions are imported from the host, functions of
+/// other Gahl modules from `gahl` until `link` finds them.
+pub fn module(mdir: &MiddleIR, options: &BuildOptions) -> Result<Module, String> {
+    let mut module = Module::default();
+
+    for function in mdir.externs() {
+        module.imports.push(Import {
+            module: HOST_IMPORT.to_string(),
+            name: function.name.clone(),
+            ty: func_type(&function.params, &function.return_type)?,
+        });
+    }
+
+    for (symbol, (params, return_type)) in mdir.imported_functions() {
+        module.imports.push(Import {
+            module: GAHL_IMPORT.to_string(),
+            name: symbol.clone(),
+            ty: func_type(params, return_type)?,
+        });
+    }
+
+    let mut helpers = HashMap::new();
+    let mut functions = mdir.functions().values().collect::<Vec<_>>();
+    functions.sort_by_key(|f| f.name.as_str());
+
+    for function in functions {
+        let mut lowering = Lowering::new(function, options, &mut helpers, &mut module.strings)?;
+        lowering.block()?;
+
+        let is_main = function.symbol == "main";
+        let mut func = Func {
+            name: function.symbol.clone(),
+            ty: func_type(&function.params, &function.return_type)?,
+            locals: lowering.locals,
+            body: lowering.body,
+            export: None,
+        };
+
+        // `_start` takes and returns nothing, a `main` with a result gets a wrapper dropping it.
+        if is_main && func.ty.results.is_empty() {
+            func.export = Some("_start".to_string());
+        } else if is_main {
+            module.funcs.push(Func {
+                name: "_start".to_string(),
+                ty: FuncType {
+                    params: vec![],
+                    results: vec![],
+                },
+                locals: vec![],
+                body: vec![Instr::Call(func.name.clone()), Instr::Op("drop")],
+                export: Some("_start".to_string()),
+            });
+        }
+
+        module.funcs.push(func);
+    }
+
+    module.funcs.push(alloc());
+    module.funcs.extend(helpers.into_values());
+
+    Ok(module)
+}
+
+// Bumps the heap pointer by `size` rounded up to 8 and grows memory when it runs past the end.
+fn alloc() -> Func {
+    use Instr::*;
+
+    Func {
+        name: ALLOC.to_string(),
+        ty: FuncType {
+            params: vec![ValType::I32],
+            results: vec![ValType::I32],
+        },
+        locals: vec![ValType::I32],
+        body: vec![
+            HeapGet,
+            LocalSet(1),
+            HeapGet,
+            LocalGet(0),
+            Op("i32.add"),
+            I32Const(7),
+            Op("i32.add"),
+            I32Const(-8),
+            Op("i32.and"),
+            HeapSet,
+            HeapGet,
+            Op("memory.size"),
+            I32Const(16),
+            Op("i32.shl"),
+            Op("i32.gt_u"),
+            If,
+            HeapGet,
+            Op("memory.size"),
+            I32Const(16),
+            Op("i32.shl"),
+            Op("i32.sub"),
+            I32Const(16),
+            Op("i32.shr_u"),
+            I32Const(1),
+            Op("i32.add"),
+            Op("memory.grow"),
+            Op("drop"),
+            End,
+            LocalGet(1),
+        ],
+        export: None,
+    }
+}
+
+// `op` on two values of `type_value` that traps instead of wrapping, for `overflow_checks`.
+fn checked(op: &str, type_value: &TypeValue) -> Func {
+    use Instr::*;
+
+    let is_signed = signed(type_value);
+
+    let (value, body) = match type_value {
+        TypeValue::I64 | TypeValue::U64 => {
+            let mut body = vec![LocalGet(0), LocalGet(1), Op(op64(op)), LocalSet(2)];
+
+            body.extend(match (op, is_signed) {
+                // The sign of the result differs from that of both operands.
+                ("add", true) => vec![
+                    LocalGet(0),
+                    LocalGet(2),
+                    Op("i64.xor"),
+                    LocalGet(1),
+                    LocalGet(2),
+                    Op("i64.xor"),
+                    Op("i64.and"),
+                    I64Const(0),
+                    Op("i64.lt_s"),
+                ],
+                ("sub", true) => vec![
+                    LocalGet(0),
+                    LocalGet(1),
+                    Op("i64.xor"),
+                    LocalGet(0),
+                    LocalGet(2),
+                    Op("i64.xor"),
+                    Op("i64.and"),
+                    I64Const(0),
+                    Op("i64.lt_s"),
+                ],
+                ("add", false) => vec![LocalGet(2), LocalGet(0), Op("i64.lt_u")],
+                ("sub", false) => vec![LocalGet(0), LocalGet(1), Op("i64.lt_u")],
+                // Dividing the product by one operand doesn't give back the other. For signed
+                // operands the only other overflow, `MIN * -1`, traps in the division itself.
+                _ => vec![
+                    LocalGet(0),
+                    Op("i64.eqz"),
+                    Op("i32.eqz"),
+                    If,
+                    LocalGet(2),
+                    LocalGet(0),
+                    Op(if is_signed { "i64.div_s" } else { "i64.div_u" }),
+                    LocalGet(1),
+                    Op("i64.ne"),
+                    If,
+                    Op("unreachable"),
+                    End,
+                    End,
+                    I32Const(0),
+                ],
+            });
+            body.extend([If, Op("unreachable"), End, LocalGet(2)]);
+
+            (ValType::I64, body)
+        }
+        // Computed in 64 bits, the result overflowed when truncating it changes its value.
+        _ => {
+            let extend = match is_signed {
+                true => "i64.extend_i32_s",
+                false => "i64.extend_i32_u",
+            };
+            let mut body = vec![
+                LocalGet(0),
+                Op(extend),
+                LocalGet(1),
+                Op(extend),
+                Op(op64(op)),
+                LocalSet(2),
+                LocalGet(2),
+            ];
+
+            let bits = narrow_bits(type_value).unwrap_or(32);
+            body.extend(match (bits, is_signed) {
+                (8, true) => vec![Op("i64.extend8_s")],
+                (16, true) => vec![Op("i64.extend16_s")],
+                (_, true) => vec![Op("i64.extend32_s")],
+                (bits, false) => vec![I64Const((1i64 << bits) - 1), Op("i64.and")],
+            });
+            body.extend([
+                LocalGet(2),
+                Op("i64.ne"),
+                If,
+                Op("unreachable"),
+                End,
+                LocalGet(2),
+                Op("i32.wrap_i64"),
+            ]);
+
+            (ValType::I32, body)
+        }
+    };
+
+    Func {
+        name: checked_name(op, type_value),
+        ty: FuncType {
+            params: vec![value, value],
+            results: vec![value],
+        },
+        locals: vec![ValType::I64],
+        body,
+        export: None,
+    }
+}
+
+fn op64(op: &str) -> &'static str {
+    match op {
+        "add" => "i64.add",
+        "sub" => "i64.sub",
+        _ => "i64.mul",
+    }
+}
+
+fn checked_name(op: &str, type_value: &TypeValue) -> String {
+    format!("gahl_{op}_{type_value}")
+}
+
+struct Lowering<'f> {
+    function: &'f Function,
+    options: &'f BuildOptions,
+    helpers: &'f mut HashMap<String, Func>,
+    strings: &'f mut Vec<String>,
+    /// Wasm local index of every parameter and local by name.
+    indices: HashMap<String, u32>,
+    /// Locals besides the parameters, temporaries are added as needed.
+    locals: Vec<ValType>,
+    body: Vec<Instr>,
+}
+
+impl<'f> Lowering<'f> {
+    fn new(
+        function: &'f Function,
+        options: &'f BuildOptions,
+        helpers: &'f mut HashMap<String, Func>,
+        strings: &'f mut Vec<String>,
+    ) -> Result<Self, String> {
+        let mut indices = HashMap::new();
+
+        for (index, (name, _)) in function.params.iter().enumerate() {
+            indices.insert(name.clone(), index as u32);
+        }
+
+        let mut lowering = Self {
+            function,
+            options,
+            helpers,
+            strings,
+            indices,
+            locals: vec![],
+            body: vec![],
+        };
+
+        // Escaping locals hold the address of their box.
+        for (name, ty) in &function.vars {
+            if lowering.indices.contains_key(name) {
+                continue;
+            }
+
+            let value = match function.escapes.contains(name) {
+                true => ValType::I32,
+                false => val_type(ty)?.ok_or_else(|| format!("`{name}` is `void`"))?,
+            };
+            let index = lowering.local(value);
+            lowering.indices.insert(name.clone(), index);
+        }
+
+        Ok(lowering)
+    }
+
+    // A new local of type `value`, by index.
+    fn local(&mut self, value: ValType) -> u32 {
+        self.locals.push(value);
+        (self.function.params.len() + self.locals.len() - 1) as u32
+    }
+
+    fn index(&self, name: &str) -> Result<u32, String> {
+        self.indices
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("`{name}` is not a local of `{}`", self.function.name))
+    }
+
+    // Allocates a box for a value of `type_value`, leaves its address on the stack.
+    fn alloc(&mut self, type_value: &TypeValue) {
+        let size = type_value_size(type_value, &self.options.target);
+
+        self.body.push(Instr::I32Const(size as i32));
+        self.body.push(Instr::Call(ALLOC.to_string()));
+    }
+
+    fn block(&mut self) -> Result<(), String> {
+        let function = self.function;
+        let returns = function.return_type != TypeValue::Void;
+
+        for (i, stmt) in function.block.iter().enumerate() {
+            let is_last = i == function.block.len() - 1;
+
+            match stmt {
+                Statement::Expr(expr, _) => {
+                    let value = self.expr(expr)?;
+
+                    if value.is_some() && !(is_last && returns) {
+                        self.body.push(Instr::Op("drop"));
+                    }
+                }
+                Statement::Var(var) => {
+                    let index = self.index(&var.lhs)?;
+
+                    if function.escapes.contains(&var.lhs) {
+                        self.alloc(&var.ty);
+                        self.body.push(Instr::LocalTee(index));
+                        self.expr(&var.rhs)?;
+                        self.body.push(Instr::Mem(store(&var.ty)));
+                    } else {
+                        self.expr(&var.rhs)?;
+                        self.body.push(Instr::LocalSet(index));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Pushes the value of the postfix `expr`, returns its type or nothing for a `void` call.
+    fn expr(&mut self, expr: &VecDeque<Expression>) -> Result<Option<TypeValue>, String> {
+        let mut stack: Vec<TypeValue> = vec![];
+
+        for e in expr {
+            match e {
+                Expression::Literal(literal) => {
+                    self.literal(literal)?;
+
+                    if literal._type() != &TypeValue::Void {
+                        stack.push(literal._type().clone());
+                    }
+                }
+                op if op.is_op() => {
+                    let (Some(_), Some(ty)) = (stack.pop(), stack.pop()) else {
+                        return Err(format!("`{op}` is missing an operand"));
+                    };
+                    self.binary_op(op, &ty)?;
+                    stack.push(ty);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(stack.pop())
+    }
+
+    fn literal(&mut self, literal: &Literal) -> Result<(), String> {
+        match literal {
+            Literal::Int(ty, value) => {
+                let constant = match val_type(ty)? {
+                    Some(ValType::F32) => Instr::F32Const(value.parse().map_err(|_| bad(value))?),
+                    Some(ValType::F64) => Instr::F64Const(value.parse().map_err(|_| bad(value))?),
+                    // Both signed and unsigned literals keep their bits.
+                    Some(ValType::I64) => {
+                        Instr::I64Const(value.parse::<i128>().map_err(|_| bad(value))? as i64)
+                    }
+                    _ => Instr::I32Const(value.parse::<i64>().map_err(|_| bad(value))? as i32),
+                };
+                self.body.push(constant);
+            }
+            Literal::Identifier(_, name, true) => {
+                let index = self.index(name)?;
+                self.body.push(Instr::LocalGet(index));
+            }
+            Literal::Identifier(ty, name, false) => {
+                let index = self.index(name)?;
+                self.body.push(Instr::LocalGet(index));
+
+                if self.function.escapes.contains(name) {
+                    self.body.push(Instr::Mem(load(ty)));
+                }
+            }
+            Literal::String(string) => {
+                let inner = string[1..string.len() - 1].to_string();
+                if !self.strings.contains(&inner) {
+                    self.strings.push(inner.clone());
+                }
+
+                self.body.push(Instr::Str(inner));
+            }
+            Literal::Call(_, symbol, args) => {
+                for (arg, param_type) in args {
+                    // Escaping locals already are the address of their box.
+                    if let Some(name) = address_taken(arg, param_type) {
+                        let index = self.index(name)?;
+                        self.body.push(Instr::LocalGet(index));
+                        continue;
+                    }
+
+                    self.expr(arg)?;
+
+                    if let Some(arg_type) = boxed_argument(arg, param_type) {
+                        let value_type = val_type(&arg_type)?
+                            .ok_or_else(|| format!("`void` passed to `{symbol}`"))?;
+                        let value = self.local(value_type);
+                        let address = self.local(ValType::I32);
+
+                        self.body.push(Instr::LocalSet(value));
+                        self.alloc(&arg_type);
+                        self.body.push(Instr::LocalTee(address));
+                        self.body.push(Instr::LocalGet(value));
+                        self.body.push(Instr::Mem(store(&arg_type)));
+                        self.body.push(Instr::LocalGet(address));
+                    }
+                }
+
+                self.body.push(Instr::Call(symbol.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn binary_op(&mut self, op: &Expression, type_value: &TypeValue) -> Result<(), String> {
+        let value = val_type(type_value)?.ok_or_else(|| format!("`{op}` on `void`"))?;
+        let is_signed = signed(type_value);
+
+        let name = match op {
+            Expression::Add => "add",
+            Expression::Min => "sub",
+            Expression::Mul => "mul",
+            Expression::Div => "div",
+            op => return Err(format!("`{op}` is not implemented yet")),
+        };
+
+        let is_float = matches!(value, ValType::F32 | ValType::F64);
+        if name != "div" && !is_float && self.options.profile.overflow_checks {
+            let helper = checked_name(name, type_value);
+            self.helpers
+                .entry(helper.clone())
+                .or_insert_with(|| checked(name, type_value));
+            self.body.push(Instr::Call(helper));
+
+            return Ok(());
+        }
+
+        let instr = match (value, name) {
+            (ValType::I32, "add") => "i32.add",
+            (ValType::I32, "sub") => "i32.sub",
+            (ValType::I32, "mul") => "i32.mul",
+            (ValType::I32, _) if is_signed => "i32.div_s",
+            (ValType::I32, _) => "i32.div_u",
+            (ValType::I64, "add") => "i64.add",
+            (ValType::I64, "sub") => "i64.sub",
+            (ValType::I64, "mul") => "i64.mul",
+            (ValType::I64, _) if is_signed => "i64.div_s",
+            (ValType::I64, _) => "i64.div_u",
+            (ValType::F32, "add") => "f32.add",
+            (ValType::F32, "sub") => "f32.sub",
+            (ValType::F32, "mul") => "f32.mul",
+            (ValType::F32, _) => "f32.div",
+            (ValType::F64, "add") => "f64.add",
+            (ValType::F64, "sub") => "f64.sub",
+            (ValType::F64, "mul") => "f64.mul",
+            (ValType::F64, _) => "f64.div",
+        };
+        self.body.push(Instr::Op(instr));
+
+        // Narrow integers are kept sign or zero extended to 32 bits, wrap the result back.
+        match (narrow_bits(type_value), is_signed) {
+            (Some(8), true) => self.body.push(Instr::Op("i32.extend8_s")),
+            (Some(16), true) => self.body.push(Instr::Op("i32.extend16_s")),
+            (Some(bits), false) => {
+                self.body.push(Instr::I32Const((1 << bits) - 1));
+                self.body.push(Instr::Op("i32.and"));
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+fn bad(value: &str) -> String {
+    format!("`{value}` is not a valid literal")
+}
@@ -0,0 +1,376 @@
+use std::{collections::HashMap, fs};
+
+use crate::{checker::mdir::MiddleIR, cli::Emit};
+
+use super::{artifact_path, clang::BuildError, BuildOptions};
+
+pub mod encode;
+pub mod lower;
+pub mod text;
+pub mod validate;
+
+/// Where string literals start in linear memory, everything below stays unused so a null pointer
+/// never points at data.
+pub const DATA_BASE: u32 = 1024;
+const PAGE_SIZE: u32 = 65536;
+
+/// The bump allocator every module carries, escaping locals are boxed through it.
+pub const ALLOC: &str = "gahl_alloc";
+/// Module name of the imports another Gahl module provides, the linker resolves them.
+pub const GAHL_IMPORT: &str = "gahl";
+/// Module name `extern` functions are imported from, the host implements them.
+pub const HOST_IMPORT: &str = "env";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    fn from_char(c: char) -> Self {
+        match c {
+            'i' => ValType::I32,
+            'I' => ValType::I64,
+            'f' => ValType::F32,
+            _ => ValType::F64,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// An instruction without immediates, one of `OPS`.
+    Op(&'static str),
+    /// A load or store, one of `MEM_OPS`.
+    Mem(&'static str),
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    /// The heap pointer is the only global.
+    HeapGet,
+    HeapSet,
+    Call(String),
+    /// Address of the nul terminated string literal, placed by the linker.
+    Str(String),
+    /// `if` without results, closed by `End`.
+    If,
+    End,
+}
+
+#[derive(Debug, Clone)]
+pub struct Func {
+    pub name: String,
+    pub ty: FuncType,
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+    pub export: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: FuncType,
+}
+
+/// A wasm module before addresses and indices are assigned, calls refer to functions by name.
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub funcs: Vec<Func>,
+    /// String literals in the order they are laid out.
+    pub strings: Vec<String>,
+}
+
+// Name, opcode, operand types and result types, `i`/`I`/`f`/`F` being i32/i64/f32/f64.
+pub const OPS: [(&str, u8, &str, &str); 43] = [
+    ("unreachable", 0x00, "", ""),
+    ("drop", 0x1a, "", ""),
+    ("memory.size", 0x3f, "", "i"),
+    ("memory.grow", 0x40, "i", "i"),
+    ("i32.eqz", 0x45, "i", "i"),
+    ("i32.ne", 0x47, "ii", "i"),
+    ("i32.lt_u", 0x49, "ii", "i"),
+    ("i32.gt_u", 0x4b, "ii", "i"),
+    ("i64.eqz", 0x50, "I", "i"),
+    ("i64.ne", 0x52, "II", "i"),
+    ("i64.lt_s", 0x53, "II", "i"),
+    ("i64.lt_u", 0x54, "II", "i"),
+    ("i32.add", 0x6a, "ii", "i"),
+    ("i32.sub", 0x6b, "ii", "i"),
+    ("i32.mul", 0x6c, "ii", "i"),
+    ("i32.div_s", 0x6d, "ii", "i"),
+    ("i32.div_u", 0x6e, "ii", "i"),
+    ("i32.and", 0x71, "ii", "i"),
+    ("i32.shl", 0x74, "ii", "i"),
+    ("i32.shr_u", 0x76, "ii", "i"),
+    ("i64.add", 0x7c, "II", "I"),
+    ("i64.sub", 0x7d, "II", "I"),
+    ("i64.mul", 0x7e, "II", "I"),
+    ("i64.div_s", 0x7f, "II", "I"),
+    ("i64.div_u", 0x80, "II", "I"),
+    ("i64.and", 0x83, "II", "I"),
+    ("i64.xor", 0x85, "II", "I"),
+    ("f32.add", 0x92, "ff", "f"),
+    ("f32.sub", 0x93, "ff", "f"),
+    ("f32.mul", 0x94, "ff", "f"),
+    ("f32.div", 0x95, "ff", "f"),
+    ("f64.add", 0xa0, "FF", "F"),
+    ("f64.sub", 0xa1, "FF", "F"),
+    ("f64.mul", 0xa2, "FF", "F"),
+    ("f64.div", 0xa3, "FF", "F"),
+    ("i32.wrap_i64", 0xa7, "I", "i"),
+    ("i64.extend_i32_s", 0xac, "i", "I"),
+    ("i64.extend_i32_u", 0xad, "i", "I"),
+    ("i32.extend8_s", 0xc0, "i", "i"),
+    ("i32.extend16_s", 0xc1, "i", "i"),
+    ("i64.extend8_s", 0xc2, "I", "I"),
+    ("i64.extend16_s", 0xc3, "I", "I"),
+    ("i64.extend32_s", 0xc4, "I", "I"),
+];
+
+// Loads and stores with their natural alignment (log2), the types are written `operands>results`.
+pub const MEM_OPS: [(&str, u8, &str, u32); 14] = [
+    ("i32.load", 0x28, "i>i", 2),
+    ("i64.load", 0x29, "i>I", 3),
+    ("f32.load", 0x2a, "i>f", 2),
+    ("f64.load", 0x2b, "i>F", 3),
+    ("i32.load8_s", 0x2c, "i>i", 0),
+    ("i32.load8_u", 0x2d, "i>i", 0),
+    ("i32.load16_s", 0x2e, "i>i", 1),
+    ("i32.load16_u", 0x2f, "i>i", 1),
+    ("i32.store", 0x36, "ii>", 2),
+    ("i64.store", 0x37, "iI>", 3),
+    ("f32.store", 0x38, "if>", 2),
+    ("f64.store", 0x39, "iF>", 3),
+    ("i32.store8", 0x3a, "ii>", 0),
+    ("i32.store16", 0x3b, "ii>", 1),
+];
+
+/// Opcode, operand and result types of an instruction without immediates.
+pub fn op(name: &str) -> Option<(u8, Vec<ValType>, Vec<ValType>)> {
+    OPS.iter()
+        .find(|(n, ..)| *n == name)
+        .map(|(_, code, pops, pushes)| {
+            (
+                *code,
+                pops.chars().map(ValType::from_char).collect(),
+                pushes.chars().map(ValType::from_char).collect(),
+            )
+        })
+}
+
+/// Opcode, alignment, operand and result types of a load or store.
+pub fn mem_op(name: &str) -> Option<(u8, u32, Vec<ValType>, Vec<ValType>)> {
+    MEM_OPS
+        .iter()
+        .find(|(n, ..)| *n == name)
+        .map(|(_, code, sig, align)| {
+            let (pops, pushes) = sig.split_once('>').unwrap_or_default();
+            (
+                *code,
+                *align,
+                pops.chars().map(ValType::from_char).collect(),
+                pushes.chars().map(ValType::from_char).collect(),
+            )
+        })
+}
+
+impl Module {
+    /// Address of every string literal and where the heap starts after them.
+    pub fn layout(&self) -> (HashMap<&str, u32>, u32) {
+        let mut addresses = HashMap::new();
+        let mut next = DATA_BASE;
+
+        for string in &self.strings {
+            addresses.insert(string.as_str(), next);
+            next += string.len() as u32 + 1;
+        }
+
+        (addresses, (next + 7) & !7)
+    }
+
+    /// The initial data segment, every literal followed by its terminating nul.
+    pub fn data(&self) -> Vec<u8> {
+        self.strings
+            .iter()
+            .flat_map(|s| s.bytes().chain(std::iter::once(0)))
+            .collect()
+    }
+
+    /// Pages of linear memory to start with, enough for the data and the first allocations.
+    pub fn pages(&self) -> u32 {
+        let (_, heap_base) = self.layout();
+        heap_base / PAGE_SIZE + 1
+    }
+
+    /// Index of every function by name, imports come first.
+    pub fn function_indices(&self) -> HashMap<&str, u32> {
+        self.imports
+            .iter()
+            .map(|i| i.name.as_str())
+            .chain(self.funcs.iter().map(|f| f.name.as_str()))
+            .enumerate()
+            .map(|(index, name)| (name, index as u32))
+            .collect()
+    }
+
+    /// Signature of the function or import called `name`.
+    pub fn signature(&self, name: &str) -> Option<&FuncType> {
+        self.imports
+            .iter()
+            .find(|i| i.name == name)
+            .map(|i| &i.ty)
+            .or_else(|| self.funcs.iter().find(|f| f.name == name).map(|f| &f.ty))
+    }
+}
+
+/// Merges lowered modules into one. Imports of functions another of them defines become direct
+/// calls, what's left is imported from the host (or from Gahl modules not given).
+pub fn link(modules: Vec<Module>) -> Module {
+    let mut linked = Module::default();
+
+    for module in modules {
+        for func in module.funcs {
+            // Runtime helpers are in every module that uses them.
+            if linked.funcs.iter().all(|f| f.name != func.name) {
+                linked.funcs.push(func);
+            }
+        }
+
+        for import in module.imports {
+            if !linked.imports.contains(&import) {
+                linked.imports.push(import);
+            }
+        }
+
+        for string in module.strings {
+            if !linked.strings.contains(&string) {
+                linked.strings.push(string);
+            }
+        }
+    }
+
+    let defined = linked
+        .funcs
+        .iter()
+        .map(|f| f.name.clone())
+        .collect::<Vec<_>>();
+    linked.imports.retain(|i| !defined.contains(&i.name));
+    linked
+        .imports
+        .sort_by(|a, b| (&a.module, &a.name).cmp(&(&b.module, &b.name)));
+    linked.funcs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    linked
+}
+
+/// Builds every module for wasm32: `asm` writes `build/<module>.wat` and `obj` a
+/// `build/<module>.wasm` of its own, `exe` links them all into `build/<project>.wasm`.
+pub fn compile(
+    modules: HashMap<String, MiddleIR>,
+    options: &BuildOptions,
+    project_name: &str,
+) -> Result<(), Vec<BuildError>> {
+    let write = |path: String, contents: &[u8]| {
+        fs::write(&path, contents).map_err(|error| BuildError::Io {
+            path,
+            error: error.to_string(),
+        })
+    };
+
+    let mut lowered = vec![];
+    let mut errors = vec![];
+
+    let mut names = modules.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+
+    for name in names {
+        let mdir = &modules[&name];
+        let module = match lower::module(mdir, options) {
+            Ok(module) => link(vec![module]),
+            Err(message) => {
+                errors.push(BuildError::Lowering {
+                    module: name,
+                    message,
+                });
+                continue;
+            }
+        };
+
+        if let Err(message) = validate::module(&module) {
+            errors.push(BuildError::Lowering {
+                module: name,
+                message,
+            });
+            continue;
+        }
+
+        if options.emits(Emit::Asm) {
+            if let Err(error) = write(
+                artifact_path(&name, "wat"),
+                text::module(&module).as_bytes(),
+            ) {
+                errors.push(error);
+            }
+        }
+        if options.emits(Emit::Obj) {
+            if let Err(error) = write(artifact_path(&name, "wasm"), &encode::module(&module)) {
+                errors.push(error);
+            }
+        }
+
+        lowered.push(module);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    if !options.emits(Emit::Exe) {
+        return Ok(());
+    }
+
+    let program = link(lowered);
+    if let Some(import) = program.imports.iter().find(|i| i.module == GAHL_IMPORT) {
+        return Err(vec![BuildError::Lowering {
+            module: project_name.to_string(),
+            message: format!("`{}` is imported but never defined", import.name),
+        }]);
+    }
+
+    validate::module(&program).map_err(|message| {
+        vec![BuildError::Lowering {
+            module: project_name.to_string(),
+            message,
+        }]
+    })?;
+
+    write(
+        format!("build/{project_name}.wasm"),
+        &encode::module(&program),
+    )
+    .map_err(|e| vec![e])
+}
@@ -0,0 +1,122 @@
+use std::fmt::Write;
+
+use super::{FuncType, Instr, Module, ValType, DATA_BASE};
+
+fn signature(ty: &FuncType) -> String {
+    let mut result = String::new();
+
+    for param in &ty.params {
+        let _ = write!(result, " (param {})", param.name());
+    }
+    for value in &ty.results {
+        let _ = write!(result, " (result {})", value.name());
+    }
+
+    result
+}
+
+// A wat string, bytes outside printable ASCII and the delimiters are written as hex escapes.
+fn string(bytes: &[u8]) -> String {
+    let mut result = String::from("\"");
+
+    for b in bytes {
+        match b {
+            0x20..=0x7e if *b != b'"' && *b != b'\\' => result.push(*b as char),
+            _ => {
+                let _ = write!(result, "\\{:02x}", b);
+            }
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+/// The WebAssembly text format of `module`, for `--emit=asm`.
+pub fn module(module: &Module) -> String {
+    let (addresses, heap_base) = module.layout();
+    let mut result = String::from("(module\n");
+
+    for import in &module.imports {
+        let _ = writeln!(
+            result,
+            "  (import \"{}\" \"{}\" (func ${}{}))",
+            import.module,
+            import.name,
+            import.name,
+            signature(&import.ty)
+        );
+    }
+
+    let _ = writeln!(result, "  (memory (export \"memory\") {})", module.pages());
+    let _ = writeln!(result, "  (global $heap (mut i32) (i32.const {heap_base}))");
+    if !module.strings.is_empty() {
+        let _ = writeln!(
+            result,
+            "  (data (i32.const {}) {})",
+            DATA_BASE,
+            string(&module.data())
+        );
+    }
+
+    for func in &module.funcs {
+        let export = match &func.export {
+            Some(name) => format!(" (export \"{name}\")"),
+            None => String::new(),
+        };
+        let _ = write!(
+            result,
+            "\n  (func ${}{export}{}",
+            func.name,
+            signature(&func.ty)
+        );
+        if !func.locals.is_empty() {
+            let locals = func
+                .locals
+                .iter()
+                .map(ValType::name)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = write!(result, " (local {locals})");
+        }
+        result += "\n";
+
+        let mut depth = 2;
+        for instr in &func.body {
+            if *instr == Instr::End {
+                depth -= 1;
+            }
+
+            let text = match instr {
+                Instr::Op(name) | Instr::Mem(name) => name.to_string(),
+                Instr::I32Const(value) => format!("i32.const {value}"),
+                Instr::I64Const(value) => format!("i64.const {value}"),
+                Instr::F32Const(value) => format!("f32.const {value:?}"),
+                Instr::F64Const(value) => format!("f64.const {value:?}"),
+                Instr::LocalGet(index) => format!("local.get {index}"),
+                Instr::LocalSet(index) => format!("local.set {index}"),
+                Instr::LocalTee(index) => format!("local.tee {index}"),
+                Instr::HeapGet => "global.get $heap".to_string(),
+                Instr::HeapSet => "global.set $heap".to_string(),
+                Instr::Call(name) => format!("call ${name}"),
+                Instr::Str(s) => format!(
+                    "i32.const {} ;; {}",
+                    addresses[s.as_str()],
+                    string(s.as_bytes())
+                ),
+                Instr::If => "if".to_string(),
+                Instr::End => "end".to_string(),
+            };
+            let _ = writeln!(result, "{}{text}", "  ".repeat(depth));
+
+            if *instr == Instr::If {
+                depth += 1;
+            }
+        }
+        result += "  )\n";
+    }
+
+    result += ")\n";
+
+    result
+}
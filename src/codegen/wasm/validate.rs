@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+
+use super::{mem_op, op, Func, Instr, Module, ValType};
+
+/// Checks the structure of a module before it is written: names are unique, calls and locals
+/// resolve, and every instruction finds operands of the right type on the stack.
+pub fn module(module: &Module) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for name in module
+        .imports
+        .iter()
+        .map(|i| &i.name)
+        .chain(module.funcs.iter().map(|f| &f.name))
+    {
+        if !names.insert(name) {
+            return Err(format!("wasm function `{name}` is defined twice"));
+        }
+    }
+
+    let mut exports = HashSet::new();
+    for export in module.funcs.iter().filter_map(|f| f.export.as_ref()) {
+        if !exports.insert(export) {
+            return Err(format!("wasm export `{export}` is defined twice"));
+        }
+    }
+
+    for func in &module.funcs {
+        function(module, func)
+            .map_err(|error| format!("invalid wasm in `{}`: {error}", func.name))?;
+    }
+
+    Ok(())
+}
+
+// An `if` or the function body, with the stack height it started at.
+struct Frame {
+    height: usize,
+    /// Past an `unreachable`, anything may be popped.
+    unreachable: bool,
+}
+
+fn function(module: &Module, func: &Func) -> Result<(), String> {
+    let locals = func
+        .ty
+        .params
+        .iter()
+        .chain(&func.locals)
+        .copied()
+        .collect::<Vec<_>>();
+    let local = |index: u32| {
+        locals
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| format!("local {index} doesn't exist"))
+    };
+
+    let mut stack: Vec<ValType> = vec![];
+    let mut frames = vec![Frame {
+        height: 0,
+        unreachable: false,
+    }];
+
+    let pop = |stack: &mut Vec<ValType>, frames: &[Frame], expected: ValType| {
+        let frame = frames.last().unwrap();
+
+        if stack.len() > frame.height {
+            let found = stack.pop().unwrap();
+            if found != expected {
+                return Err(format!(
+                    "expected {} on the stack, found {}",
+                    expected.name(),
+                    found.name()
+                ));
+            }
+            Ok(())
+        } else if frame.unreachable {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected {} on the stack, found nothing",
+                expected.name()
+            ))
+        }
+    };
+
+    for instr in &func.body {
+        let (pops, pushes) = match instr {
+            Instr::Op(name) => {
+                let (_, pops, pushes) = op(name).ok_or_else(|| format!("unknown `{name}`"))?;
+
+                if *name == "drop" {
+                    let frame = frames.last().unwrap();
+                    if stack.len() > frame.height {
+                        stack.pop();
+                    } else if !frame.unreachable {
+                        return Err("`drop` on an empty stack".to_string());
+                    }
+                }
+                if *name == "unreachable" {
+                    frames.last_mut().unwrap().unreachable = true;
+                }
+
+                (pops, pushes)
+            }
+            Instr::Mem(name) => {
+                let (_, _, pops, pushes) =
+                    mem_op(name).ok_or_else(|| format!("unknown `{name}`"))?;
+                (pops, pushes)
+            }
+            Instr::I32Const(_) | Instr::Str(_) | Instr::HeapGet => (vec![], vec![ValType::I32]),
+            Instr::I64Const(_) => (vec![], vec![ValType::I64]),
+            Instr::F32Const(_) => (vec![], vec![ValType::F32]),
+            Instr::F64Const(_) => (vec![], vec![ValType::F64]),
+            Instr::HeapSet => (vec![ValType::I32], vec![]),
+            Instr::LocalGet(index) => (vec![], vec![local(*index)?]),
+            Instr::LocalSet(index) => (vec![local(*index)?], vec![]),
+            Instr::LocalTee(index) => (vec![local(*index)?], vec![local(*index)?]),
+            Instr::Call(name) => {
+                let ty = module
+                    .signature(name)
+                    .ok_or_else(|| format!("call to unknown function `{name}`"))?;
+                (ty.params.clone(), ty.results.clone())
+            }
+            Instr::If => {
+                pop(&mut stack, &frames, ValType::I32)?;
+                frames.push(Frame {
+                    height: stack.len(),
+                    unreachable: false,
+                });
+                continue;
+            }
+            Instr::End => {
+                if frames.len() == 1 {
+                    return Err("`end` without `if`".to_string());
+                }
+
+                let frame = frames.pop().unwrap();
+                if stack.len() != frame.height && !frame.unreachable {
+                    return Err(format!(
+                        "`if` leaves {} values on the stack",
+                        stack.len() - frame.height
+                    ));
+                }
+                stack.truncate(frame.height);
+                continue;
+            }
+        };
+
+        for expected in pops.iter().rev() {
+            pop(&mut stack, &frames, *expected)?;
+        }
+        stack.extend(pushes);
+    }
+
+    if frames.len() != 1 {
+        return Err("`if` without `end`".to_string());
+    }
+
+    let frame = &frames[0];
+    let results = &func.ty.results;
+    let fits = match frame.unreachable {
+        true => stack.len() <= results.len() && results.ends_with(&stack),
+        false => &stack == results,
+    };
+
+    if !fits {
+        let names = |types: &[ValType]| {
+            types
+                .iter()
+                .map(ValType::name)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        return Err(format!(
+            "ends with [{}] on the stack, expected [{}]",
+            names(&stack),
+            names(results)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::{
+        ast::{Location, TypeValue},
+        checker::mdir::{Expression, ExternFunction, Function, Literal, MiddleIR, Statement},
+        cli::{Backend, Emit},
+        codegen::{
+            target::Target,
+            wasm::{encode, link, lower, Instr},
+            BuildOptions,
+        },
+        config::{MemoryMode, Profile},
+    };
+
+    fn function(name: &str, params: &[&str], block: VecDeque<Expression>) -> Function {
+        let mut function = Function::default();
+        function.name = name.to_string();
+        function.symbol = name.to_string();
+        function.params = params
+            .iter()
+            .map(|p| (p.to_string(), TypeValue::I32))
+            .collect();
+        function.return_type = TypeValue::I32;
+        function.block = vec![Statement::Expr(block, Location::default())];
+        function
+    }
+
+    // `host_add : extern fn(a: i32, b: i32) i32`, `twice(n)` is `n * 2` and `main` returns
+    // `host_add(twice(3), 4)`.
+    fn program() -> MiddleIR {
+        let int = |n: &str| Expression::Literal(Literal::Int(TypeValue::I32, n.to_string()));
+        let arg = |e: Vec<Expression>| (VecDeque::from(e), TypeValue::I32);
+
+        let mut mdir = MiddleIR::new();
+        mdir.set_externs(vec![ExternFunction {
+            name: "host_add".to_string(),
+            params: vec![
+                ("a".to_string(), TypeValue::I32),
+                ("b".to_string(), TypeValue::I32),
+            ],
+            return_type: Box::new(TypeValue::I32),
+        }]);
+
+        let n = Expression::Literal(Literal::Identifier(TypeValue::I32, "n".to_string(), true));
+        mdir.insert_function(function(
+            "twice",
+            &["n"],
+            VecDeque::from([n, int("2"), Expression::Mul]),
+        ));
+
+        let twice = Literal::Call(
+            TypeValue::I32,
+            "twice".to_string(),
+            vec![arg(vec![int("3")])],
+        );
+        let call = Literal::Call(
+            TypeValue::I32,
+            "host_add".to_string(),
+            vec![arg(vec![Expression::Literal(twice)]), arg(vec![int("4")])],
+        );
+        mdir.insert_function(function(
+            "main",
+            &[],
+            VecDeque::from([Expression::Literal(call)]),
+        ));
+
+        mdir
+    }
+
+    fn lowered() -> super::Module {
+        let options = BuildOptions {
+            profile: Profile::dev(MemoryMode::Arena),
+            target: Target::from_triple("wasm32").unwrap(),
+            emit: vec![Emit::Exe],
+            jobs: 1,
+            backend: Backend::Clang,
+        };
+
+        link(vec![lower::module(&program(), &options).unwrap()])
+    }
+
+    fn leb(bytes: &[u8], at: &mut usize) -> usize {
+        let (mut value, mut shift) = (0, 0);
+        loop {
+            let byte = bytes[*at];
+            *at += 1;
+            value |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn name(bytes: &[u8], at: &mut usize) -> String {
+        let len = leb(bytes, at);
+        *at += len;
+        String::from_utf8(bytes[*at - len..*at].to_vec()).unwrap()
+    }
+
+    // Contents of the section `id` of an encoded module.
+    fn section(bytes: &[u8], id: u8) -> &[u8] {
+        let mut at = 8;
+        while at < bytes.len() {
+            let section = bytes[at];
+            at += 1;
+            let len = leb(bytes, &mut at);
+
+            if section == id {
+                return &bytes[at..at + len];
+            }
+            at += len;
+        }
+
+        panic!("no section {id}")
+    }
+
+    #[test]
+    fn accepts_lowered_program() {
+        let module = lowered();
+        assert_eq!(super::module(&module), Ok(()));
+
+        let bytes = encode::module(&module);
+        assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+
+        // (module, name) of every import, each followed by its kind and type.
+        let imports = section(&bytes, 2);
+        let mut at = 0;
+        let found = (0..leb(imports, &mut at))
+            .map(|_| {
+                let import = (name(imports, &mut at), name(imports, &mut at));
+                at += 1;
+                leb(imports, &mut at);
+                import
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(found, [("env".to_string(), "host_add".to_string())]);
+
+        let exports = section(&bytes, 7);
+        let mut at = 0;
+        let found = (0..leb(exports, &mut at))
+            .map(|_| {
+                let export = name(exports, &mut at);
+                at += 1;
+                leb(exports, &mut at);
+                export
+            })
+            .collect::<Vec<_>>();
+        assert!(found.contains(&"_start".to_string()));
+        assert!(found.contains(&"memory".to_string()));
+    }
+
+    #[test]
+    fn rejects_corrupted_program() {
+        let mut module = lowered();
+        let twice = module.funcs.iter_mut().find(|f| f.name == "twice").unwrap();
+        let constant = twice
+            .body
+            .iter_mut()
+            .find(|instr| **instr == Instr::I32Const(2))
+            .unwrap();
+        *constant = Instr::I64Const(2);
+
+        assert!(super::module(&module).is_err());
+
+        let mut module = lowered();
+        let main = module.funcs.iter_mut().find(|f| f.name == "main").unwrap();
+        main.body.push(Instr::Call("missing".to_string()));
+
+        assert!(super::module(&module).is_err());
+    }
+}
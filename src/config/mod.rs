@@ -1,11 +1,14 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use toml::de;
+use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub project: Project,
     pub clibs: Option<Clibs>,
+    #[serde(default)]
+    pub build: Build,
+    #[serde(default)]
+    pub profile: Profiles,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,6 +16,117 @@ pub struct Project {
     pub name: String,
     pub author: String,
     pub exec_entry: String,
+    /// Extra directories searched for imports, relative to the project root.
+    #[serde(default)]
+    pub search_paths: Vec<String>,
+    /// Overrides the bundled standard library.
+    pub std_path: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Build {
+    /// Where the values the compiler puts on the heap are allocated.
+    #[serde(default)]
+    pub memory: MemoryMode,
+    /// Target triple, the host when not set.
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryMode {
+    /// Boehm GC, links `libgc`.
+    #[default]
+    Gc,
+    /// The bundled bump allocator, everything is released when the program exits.
+    Arena,
+    /// Plain `malloc`, the program releases what it allocates itself.
+    Manual,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    pub dev: ProfileConfig,
+    #[serde(default)]
+    pub release: ProfileConfig,
+}
+
+/// A `[profile.*]` section, whatever is left out keeps the default of the profile.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub opt_level: Option<u8>,
+    pub debug: Option<bool>,
+    pub overflow_checks: Option<bool>,
+    /// Overrides `memory` under `[build]`.
+    pub memory: Option<MemoryMode>,
+}
+
+/// The settings a build ends up with.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub opt_level: u8,
+    pub debug: bool,
+    pub overflow_checks: bool,
+    pub memory: MemoryMode,
+}
+
+impl Profile {
+    pub fn dev(memory: MemoryMode) -> Self {
+        Self {
+            opt_level: 0,
+            debug: true,
+            overflow_checks: true,
+            memory,
+        }
+    }
+
+    pub fn release(memory: MemoryMode) -> Self {
+        Self {
+            opt_level: 3,
+            debug: false,
+            overflow_checks: false,
+            memory,
+        }
+    }
+
+    /// The profile selected by `release` with the overrides from `config.toml` applied.
+    pub fn resolve(config: &Config, release: bool) -> Result<Self, String> {
+        let (name, mut profile, overrides) = if release {
+            (
+                "release",
+                Self::release(config.build.memory),
+                &config.profile.release,
+            )
+        } else {
+            ("dev", Self::dev(config.build.memory), &config.profile.dev)
+        };
+
+        if let Some(opt_level) = overrides.opt_level {
+            if opt_level > 3 {
+                return Err(format!(
+                    "`opt_level` in `[profile.{name}]` has to be between 0 and 3, found {opt_level}"
+                ));
+            }
+            profile.opt_level = opt_level;
+        }
+        profile.debug = overrides.debug.unwrap_or(profile.debug);
+        profile.overflow_checks = overrides.overflow_checks.unwrap_or(profile.overflow_checks);
+        profile.memory = overrides.memory.unwrap_or(profile.memory);
+
+        Ok(profile)
+    }
+
+    /// Flags for every clang invocation, compiling and linking alike.
+    pub fn clang_flags(&self) -> Vec<String> {
+        let mut flags = vec![format!("-O{}", self.opt_level)];
+
+        if self.debug {
+            flags.push("-g".to_string());
+        }
+
+        flags
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,6 +141,21 @@ pub struct Clib {
     pub flags: Vec<String>,
 }
 
+/// The closest directory at or above the current one containing a `config.toml`.
+pub fn find_project_root() -> Result<PathBuf, String> {
+    let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+
+    cwd.ancestors()
+        .find(|dir| dir.join("config.toml").is_file())
+        .map(|dir| dir.to_path_buf())
+        .ok_or_else(|| {
+            format!(
+                "No `config.toml` found in `{}` or any parent directory",
+                cwd.display()
+            )
+        })
+}
+
 pub fn parse_config() -> Result<Config, String> {
     let file = match std::fs::read_to_string("config.toml") {
         Ok(f) => f,
@@ -1,22 +1,418 @@
-use crate::checker::mdir::MiddleIR;
+use std::{collections::HashMap, fmt::Write};
 
-pub fn gen_docs(mdir: MiddleIR) -> String {
-    let mut result = String::new();
+use crate::ast::{DocComment, ImportKey, Module, Visibility};
 
-    mdir.functions().iter().for_each(|(_, f)| {
-        result += &f.name;
-        result += "(";
-        for (name, ty) in &f.params {
-            result += &format!("{name} : {:?}", ty)
+/// A generated file, relative to the documentation directory.
+pub struct Page {
+    pub file: String,
+    pub contents: String,
+}
+
+/// Where a name in a module's docs points to: a page of the site and an item on it.
+#[derive(Clone)]
+struct Link {
+    /// Documented name of the module, `None` for the current page.
+    module: Option<String>,
+    anchor: Option<String>,
+}
+
+impl Link {
+    fn href(&self, extension: &str) -> String {
+        let page = match &self.module {
+            Some(module) => format!("{module}.{extension}"),
+            None => String::new(),
+        };
+
+        match &self.anchor {
+            Some(anchor) => format!("{page}#{anchor}"),
+            None => page,
+        }
+    }
+}
+
+/// Name a module is documented under, its import path.
+fn doc_name(module: &Module) -> String {
+    match module.path.is_empty() {
+        true => module.name.clone(),
+        false => module.path.join("."),
+    }
+}
+
+/// A public function as it's documented.
+struct Item<'m> {
+    name: &'m str,
+    /// The declaration and the head of the definition, in Gahl syntax.
+    signature: String,
+    docs: Vec<&'m DocComment>,
+}
+
+fn items(module: &Module) -> Vec<Item<'_>> {
+    let mut decls = module
+        .fn_decls
+        .iter()
+        .filter(|(_, (_, _, visibility))| *visibility == Visibility::Public)
+        .collect::<Vec<_>>();
+    decls.sort_by_key(|(_, (_, location, _))| location.span.start);
+
+    decls
+        .into_iter()
+        .map(|(name, (ty, _, _))| {
+            let mut signature = String::new();
+
+            for attribute in module.attributes.get(name).into_iter().flatten() {
+                let args = attribute
+                    .args
+                    .iter()
+                    .map(|arg| format!("{arg:?}"))
+                    .collect::<Vec<_>>();
+                let _ = match args.is_empty() {
+                    true => writeln!(signature, "@{}", attribute.name),
+                    false => writeln!(signature, "@{}({})", attribute.name, args.join(", ")),
+                };
+            }
+
+            let _ = write!(signature, "pub {name} : {}", ty.type_value);
+            if let Some((func, _)) = module.fn_defns.get(name) {
+                let _ = write!(signature, "\n{name} = fn({})", func.args.join(" "));
+            }
+
+            Item {
+                name,
+                signature,
+                docs: module.docs.get(name).into_iter().flatten().collect(),
+            }
+        })
+        .collect()
+}
+
+/// The names a module's docs can link: its own items and everything it imports.
+fn links(module: &Module, modules: &HashMap<String, Module>) -> HashMap<String, Link> {
+    let mut links = HashMap::new();
+    let mut wildcards = HashMap::new();
+
+    for (key, target) in module.imports.iter().flatten() {
+        let imported = match modules.get(&target.path) {
+            Some(m) => m,
+            None => continue,
+        };
+        let target_module = doc_name(imported);
+
+        match key {
+            ImportKey::Symbol(symbol) => {
+                let anchor = target.symbol.clone().unwrap_or(symbol.clone());
+                let link = Link {
+                    module: Some(target_module.clone()),
+                    anchor: Some(anchor.clone()),
+                };
+                links.insert(format!("{target_module}.{anchor}"), link.clone());
+                links.insert(symbol.clone(), link);
+            }
+            ImportKey::Module(_) | ImportKey::Wildcard(_) => {
+                // Names a wildcard brings in, unless something more specific has them.
+                if let ImportKey::Wildcard(_) = key {
+                    for item in items(imported) {
+                        let link = Link {
+                            module: Some(target_module.clone()),
+                            anchor: Some(item.name.to_string()),
+                        };
+                        wildcards.insert(item.name.to_string(), link);
+                    }
+                }
+
+                let link = Link {
+                    module: Some(target_module.clone()),
+                    anchor: None,
+                };
+                links.insert(target_module, link);
+            }
+        }
+    }
+
+    for (name, link) in wildcards {
+        links.entry(name).or_insert(link);
+    }
+
+    for item in items(module) {
+        let link = Link {
+            module: None,
+            anchor: Some(item.name.to_string()),
+        };
+        links.insert(item.name.to_string(), link);
+    }
+
+    links
+}
+
+// The imports of a module as (what's imported, link to it), sorted.
+fn imports(module: &Module, modules: &HashMap<String, Module>) -> Vec<(String, Link)> {
+    let mut imports = module
+        .imports
+        .iter()
+        .flatten()
+        .filter_map(|(key, target)| {
+            let target_module = doc_name(modules.get(&target.path)?);
+
+            Some(match key {
+                ImportKey::Symbol(symbol) => {
+                    let anchor = target.symbol.clone().unwrap_or(symbol.clone());
+                    let name = match *symbol == anchor {
+                        true => format!("{target_module}.{anchor}"),
+                        false => format!("{target_module}.{anchor} as {symbol}"),
+                    };
+                    let link = Link {
+                        module: Some(target_module),
+                        anchor: Some(anchor),
+                    };
+                    (name, link)
+                }
+                ImportKey::Module(_) | ImportKey::Wildcard(_) => {
+                    let name = match key {
+                        ImportKey::Wildcard(_) => format!("{target_module}.*"),
+                        _ => target_module.clone(),
+                    };
+                    let link = Link {
+                        module: Some(target_module),
+                        anchor: None,
+                    };
+                    (name, link)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    imports.sort_by(|a, b| a.0.cmp(&b.0));
+    imports
+}
+
+// Text of a doc comment with every `name` that has a link turned into one.
+fn linked_markdown(md: &str, links: &HashMap<String, Link>) -> String {
+    let mut out = String::new();
+
+    for (i, part) in md.split('`').enumerate() {
+        let is_code = i % 2 == 1;
+
+        match links.get(part) {
+            Some(link) if is_code => {
+                let _ = write!(out, "[`{part}`]({})", link.href("md"));
+            }
+            _ if is_code => {
+                let _ = write!(out, "`{part}`");
+            }
+            _ => out.push_str(part),
+        }
+    }
+
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Paragraphs of a doc comment, with inline code and links.
+fn linked_html(md: &str, links: &HashMap<String, Link>) -> String {
+    let mut out = String::new();
+
+    for paragraph in md.split("\n\n").filter(|p| !p.trim().is_empty()) {
+        out.push_str("<p>");
+
+        for (i, part) in paragraph.split('`').enumerate() {
+            let is_code = i % 2 == 1;
+            let text = escape_html(part);
+
+            match links.get(part) {
+                Some(link) if is_code => {
+                    let href = escape_html(&link.href("html"));
+                    let _ = write!(out, "<a href=\"{href}\"><code>{text}</code></a>");
+                }
+                _ if is_code => {
+                    let _ = write!(out, "<code>{text}</code>");
+                }
+                _ => out.push_str(&text),
+            }
         }
-        result += ")";
-        result += &format!("{:?} \n\n", &f.return_type);
 
-        // TODO: Report parse errors and such
-        for dc in &f.doc_comments {
-            result += &dc.md;
+        out.push_str("</p>\n");
+    }
+
+    out
+}
+
+fn module_markdown(
+    module: &Module,
+    modules: &HashMap<String, Module>,
+    links: &HashMap<String, Link>,
+) -> String {
+    let mut out = format!("# {}\n\n", doc_name(module));
+
+    for doc in &module.doc_comments {
+        let _ = write!(out, "{}\n\n", linked_markdown(&doc.md, links));
+    }
+
+    let imports = imports(module, modules);
+    if !imports.is_empty() {
+        out.push_str("## Imports\n\n");
+        for (name, link) in imports {
+            let _ = writeln!(out, "- [`{name}`]({})", link.href("md"));
         }
+        out.push('\n');
+    }
+
+    let items = items(module);
+    if !items.is_empty() {
+        out.push_str("## Functions\n");
+    }
+    for item in items {
+        let _ = write!(
+            out,
+            "\n### {}\n\n```gahl\n{}\n```\n",
+            item.name, item.signature
+        );
+
+        for doc in item.docs {
+            let _ = write!(out, "\n{}\n", linked_markdown(&doc.md, links));
+        }
+    }
+
+    out
+}
+
+fn html_page(title: &str, modules: &[String], body: &str) -> String {
+    let nav = modules
+        .iter()
+        .map(|m| format!("<li><a href=\"{0}.html\">{0}</a></li>", escape_html(m)))
+        .collect::<String>();
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 60em; margin: 0 auto; display: flex; gap: 2em; }}
+nav {{ min-width: 12em; }}
+nav ul {{ list-style: none; padding: 0; }}
+main {{ flex: 1; }}
+pre {{ background: #f4f4f4; padding: 0.8em; }}
+code {{ background: #f4f4f4; }}
+</style>
+</head>
+<body>
+<nav><a href=\"index.html\">Index</a><ul>{nav}</ul></nav>
+<main>
+{body}</main>
+</body>
+</html>
+",
+        title = escape_html(title),
+    )
+}
+
+fn module_html(
+    module: &Module,
+    modules: &HashMap<String, Module>,
+    links: &HashMap<String, Link>,
+    names: &[String],
+) -> String {
+    let name = doc_name(module);
+    let mut body = format!("<h1>{}</h1>\n", escape_html(&name));
+
+    for doc in &module.doc_comments {
+        body.push_str(&linked_html(&doc.md, links));
+    }
+
+    let imports = imports(module, modules);
+    if !imports.is_empty() {
+        body.push_str("<h2>Imports</h2>\n<ul>\n");
+        for (name, link) in imports {
+            let _ = writeln!(
+                body,
+                "<li><a href=\"{}\"><code>{}</code></a></li>",
+                escape_html(&link.href("html")),
+                escape_html(&name)
+            );
+        }
+        body.push_str("</ul>\n");
+    }
+
+    let items = items(module);
+    if !items.is_empty() {
+        body.push_str("<h2>Functions</h2>\n");
+    }
+    for item in items {
+        let _ = write!(
+            body,
+            "<h3 id=\"{0}\">{0}</h3>\n<pre><code>{1}</code></pre>\n",
+            escape_html(item.name),
+            escape_html(&item.signature)
+        );
+
+        for doc in item.docs {
+            body.push_str(&linked_html(&doc.md, links));
+        }
+    }
+
+    html_page(&name, names, &body)
+}
+
+// First paragraph of the module docs, for the index.
+fn summary(module: &Module) -> String {
+    module
+        .doc_comments
+        .first()
+        .and_then(|doc| doc.md.split("\n\n").next())
+        .unwrap_or_default()
+        .replace('\n', " ")
+}
+
+/// Documentation of the public functions of `modules`: a Markdown and an HTML page per module,
+/// cross-linked through their imports, and an index of both.
+pub fn generate(modules: &HashMap<String, Module>) -> Vec<Page> {
+    let mut sorted = modules.values().collect::<Vec<&Module>>();
+    sorted.sort_by_key(|m| doc_name(m));
+    let names = sorted.iter().map(|m| doc_name(m)).collect::<Vec<String>>();
+
+    let mut pages = vec![];
+    let mut index_md = String::from("# Documentation\n\n");
+    let mut index_html = String::from("<h1>Documentation</h1>\n<ul>\n");
+
+    for module in sorted {
+        let name = doc_name(module);
+        let links = links(module, modules);
+        let summary = summary(module);
+
+        let entry = format!("- [{name}]({name}.md) {summary}");
+        let _ = writeln!(index_md, "{}", entry.trim_end());
+        let _ = writeln!(
+            index_html,
+            "<li><a href=\"{0}.html\">{0}</a> {1}</li>",
+            escape_html(&name),
+            escape_html(&summary)
+        );
+
+        pages.push(Page {
+            file: format!("{name}.md"),
+            contents: module_markdown(module, modules, &links),
+        });
+        pages.push(Page {
+            file: format!("{name}.html"),
+            contents: module_html(module, modules, &links, &names),
+        });
+    }
+
+    index_html.push_str("</ul>\n");
+    pages.push(Page {
+        file: "index.md".to_string(),
+        contents: index_md,
+    });
+    pages.push(Page {
+        file: "index.html".to_string(),
+        contents: html_page("Documentation", &names, &index_html),
     });
 
-    result
-}
\ No newline at end of file
+    pages
+}
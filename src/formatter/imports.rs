@@ -0,0 +1,150 @@
+use crate::lexer::token::TokenKind as TK;
+
+use super::Word;
+
+/// An entry of an import block.
+#[derive(Debug, PartialEq)]
+enum Entry {
+    /// `a.b.c`, `a.b.*` or `a.b.c as d`, as it's written.
+    Single(String),
+    /// `a.b { ... }`.
+    Group(String, Vec<Entry>),
+}
+
+impl Entry {
+    fn path(&self) -> &str {
+        match self {
+            Entry::Single(path) | Entry::Group(path, _) => path,
+        }
+    }
+}
+
+// `a.b.c`, `a.b.*` or `a.b.c as d`, with the group after it if there is one.
+fn entry(words: &[Word], i: &mut usize) -> Option<Entry> {
+    let mut path = words[*i].token.literal();
+    *i += 1;
+
+    while words.get(*i).map(|w| w.token.kind()) == Some(TK::Dot) {
+        let segment = words.get(*i + 1)?;
+
+        match segment.token.kind() {
+            TK::Identifier => path += &format!(".{}", segment.token.literal()),
+            TK::Mul => {
+                *i += 2;
+                return Some(Entry::Single(format!("{path}.*")));
+            }
+            _ => return None,
+        }
+        *i += 2;
+    }
+
+    match words.get(*i).map(|w| w.token.kind()) {
+        Some(TK::KwAs) => {
+            let alias = words
+                .get(*i + 1)
+                .filter(|w| w.token.kind() == TK::Identifier)?;
+            *i += 2;
+            Some(Entry::Single(format!(
+                "{path} as {}",
+                alias.token.literal()
+            )))
+        }
+        Some(TK::OpenCurly) => {
+            *i += 1;
+            Some(Entry::Group(path, entries(words, i)?))
+        }
+        _ => Some(Entry::Single(path)),
+    }
+}
+
+// The entries of a group up to and including its `}`. `None` when there's anything else, like a
+// doc comment, which is then left where it is.
+fn entries(words: &[Word], i: &mut usize) -> Option<Vec<Entry>> {
+    let mut entries = vec![];
+
+    while let Some(word) = words.get(*i) {
+        match word.token.kind() {
+            TK::ClosedCurly => {
+                *i += 1;
+                return Some(entries);
+            }
+            TK::Identifier => entries.push(entry(words, i)?),
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+// Sorted by path, without duplicates and with groups of the same path merged.
+fn normalize(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut normalized: Vec<Entry> = vec![];
+
+    for entry in entries {
+        match entry {
+            Entry::Group(path, more) => {
+                let same = normalized.iter_mut().find_map(|e| match e {
+                    Entry::Group(other, inner) if *other == path => Some(inner),
+                    _ => None,
+                });
+
+                match same {
+                    Some(inner) => inner.extend(more),
+                    None => normalized.push(Entry::Group(path, more)),
+                }
+            }
+            entry if normalized.contains(&entry) => (),
+            entry => normalized.push(entry),
+        }
+    }
+
+    for entry in &mut normalized {
+        if let Entry::Group(_, inner) = entry {
+            *inner = normalize(std::mem::take(inner));
+        }
+    }
+    normalized.sort_by(|a, b| a.path().cmp(b.path()));
+
+    normalized
+}
+
+fn render(entries: &[Entry], depth: usize, out: &mut String) {
+    let indent = super::INDENT.repeat(depth);
+
+    for entry in entries {
+        match entry {
+            Entry::Single(path) => *out += &format!("{indent}{path}\n"),
+            Entry::Group(path, inner) if inner.is_empty() => {
+                *out += &format!("{indent}{path} {{}}\n");
+            }
+            Entry::Group(path, inner) => {
+                *out += &format!("{indent}{path} {{\n");
+                render(inner, depth + 1, out);
+                *out += &format!("{indent}}}\n");
+            }
+        }
+    }
+}
+
+/// The import block `words` start with, one entry per line and sorted, along with the number of
+/// words it takes up. `None` when they don't start with one this can rewrite.
+pub fn block(words: &[Word]) -> Option<(String, usize)> {
+    let opens = words.len() >= 2
+        && words[0].token.kind() == TK::KwImport
+        && words[1].token.kind() == TK::OpenCurly;
+    if !opens {
+        return None;
+    }
+
+    let mut i = 2;
+    let entries = normalize(entries(words, &mut i)?);
+
+    let mut block = String::from("import {");
+    if !entries.is_empty() {
+        block.push('\n');
+        render(&entries, 1, &mut block);
+    }
+    block.push('}');
+
+    Some((block, i))
+}
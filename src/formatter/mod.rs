@@ -0,0 +1,482 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::lexer::{
+    token::{Token, TokenKind as TK},
+    Lexer,
+};
+
+pub mod imports;
+
+const INDENT: &str = "  ";
+
+/// A token that isn't trivia, with what the trivia before it was.
+pub struct Word {
+    token: Token,
+    /// Line breaks before it.
+    newlines: usize,
+    /// Whether there's any whitespace before it.
+    spaced: bool,
+}
+
+fn words(source: &str) -> Vec<Word> {
+    let mut words = vec![];
+    let (mut newlines, mut spaced) = (0, false);
+
+    for token in Lexer::new(source).lex_lossless() {
+        match token.kind() {
+            TK::NewLine => (newlines, spaced) = (newlines + 1, true),
+            kind if kind.is_trivia() => spaced = true,
+            _ => {
+                words.push(Word {
+                    token,
+                    newlines,
+                    spaced,
+                });
+                (newlines, spaced) = (0, false);
+            }
+        }
+    }
+
+    words
+}
+
+/// A line of output, without its indentation.
+struct Line {
+    depth: usize,
+    text: String,
+    /// Whether there are blank lines before it in the source.
+    blank_before: bool,
+    first: TK,
+    last: TK,
+    /// `pub name` of a declaration `pub name : type` outside of any block, the colons of
+    /// consecutive ones are aligned.
+    decl: Option<String>,
+    /// `name` of a definition `name = ...` outside of any block.
+    defn: Option<String>,
+}
+
+// `-` and `*` are operators when something they can apply to is in front of them, unless only
+// the space before them says otherwise, like in `fn(i32 *u8)`.
+fn is_binary(words: &[Word], i: usize) -> bool {
+    let operand = i > 0
+        && words[i].newlines == 0
+        && matches!(
+            words[i - 1].token.kind(),
+            TK::Identifier | TK::Integer | TK::String | TK::ClosedParen | TK::ClosedBracket
+        );
+    let spaced_after = words.get(i + 1).is_none_or(|w| w.spaced);
+
+    operand && words[i].spaced == spaced_after
+}
+
+// Whether `a` and `b` written next to each other would lex as something else.
+fn merges(a: &Token, b: &Token) -> bool {
+    let tokens = Lexer::new(&(a.literal() + &b.literal())).lex_lossless();
+
+    tokens.len() != 2 || tokens[0].kind() != a.kind() || tokens[1].kind() != b.kind()
+}
+
+// Whether there's a space between the words `i - 1` and `i` on the same line. `in_parens` is
+// whether `i` is inside parentheses or brackets.
+fn space_before(words: &[Word], i: usize, in_parens: bool) -> bool {
+    let (a, b) = (words[i - 1].token.kind(), words[i].token.kind());
+    let unary =
+        |i: usize| matches!(words[i].token.kind(), TK::Min | TK::Mul) && !is_binary(words, i);
+
+    let space = match (a, b) {
+        _ if unary(i - 1) => false,
+        (_, TK::Min | TK::Mul) if is_binary(words, i) => true,
+        (TK::Min | TK::Mul, _) => true,
+        (_, TK::Comma) => false,
+        (TK::Comma, _) => true,
+        (TK::OpenParen | TK::OpenBracket, _) | (_, TK::ClosedParen | TK::ClosedBracket) => false,
+        (TK::Dot, _) | (_, TK::Dot) | (TK::At, _) => false,
+        (TK::Identifier | TK::KwFn, TK::OpenParen) => false,
+        (TK::OpenCurly, TK::ClosedCurly) => false,
+        (_, TK::OpenCurly | TK::ClosedCurly) | (TK::OpenCurly, _) => true,
+        (_, TK::Column) => !in_parens,
+        (TK::Column | TK::Not, _) => true,
+        // The return type of `fn(...) type`.
+        (TK::ClosedParen, _) if words[i].token.literal().starts_with(char::is_alphanumeric) => true,
+        (TK::Coleq | TK::Eq | TK::EqEq | TK::RightArrow | TK::Add | TK::Div | TK::Mod, _)
+        | (_, TK::Coleq | TK::Eq | TK::EqEq | TK::RightArrow | TK::Add | TK::Div | TK::Mod) => true,
+        (TK::Caret | TK::Pipe, _) | (_, TK::Caret | TK::Pipe) => true,
+        _ => words[i].spaced,
+    };
+
+    space || merges(&words[i - 1].token, &words[i].token)
+}
+
+// Breaks `words` into lines where the source has line breaks, with the spacing inside lines
+// normalized. The import block at `words[0]`, if any, is already one.
+fn lines(words: &[Word], mut lines: Vec<Line>, start: usize) -> Vec<Line> {
+    let mut depth = 0usize;
+    let mut parens = 0usize;
+
+    for i in start..words.len() {
+        let word = &words[i];
+        let kind = word.token.kind();
+
+        let closes = matches!(kind, TK::ClosedCurly | TK::ClosedParen | TK::ClosedBracket);
+        if i == start || word.newlines > 0 {
+            let depth = match kind {
+                _ if closes => depth.saturating_sub(1),
+                // Variants of an enum.
+                TK::Pipe if depth == 0 => 1,
+                _ => depth,
+            };
+            lines.push(Line {
+                depth,
+                text: String::new(),
+                blank_before: word.newlines > 1,
+                first: kind.clone(),
+                last: kind.clone(),
+                decl: None,
+                defn: None,
+            });
+        } else if space_before(words, i, parens > 0) {
+            lines.last_mut().unwrap().text.push(' ');
+        }
+
+        match kind {
+            TK::OpenCurly => depth += 1,
+            TK::OpenParen | TK::OpenBracket => (depth, parens) = (depth + 1, parens + 1),
+            TK::ClosedCurly => depth = depth.saturating_sub(1),
+            TK::ClosedParen | TK::ClosedBracket => {
+                (depth, parens) = (depth.saturating_sub(1), parens.saturating_sub(1))
+            }
+            _ => (),
+        }
+
+        let line = lines.last_mut().unwrap();
+        line.text += &word.token.literal();
+        line.last = kind;
+    }
+
+    for line in &mut lines {
+        if line.depth > 0 {
+            continue;
+        }
+        // `pub name : ...` and `name = ...`.
+        let head = line.text.strip_prefix("pub ").unwrap_or(&line.text);
+        let name_end = head
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(head.len());
+
+        match &head[name_end..] {
+            rest if rest.starts_with(" : ") && name_end > 0 => {
+                let end = line.text.len() - rest.len();
+                line.decl = Some(line.text[..end].to_string());
+            }
+            rest if rest.starts_with(" = ") && name_end > 0 => {
+                line.defn = Some(head[..name_end].to_string());
+            }
+            _ => (),
+        }
+    }
+
+    lines
+}
+
+// Whether there's a blank line between `prev` and `line`, the source's choice unless it's inside a
+// pair of braces or between a declaration and its definition.
+fn blank_between(prev: &Line, line: &Line) -> bool {
+    let pair = match (&prev.decl, &line.defn) {
+        (Some(decl), Some(defn)) => decl.strip_prefix("pub ").unwrap_or(decl) == defn,
+        _ => false,
+    };
+
+    match () {
+        _ if prev.last == TK::OpenCurly || line.first == TK::ClosedCurly => false,
+        _ if pair => false,
+        _ => line.blank_before,
+    }
+}
+
+fn render(lines: &[Line]) -> String {
+    let mut out = String::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        // Consecutive declarations, to align their colons.
+        let mut end = i + 1;
+        if lines[i].decl.is_some() {
+            while end < lines.len()
+                && lines[end].decl.is_some()
+                && !blank_between(&lines[end - 1], &lines[end])
+            {
+                end += 1;
+            }
+        }
+        let width = lines[i..end]
+            .iter()
+            .filter_map(|line| line.decl.as_ref().map(|decl| decl.chars().count()))
+            .max()
+            .unwrap_or(0);
+
+        for (j, line) in lines.iter().enumerate().take(end).skip(i) {
+            if j > 0 && blank_between(&lines[j - 1], line) {
+                out.push('\n');
+            }
+
+            let text = match &line.decl {
+                Some(decl) if end - i > 1 => {
+                    let padding = " ".repeat(width - decl.chars().count());
+                    format!("{decl}{padding}{}", &line.text[decl.len()..])
+                }
+                _ => line.text.clone(),
+            };
+            out += &format!("{}{}\n", INDENT.repeat(line.depth), text.trim_end());
+        }
+
+        i = end;
+    }
+
+    out
+}
+
+// Tokens of `words` after the import block, what formatting mustn't change.
+fn code(words: &[Word]) -> Vec<(TK, String)> {
+    let start = imports::block(words).map_or(0, |(_, end)| end);
+
+    words[start..]
+        .iter()
+        .map(|w| (w.token.kind(), w.token.literal()))
+        .collect()
+}
+
+/// `source` as `gahl fmt` writes it: two spaces of indentation, normalized spacing, colons of
+/// consecutive declarations aligned and the import block sorted with one import per line.
+/// Comments and line breaks are kept, blank lines too but at most one in a row.
+pub fn format(source: &str) -> Result<String, String> {
+    let words = words(source);
+
+    let (lines, start) = match imports::block(&words) {
+        Some((block, end)) => {
+            let line = Line {
+                depth: 0,
+                text: block,
+                blank_before: false,
+                first: TK::KwImport,
+                last: TK::ClosedCurly,
+                decl: None,
+                defn: None,
+            };
+            (vec![line], end)
+        }
+        None => (vec![], 0),
+    };
+    let formatted = render(&self::lines(&words, lines, start));
+
+    // Only the layout may change, an error here is a bug of the formatter.
+    if code(&self::words(&formatted)) != code(&words) {
+        return Err("formatting would change the code, it's left as it is.".to_string());
+    }
+
+    Ok(formatted)
+}
+
+// Row of the first line that differs, counting from 1.
+fn first_difference(a: &str, b: &str) -> usize {
+    let mut lines = a.lines().zip(b.lines());
+    let same = lines.by_ref().take_while(|(a, b)| a == b).count();
+
+    same + 1
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        if name.starts_with('.') || name == "build" {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "gh") {
+            let path = path.strip_prefix(".").unwrap_or(&path);
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+}
+
+/// The `.gh` files under the current directory, except in `build/` and hidden directories.
+pub fn project_files() -> Vec<String> {
+    let mut files = vec![];
+    collect_files(Path::new("."), &mut files);
+
+    files.sort();
+    files
+}
+
+/// Formats `files` in place, or with `check` only reports the ones that aren't formatted.
+/// Returns whether everything went well, and with `check` whether all of them are formatted.
+pub fn format_files(files: &[String], check: bool) -> bool {
+    let mut ok = true;
+
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("\x1b[31mError:\x1b[0m could not read `{file}`: {err}");
+                ok = false;
+                continue;
+            }
+        };
+        let formatted = match format(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("\x1b[31mError:\x1b[0m `{file}`: {err}");
+                ok = false;
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+        if check {
+            let row = first_difference(&source, &formatted);
+            println!("`{file}` isn't formatted, it differs from line {row} on.");
+            ok = false;
+        } else if let Err(err) = fs::write(file, formatted) {
+            eprintln!("\x1b[31mError:\x1b[0m could not write `{file}`: {err}");
+            ok = false;
+        }
+    }
+
+    ok
+}
+
+/// Formats stdin to stdout, for editors. With `check` nothing is written, returns whether stdin
+/// was formatted already.
+pub fn format_stdin(check: bool) -> bool {
+    let mut source = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut source) {
+        eprintln!("\x1b[31mError:\x1b[0m could not read stdin: {err}");
+        return false;
+    }
+
+    match format(&source) {
+        Ok(formatted) if check => formatted == source,
+        Ok(formatted) => {
+            print!("{formatted}");
+            true
+        }
+        Err(err) => {
+            eprintln!("\x1b[31mError:\x1b[0m {err}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    const MESSY: &str = "import {
+  utils.util.add
+    std.io.println
+}
+; Small helpers. ;
+
+
+; Adds `a` and `b`. ;
+pub add: fn(i32 i32)i32
+add=fn(a b){
+      a+b
+}
+sub : fn(i32 i32) i32
+sub = fn(a b) {
+  a - b
+}
+";
+
+    #[test]
+    fn idempotent() {
+        let once = format(MESSY).unwrap();
+
+        assert_ne!(once, MESSY);
+        assert_eq!(format(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let source = "; Module docs. ;
+
+; Spans
+  two lines. ;
+pub one : fn() i32
+one = fn() {
+  1
+}
+";
+        assert_eq!(format(source).unwrap(), source);
+        assert!(format(MESSY)
+            .unwrap()
+            .contains("; Small helpers. ;\n\n; Adds `a` and `b`. ;\n"));
+    }
+
+    #[test]
+    fn keeps_blank_lines() {
+        let source = "one : fn() i32
+one = fn() {
+  1
+}
+two : fn() i32
+two = fn() {
+  2
+}
+
+
+
+three : fn() void
+";
+
+        assert_eq!(format(source).unwrap(), source.replace("\n\n\n\n", "\n\n"));
+    }
+
+    #[test]
+    fn aligns_declarations() {
+        let source =
+            "gahl_rt_exit : extern fn(code: i32) void\npub add : fn(i32 i32) i32\nx : fn() void\n";
+
+        assert_eq!(
+            format(source).unwrap(),
+            "gahl_rt_exit : extern fn(code: i32) void\npub add      : fn(i32 i32) i32\nx            : fn() void\n"
+        );
+    }
+
+    #[test]
+    fn sorts_imports() {
+        let source = "import {
+  utils.util.add
+  std.io { println_int println }
+  std.io.println
+  utils.util.add
+}
+";
+
+        assert_eq!(
+            format(source).unwrap(),
+            "import {
+  std.io {
+    println
+    println_int
+  }
+  std.io.println
+  utils.util.add
+}
+"
+        );
+    }
+}
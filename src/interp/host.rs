@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Read, Write},
+};
+
+use crate::ast::TypeValue;
+
+use super::{Heap, RuntimeError, Value};
+
+/// A function the interpreter runs in place of an `extern` one.
+pub type HostFn = fn(&mut Host, &mut Heap, &[Value]) -> Result<Value, RuntimeError>;
+
+/// The `extern` functions the interpreter can call, by name. The defaults mirror the C runtime
+/// behind `std` (`std/runtime.c`), embedders can register more.
+pub struct Host {
+    functions: HashMap<String, HostFn>,
+    /// Open files of `std.fs`, by handle.
+    files: HashMap<i32, File>,
+    next_handle: i32,
+    /// What `std.process.arg` returns, the program name first.
+    pub args: Vec<String>,
+}
+
+impl Host {
+    pub fn new(args: Vec<String>) -> Self {
+        let mut host = Self {
+            functions: HashMap::new(),
+            files: HashMap::new(),
+            next_handle: 0,
+            args,
+        };
+
+        host.register("gahl_rt_print_str", |_, _, args| {
+            print!("{}", str_arg(args, 0)?);
+            Ok(Value::Void)
+        });
+        host.register("gahl_rt_println_str", |_, _, args| {
+            println!("{}", str_arg(args, 0)?);
+            Ok(Value::Void)
+        });
+        host.register("gahl_rt_print_i32", |_, _, args| {
+            print!("{}", int_arg(args, 0)?);
+            Ok(Value::Void)
+        });
+        host.register("gahl_rt_println_i32", |_, _, args| {
+            println!("{}", int_arg(args, 0)?);
+            Ok(Value::Void)
+        });
+        host.register("gahl_rt_read_line", |_, _, _| {
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            let _ = io::stdin().lock().read_line(&mut line);
+            Ok(Value::Str(line.trim_end_matches(['\n', '\r']).to_string()))
+        });
+
+        host.register("gahl_rt_fs_open", |host, _, args| {
+            let path = str_arg(args, 0)?;
+            let mode = str_arg(args, 1)?;
+
+            let mut options = OpenOptions::new();
+            match mode.trim_end_matches('b') {
+                "r" => options.read(true),
+                "w" => options.write(true).create(true).truncate(true),
+                "a" => options.append(true).create(true),
+                "r+" => options.read(true).write(true),
+                "w+" => options.read(true).write(true).create(true).truncate(true),
+                "a+" => options.read(true).append(true).create(true),
+                _ => return Ok(i32_value(-1)),
+            };
+
+            Ok(match options.open(path) {
+                Ok(file) => {
+                    let handle = host.next_handle;
+                    host.next_handle += 1;
+                    host.files.insert(handle, file);
+                    i32_value(handle)
+                }
+                Err(_) => i32_value(-1),
+            })
+        });
+        host.register("gahl_rt_fs_read", |host, _, args| {
+            let handle = int_arg(args, 0)? as i32;
+
+            let mut contents = String::new();
+            if let Some(file) = host.files.get_mut(&handle) {
+                let _ = file.read_to_string(&mut contents);
+            }
+            Ok(Value::Str(contents))
+        });
+        host.register("gahl_rt_fs_write", |host, _, args| {
+            let handle = int_arg(args, 0)? as i32;
+            let s = str_arg(args, 1)?;
+
+            let written = host
+                .files
+                .get_mut(&handle)
+                .is_some_and(|file| file.write_all(s.as_bytes()).is_ok());
+            Ok(i32_value(if written { s.len() as i32 } else { -1 }))
+        });
+        host.register("gahl_rt_fs_close", |host, _, args| {
+            let handle = int_arg(args, 0)? as i32;
+
+            Ok(match host.files.remove(&handle) {
+                Some(_) => i32_value(0),
+                None => i32_value(-1),
+            })
+        });
+
+        // Memory is a run of cells, one per byte.
+        host.register("gahl_rt_mem_alloc", |_, heap, args| {
+            let size = int_arg(args, 0)?.max(1) as usize;
+            let ptr = heap.alloc(Value::Int(TypeValue::U8, 0));
+            for _ in 1..size {
+                heap.alloc(Value::Int(TypeValue::U8, 0));
+            }
+            Ok(Value::Ptr(ptr))
+        });
+        host.register("gahl_rt_mem_release", |_, _, _| Ok(i32_value(0)));
+        host.register("gahl_rt_mem_copy", |_, heap, args| {
+            let (Some(Value::Ptr(dst)), Some(Value::Ptr(src))) = (args.first(), args.get(1)) else {
+                return Err(RuntimeError::new("`mem_copy` needs two pointers"));
+            };
+            let size = int_arg(args, 2)? as usize;
+
+            for i in 0..size {
+                let value = heap.load(src + i)?;
+                heap.store(dst + i, value)?;
+            }
+            Ok(i32_value(0))
+        });
+
+        host.register("gahl_rt_exit", |_, _, args| {
+            let _ = io::stdout().flush();
+            Err(RuntimeError::Exit(int_arg(args, 0)? as i32))
+        });
+        host.register("gahl_rt_arg_count", |host, _, _| {
+            Ok(i32_value(host.args.len() as i32))
+        });
+        host.register("gahl_rt_arg", |host, _, args| {
+            let i = int_arg(args, 0)?;
+            let arg = usize::try_from(i)
+                .ok()
+                .and_then(|i| host.args.get(i))
+                .cloned()
+                .unwrap_or_default();
+            Ok(Value::Str(arg))
+        });
+
+        // The builtins `assert` and `assert_eq`, the checker calls the one for the type of the
+        // values.
+        for suffix in [
+            "i8", "i16", "i32", "i64", "isize", "u8", "u16", "u32", "u64", "usize",
+        ] {
+            host.register(&format!("gahl_rt_assert_{suffix}"), assert);
+            host.register(&format!("gahl_rt_assert_eq_{suffix}"), assert_eq);
+        }
+        host.register("gahl_rt_assert_eq_str", assert_eq);
+
+        host
+    }
+
+    /// Makes `function` answer calls to the `extern` function `name`.
+    pub fn register(&mut self, name: &str, function: HostFn) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<HostFn> {
+        self.functions.get(name).copied()
+    }
+}
+
+fn assert(_: &mut Host, _: &mut Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    match int_arg(args, 0)? {
+        0 => Err(RuntimeError::new(format!(
+            "assertion failed at {}",
+            str_arg(args, 1)?
+        ))),
+        _ => Ok(Value::Void),
+    }
+}
+
+fn assert_eq(_: &mut Host, _: &mut Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (args.first(), args.get(1)) {
+        (Some(left), Some(right)) if left != right => Err(RuntimeError::new(format!(
+            "assertion failed at {}: `left == right`\n  left: {left}\n right: {right}",
+            str_arg(args, 2)?
+        ))),
+        _ => Ok(Value::Void),
+    }
+}
+
+fn i32_value(n: i32) -> Value {
+    Value::Int(TypeValue::I32, n as i128)
+}
+
+fn str_arg(args: &[Value], index: usize) -> Result<&str, RuntimeError> {
+    match args.get(index) {
+        Some(Value::Str(s)) => Ok(s),
+        _ => Err(RuntimeError::new(format!(
+            "argument {index} is not a string"
+        ))),
+    }
+}
+
+fn int_arg(args: &[Value], index: usize) -> Result<i128, RuntimeError> {
+    match args.get(index) {
+        Some(Value::Int(_, n)) => Ok(*n),
+        _ => Err(RuntimeError::new(format!(
+            "argument {index} is not an integer"
+        ))),
+    }
+}
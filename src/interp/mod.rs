@@ -0,0 +1,430 @@
+use core::fmt;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+    sync::Arc,
+    thread,
+};
+
+use crate::{
+    ast::TypeValue,
+    checker::mdir::{
+        escape::{address_taken, boxed_argument},
+        Expression, Function, Literal, MiddleIR, Statement,
+    },
+};
+
+use self::host::Host;
+
+pub mod host;
+
+/// Calls deeper than this are reported as a stack overflow instead of crashing the interpreter.
+const MAX_DEPTH: usize = 10_000;
+/// Stack of the thread the interpreter runs on, deep recursion needs far more than the default.
+pub const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Void,
+    Int(TypeValue, i128),
+    Float(TypeValue, f64),
+    Str(String),
+    /// Index of a heap cell.
+    Ptr(usize),
+}
+
+impl Value {
+    pub fn type_value(&self) -> TypeValue {
+        match self {
+            Value::Void => TypeValue::Void,
+            Value::Int(ty, _) | Value::Float(ty, _) => ty.clone(),
+            Value::Str(_) => TypeValue::String,
+            Value::Ptr(_) => TypeValue::Ptr(Box::new(TypeValue::Void)),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Void => write!(f, "()"),
+            Value::Int(_, n) => write!(f, "{n}"),
+            Value::Float(_, x) => write!(f, "{x:?}"),
+            Value::Str(s) => write!(f, "{s:?}"),
+            Value::Ptr(cell) => write!(f, "<cell {cell}>"),
+        }
+    }
+}
+
+/// Why the program stopped before `main` returned.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// `std.process.exit` was called.
+    Exit(i32),
+    Error {
+        message: String,
+        /// Functions the error unwound through, innermost first.
+        trace: Vec<String>,
+    },
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        RuntimeError::Error {
+            message: message.into(),
+            trace: vec![],
+        }
+    }
+
+    fn in_function(mut self, name: &str) -> Self {
+        if let RuntimeError::Error { trace, .. } = &mut self {
+            trace.push(name.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Exit(code) => write!(f, "exited with {code}"),
+            RuntimeError::Error { message, trace } => {
+                write!(f, "\x1b[31mError:\x1b[0m {message}")?;
+                // Recursion shows up once with the number of frames.
+                let mut i = 0;
+                while let Some(name) = trace.get(i) {
+                    let repeats = trace[i..].iter().take_while(|n| *n == name).count();
+                    write!(f, "\n  in `{name}`")?;
+                    if repeats > 1 {
+                        write!(f, " ({repeats} times)")?;
+                    }
+                    i += repeats;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Memory of the interpreted program, a value per cell. Nothing is ever freed.
+#[derive(Debug, Default)]
+pub struct Heap {
+    cells: Vec<Value>,
+}
+
+impl Heap {
+    pub fn alloc(&mut self, value: Value) -> usize {
+        self.cells.push(value);
+        self.cells.len() - 1
+    }
+
+    pub fn load(&self, cell: usize) -> Result<Value, RuntimeError> {
+        self.cells
+            .get(cell)
+            .cloned()
+            .ok_or_else(|| RuntimeError::new(format!("read of invalid cell {cell}")))
+    }
+
+    pub fn store(&mut self, cell: usize, value: Value) -> Result<(), RuntimeError> {
+        match self.cells.get_mut(cell) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(RuntimeError::new(format!("write to invalid cell {cell}"))),
+        }
+    }
+}
+
+/// Runs the MIR of checked modules directly, `extern` calls go to the [`Host`].
+pub struct Interpreter {
+    /// Every function of the program by the symbol calls refer to it with.
+    functions: HashMap<String, Arc<Function>>,
+    pub host: Host,
+    pub heap: Heap,
+    /// Overflowing arithmetic is an error instead of wrapping, as with the profile setting.
+    overflow_checks: bool,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new(host: Host, overflow_checks: bool) -> Self {
+        Self {
+            functions: HashMap::new(),
+            host,
+            heap: Heap::default(),
+            overflow_checks,
+            depth: 0,
+        }
+    }
+
+    /// Adds the functions of `mdir`, replacing earlier ones with the same symbol.
+    pub fn load(&mut self, mdir: MiddleIR) {
+        for (_, function) in mdir.into_functions() {
+            self.functions
+                .insert(function.symbol.clone(), Arc::new(function));
+        }
+    }
+
+    /// Calls the function or host function `symbol`.
+    pub fn call(&mut self, symbol: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        if let Some(function) = self.functions.get(symbol).cloned() {
+            if self.depth >= MAX_DEPTH {
+                return Err(RuntimeError::new("stack overflow"));
+            }
+
+            self.depth += 1;
+            let mut frame = function
+                .params
+                .iter()
+                .map(|(name, _)| name.clone())
+                .zip(args)
+                .collect();
+            let result = self.block(&function, &mut frame);
+            self.depth -= 1;
+
+            return result.map_err(|error| error.in_function(&function.name));
+        }
+
+        match self.host.get(symbol) {
+            Some(host_fn) => host_fn(&mut self.host, &mut self.heap, &args),
+            None => Err(RuntimeError::new(format!(
+                "`{symbol}` is neither a Gahl function nor a host function of the interpreter"
+            ))),
+        }
+    }
+
+    /// Runs the statements of `function` with the locals in `frame`, returns the value of the
+    /// last expression when the function returns one.
+    pub fn block(
+        &mut self,
+        function: &Function,
+        frame: &mut HashMap<String, Value>,
+    ) -> Result<Value, RuntimeError> {
+        let returns = function.return_type != TypeValue::Void;
+        let mut result = Value::Void;
+
+        for (i, stmt) in function.block.iter().enumerate() {
+            let is_last = i == function.block.len() - 1;
+
+            match stmt {
+                Statement::Expr(expr, _) => {
+                    let value = self.expr(function, frame, expr)?;
+
+                    if is_last && returns {
+                        result = value;
+                    }
+                }
+                Statement::Var(var) => {
+                    let value = self.expr(function, frame, &var.rhs)?;
+
+                    // Escaping locals live in a cell of their own, like the heap box of codegen.
+                    let value = match function.escapes.contains(&var.lhs) {
+                        true => Value::Ptr(self.heap.alloc(value)),
+                        false => value,
+                    };
+                    frame.insert(var.lhs.clone(), value);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn expr(
+        &mut self,
+        function: &Function,
+        frame: &HashMap<String, Value>,
+        expr: &VecDeque<Expression>,
+    ) -> Result<Value, RuntimeError> {
+        let mut stack = vec![];
+
+        for e in expr {
+            match e {
+                Expression::Literal(literal) => {
+                    let value = self.literal(function, frame, literal)?;
+                    stack.push(value);
+                }
+                op if op.is_op() => {
+                    let (Some(rhs), Some(lhs)) = (stack.pop(), stack.pop()) else {
+                        return Err(RuntimeError::new(format!("`{op}` is missing an operand")));
+                    };
+                    stack.push(self.binary_op(op, lhs, rhs)?);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(stack.pop().unwrap_or(Value::Void))
+    }
+
+    fn literal(
+        &mut self,
+        function: &Function,
+        frame: &HashMap<String, Value>,
+        literal: &Literal,
+    ) -> Result<Value, RuntimeError> {
+        let local = |name: &String| {
+            frame
+                .get(name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::new(format!("`{name}` is used before it is set")))
+        };
+
+        Ok(match literal {
+            Literal::Int(ty @ (TypeValue::F32 | TypeValue::F64), value) => {
+                let x = value.parse().map_err(|_| invalid(value))?;
+                Value::Float(ty.clone(), x)
+            }
+            Literal::Int(ty, value) => {
+                Value::Int(ty.clone(), value.parse().map_err(|_| invalid(value))?)
+            }
+            Literal::Identifier(_, name, false) if function.escapes.contains(name) => {
+                match local(name)? {
+                    Value::Ptr(cell) => self.heap.load(cell)?,
+                    value => value,
+                }
+            }
+            Literal::Identifier(_, name, _) => local(name)?,
+            Literal::String(string) => Value::Str(string[1..string.len() - 1].to_string()),
+            Literal::Call(_, symbol, args) => {
+                let mut values = vec![];
+
+                for (arg, param_type) in args {
+                    // Escaping locals already are the address of their cell.
+                    if let Some(name) = address_taken(arg, param_type) {
+                        values.push(local(name)?);
+                        continue;
+                    }
+
+                    let value = self.expr(function, frame, arg)?;
+
+                    values.push(match boxed_argument(arg, param_type).is_some() {
+                        true => Value::Ptr(self.heap.alloc(value)),
+                        false => value,
+                    });
+                }
+
+                self.call(symbol, values)?
+            }
+        })
+    }
+
+    fn binary_op(&self, op: &Expression, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+        match (lhs, rhs) {
+            (Value::Int(ty, a), Value::Int(_, b)) => {
+                let (verb, checked, wrapped) = match op {
+                    Expression::Add => ("add", a.checked_add(b), a.wrapping_add(b)),
+                    Expression::Min => ("subtract", a.checked_sub(b), a.wrapping_sub(b)),
+                    Expression::Mul => ("multiply", a.checked_mul(b), a.wrapping_mul(b)),
+                    Expression::Div if b == 0 => {
+                        return Err(RuntimeError::new("attempt to divide by zero"))
+                    }
+                    Expression::Div => ("divide", a.checked_div(b), a.wrapping_div(b)),
+                    _ => {
+                        let exponent =
+                            u32::try_from(b).map_err(|_| RuntimeError::new("negative exponent"))?;
+                        ("raise", a.checked_pow(exponent), a.wrapping_pow(exponent))
+                    }
+                };
+
+                let (min, max) = int_range(&ty);
+                match checked {
+                    Some(n) if (min..=max).contains(&n) => Ok(Value::Int(ty, n)),
+                    _ if self.overflow_checks => Err(RuntimeError::new(format!(
+                        "attempt to {verb} with overflow"
+                    ))),
+                    _ => Ok(Value::Int(ty.clone(), wrap(&ty, wrapped))),
+                }
+            }
+            (Value::Float(ty, a), Value::Float(_, b)) => {
+                let x = match op {
+                    Expression::Add => a + b,
+                    Expression::Min => a - b,
+                    Expression::Mul => a * b,
+                    Expression::Div => a / b,
+                    _ => a.powf(b),
+                };
+
+                // `f32` arithmetic rounds every result to single precision.
+                let x = match ty {
+                    TypeValue::F32 => x as f32 as f64,
+                    _ => x,
+                };
+                Ok(Value::Float(ty, x))
+            }
+            (lhs, rhs) => Err(RuntimeError::new(format!("can't `{op}` {lhs} and {rhs}"))),
+        }
+    }
+}
+
+fn invalid(value: &str) -> RuntimeError {
+    RuntimeError::new(format!("`{value}` is not a valid literal"))
+}
+
+// Width in bits and signedness of an integer type, pointer sized ones are 64 bits wide.
+fn int_layout(type_value: &TypeValue) -> (u32, bool) {
+    match type_value {
+        TypeValue::I8 => (8, true),
+        TypeValue::I16 => (16, true),
+        TypeValue::I32 => (32, true),
+        TypeValue::I64 | TypeValue::Isize => (64, true),
+        TypeValue::U8 | TypeValue::Bool => (8, false),
+        TypeValue::U16 => (16, false),
+        TypeValue::U32 => (32, false),
+        TypeValue::U64 | TypeValue::Usize => (64, false),
+        TypeValue::U128 => (128, false),
+        _ => (128, true),
+    }
+}
+
+fn int_range(type_value: &TypeValue) -> (i128, i128) {
+    match int_layout(type_value) {
+        (128, true) => (i128::MIN, i128::MAX),
+        // Unsigned 128 bit values are limited to what fits into an `i128`.
+        (128, false) => (0, i128::MAX),
+        (bits, true) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+        (bits, false) => (0, (1 << bits) - 1),
+    }
+}
+
+// Truncates `n` to the width of `type_value`, as the machine would.
+fn wrap(type_value: &TypeValue, n: i128) -> i128 {
+    match int_layout(type_value) {
+        (128, _) => n,
+        (bits, true) => (n << (128 - bits)) >> (128 - bits),
+        (bits, false) => n & ((1 << bits) - 1),
+    }
+}
+
+/// Runs `main` of the checked program, on a thread with room for deep recursion. Returns the exit
+/// code: what `main` returns, what the program passed to `std.process.exit` or 0.
+pub fn run(
+    modules: HashMap<String, MiddleIR>,
+    overflow_checks: bool,
+    args: Vec<String>,
+) -> Result<i32, RuntimeError> {
+    let handle = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut interpreter = Interpreter::new(Host::new(args), overflow_checks);
+            for (_, mdir) in modules {
+                interpreter.load(mdir);
+            }
+
+            let result = interpreter.call("main", vec![]);
+            let _ = io::stdout().flush();
+
+            match result {
+                Ok(Value::Int(_, code)) => Ok(code as i32),
+                Ok(_) => Ok(0),
+                Err(RuntimeError::Exit(code)) => Ok(code),
+                Err(error) => Err(error),
+            }
+        })
+        .map_err(|error| RuntimeError::new(format!("could not start the interpreter: {error}")))?;
+
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(RuntimeError::new("the interpreter panicked")))
+}
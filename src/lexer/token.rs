@@ -1,3 +1,4 @@
+use core::fmt;
 use std::ops::Range;
 
 #[derive(Debug, Clone)]
@@ -28,11 +29,13 @@ pub enum TokenKind {
     Ti32,
     Ti64,
     Ti128,
+    Tisize,
     Tu8,
     Tu16,
     Tu32,
     Tu64,
     Tu128,
+    Tusize,
     Tf32,
     Tf64,
     Tchar,
@@ -46,6 +49,8 @@ pub enum TokenKind {
     KwImport,
     KwStruct,
     KwEnum,
+    KwPub,
+    KwAs,
 
     // Funnies
     Pipe,
@@ -89,6 +94,14 @@ pub enum TokenKind {
 }
 
 impl TokenKind {
+    /// Whitespace and comments, which only matter to the formatter.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            Self::Whitespace | Self::NewLine | Self::Comment | Self::UnclosedComment
+        )
+    }
+
     pub fn is_sync_token(&self) -> bool {
         matches!(
             self,
@@ -109,6 +122,7 @@ impl TokenKind {
                 | Self::KwMatch
                 | Self::KwImport
                 | Self::KwStruct
+                | Self::KwPub
                 | Self::Identifier
         )
     }
@@ -123,11 +137,13 @@ impl TokenKind {
                 | Self::Ti32
                 | Self::Ti64
                 | Self::Ti128
+                | Self::Tisize
                 | Self::Tu8
                 | Self::Tu16
                 | Self::Tu32
                 | Self::Tu64
                 | Self::Tu128
+                | Self::Tusize
                 | Self::Tf32
                 | Self::Tf64
                 | Self::Tstring
@@ -139,7 +155,12 @@ impl TokenKind {
     pub fn is_expr(&self) -> bool {
         matches!(
             self,
-            Self::Integer | Self::Identifier | Self::Min | Self::OpenParen | Self::KwFn | Self::KwExtern
+            Self::Integer
+                | Self::Identifier
+                | Self::Min
+                | Self::OpenParen
+                | Self::KwFn
+                | Self::KwExtern
         )
     }
 
@@ -192,3 +213,19 @@ impl Token {
         self.span.start..self.span.end
     }
 }
+
+// One token per line for `--emit=tokens`: row, span, kind and the literal.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = format!("{}..{}", self.span.start, self.span.end);
+
+        write!(
+            f,
+            "{:>4} {:<10} {:<14} {:?}",
+            self.row + 1,
+            span,
+            format!("{:?}", self.kind),
+            self.span.literal
+        )
+    }
+}
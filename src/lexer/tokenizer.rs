@@ -4,7 +4,16 @@ use super::{
 };
 
 impl Lexer {
+    /// Tokens for the parser, without trivia.
     pub fn lex(&mut self) -> Vec<Token> {
+        self.lex_lossless()
+            .into_iter()
+            .filter(|t| !t.kind().is_trivia())
+            .collect::<Vec<Token>>()
+    }
+
+    /// Every token, trivia included, so their literals put together are the input again.
+    pub fn lex_lossless(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
 
         while !self.is_eof() {
@@ -13,9 +22,6 @@ impl Lexer {
         }
 
         tokens
-            .into_iter()
-            .filter(|t| t.kind() != TokenKind::Whitespace)
-            .collect::<Vec<Token>>()
     }
 
     fn next(&mut self) -> Token {
@@ -62,7 +68,7 @@ impl Lexer {
             }
             '\n' => {
                 self.inc_row();
-                TokenKind::Whitespace
+                TokenKind::NewLine
             }
             c if c.is_numeric() => self.integer(),
             c if c.is_whitespace() => self.whitespace(),
@@ -78,14 +84,15 @@ impl Lexer {
     }
 
     fn doc_comment(&mut self) -> TokenKind {
-        if let Some('\n') = self.peek() {
-            self.inc_row();
-        }
-
         self.eat_while(|c| c != ';');
 
         self.eat();
 
+        // Doc comments can span lines, the rows after them have to stay right.
+        for _ in 0..self.tok_str.matches('\n').count() {
+            self.inc_row();
+        }
+
         TokenKind::DocComment
     }
 
@@ -114,6 +121,8 @@ impl Lexer {
             "struct" => TokenKind::KwStruct,
             "extern" => TokenKind::KwExtern,
             "enum" => TokenKind::KwEnum,
+            "pub" => TokenKind::KwPub,
+            "as" => TokenKind::KwAs,
             // Types
             "void" => TokenKind::Tvoid,
             // boolean
@@ -124,12 +133,14 @@ impl Lexer {
             "i32" => TokenKind::Ti32,
             "i64" => TokenKind::Ti64,
             "i128" => TokenKind::Ti128,
+            "isize" => TokenKind::Tisize,
             // uint
             "u8" => TokenKind::Tu8,
             "u16" => TokenKind::Tu16,
             "u32" => TokenKind::Tu32,
             "u64" => TokenKind::Tu64,
             "u128" => TokenKind::Tu128,
+            "usize" => TokenKind::Tusize,
             // float
             "f32" => TokenKind::Tf32,
             "f64" => TokenKind::Tf64,
@@ -187,8 +198,9 @@ fn is_ident_cont(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
 }
 
+// Newlines are tokens of their own, to count rows.
 fn is_whitespace(c: char) -> bool {
-    c.is_whitespace()
+    c.is_whitespace() && c != '\n'
 }
 
 fn is_integer_cont(c: char) -> bool {
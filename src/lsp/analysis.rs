@@ -0,0 +1,551 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol, Position,
+    Range, SymbolKind, SymbolTag,
+};
+
+use crate::{
+    ast::{ImportKey, ImportTarget, Location, Module, TypeValue, Visibility},
+    checker::{
+        analyzer::Modules,
+        builtin::BUILTINS,
+        mdir::{Function, MiddleIR, Statement},
+        Checker,
+    },
+    lexer::{token::TokenKind, Lexer},
+    parser::{error::ModuleError, search::SearchPaths, Parser},
+};
+
+const KEYWORDS: [&str; 9] = [
+    "fn", "if", "match", "import", "struct", "extern", "enum", "pub", "as",
+];
+
+/// LSP position of the char at `offset` in `source`. Columns count UTF-16 code units.
+pub fn position(source: &str, offset: usize) -> Position {
+    let (mut line, mut character) = (0, 0);
+
+    for c in source.chars().take(offset) {
+        match c {
+            '\n' => (line, character) = (line + 1, 0),
+            c => character += c.len_utf16() as u32,
+        }
+    }
+
+    Position::new(line, character)
+}
+
+/// Char offset of `position` in `source`, the end of its line when it's past it.
+pub fn offset(source: &str, position: Position) -> usize {
+    let (mut line, mut character) = (0, 0);
+
+    for (i, c) in source.chars().enumerate() {
+        if line == position.line && (character >= position.character || c == '\n') {
+            return i;
+        }
+        match c {
+            '\n' => (line, character) = (line + 1, 0),
+            c => character += c.len_utf16() as u32,
+        }
+    }
+
+    source.chars().count()
+}
+
+pub fn range(source: &str, location: &Location) -> Range {
+    Range::new(
+        position(source, location.span.start),
+        position(source, location.span.end),
+    )
+}
+
+// Messages of the compiler are colored for the terminal.
+fn without_colors(message: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = message.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                chars.by_ref().find(|c| *c == 'm');
+            }
+            c => plain.push(c),
+        }
+    }
+
+    plain
+}
+
+fn diagnostic(range: Range, severity: DiagnosticSeverity, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        source: Some("gahl".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// What a name in a document refers to.
+pub enum Item {
+    /// A local or parameter of the function around it, with where it's defined.
+    Local(String, TypeValue, Location),
+    /// A function or extern, by the name of its module and its name in there.
+    Function(String, String),
+    Builtin(String),
+    /// A whole module, by its name.
+    Module(String),
+}
+
+/// Everything known about an open document since it last changed.
+pub struct Analysis {
+    /// Name of the document's module, its path from the project root.
+    pub name: String,
+    pub source: String,
+    /// The document's module and every module it imports, empty when they couldn't be parsed.
+    modules: Modules,
+    /// MIR of the document's module, for the types of locals.
+    mdir: Option<MiddleIR>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parses and checks `source` as the module `name`, imports are read from disk. Diagnostics are
+/// only about this module, the others get theirs when they're opened.
+pub fn analyze(name: &str, source: String, search_paths: SearchPaths) -> Analysis {
+    let mut analysis = Analysis {
+        name: name.to_string(),
+        source,
+        modules: Arc::new(HashMap::new()),
+        mdir: None,
+        diagnostics: vec![],
+    };
+    // Code the compiler can't handle yet panics, it shouldn't take the server with it.
+    let crashed = diagnostic(
+        Range::default(),
+        DiagnosticSeverity::ERROR,
+        "The compiler can't handle this module yet.".to_string(),
+    );
+
+    let parsed = panic::catch_unwind(AssertUnwindSafe(|| {
+        Parser::new(name, search_paths)
+            .with_source(analysis.source.clone())
+            .parse(name)
+    }));
+    let modules = match parsed {
+        Ok(Ok(modules)) => modules,
+        Ok(Err(error)) => {
+            let diagnostic = analysis.module_error(&error);
+            analysis.diagnostics.push(diagnostic);
+            return analysis;
+        }
+        Err(_) => {
+            analysis.diagnostics.push(crashed);
+            return analysis;
+        }
+    };
+    analysis.modules = Arc::new(
+        modules
+            .into_iter()
+            .map(|(name, module)| (name, Arc::new(module)))
+            .collect(),
+    );
+
+    let module = match analysis.modules.get(name) {
+        Some(module) => module.clone(),
+        None => return analysis,
+    };
+    let checked = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut checker = Checker::new(&module, analysis.modules.clone());
+        let mdir = checker.types();
+
+        let diagnostics = checker
+            .errors()
+            .iter()
+            .map(|error| (error, DiagnosticSeverity::ERROR))
+            .chain(
+                checker
+                    .warnings()
+                    .iter()
+                    .map(|warning| (warning, DiagnosticSeverity::WARNING)),
+            )
+            .map(|(error, severity)| {
+                let range = range(&analysis.source, error.location());
+                diagnostic(range, severity, error.message().to_string())
+            })
+            .collect::<Vec<Diagnostic>>();
+
+        (mdir, diagnostics)
+    }));
+
+    match checked {
+        Ok((mdir, diagnostics)) => {
+            analysis.mdir = Some(mdir);
+            analysis.diagnostics = diagnostics;
+        }
+        Err(_) => analysis.diagnostics.push(crashed),
+    }
+
+    analysis
+}
+
+impl Analysis {
+    fn module(&self) -> Option<&Module> {
+        self.modules.get(&self.name).map(|module| &**module)
+    }
+
+    // At the import that failed when it's in this module, at the start otherwise.
+    fn module_error(&self, error: &ModuleError) -> Diagnostic {
+        let imported_from = match error {
+            ModuleError::Unreadable { imported_from, .. } => imported_from.as_ref(),
+            ModuleError::Cycle { imported_from, .. } => Some(imported_from),
+        };
+        let range = match imported_from {
+            Some((module, location)) if *module == self.name => range(&self.source, location),
+            _ => Range::default(),
+        };
+
+        diagnostic(
+            range,
+            DiagnosticSeverity::ERROR,
+            without_colors(&error.to_string()),
+        )
+    }
+
+    // The identifier the char at `offset` is in, or directly after.
+    fn identifier_at(&self, offset: usize) -> Option<String> {
+        Lexer::new(&self.source)
+            .lex()
+            .into_iter()
+            .find(|t| {
+                t.kind() == TokenKind::Identifier
+                    && t.pos().start <= offset
+                    && offset <= t.pos().end
+            })
+            .map(|t| t.literal())
+    }
+
+    // The MIR of the function whose definition `offset` is in.
+    fn enclosing_function(&self, offset: usize) -> Option<&Function> {
+        let (name, _) = self.module()?.fn_defns.iter().find(|(_, (func, _))| {
+            func.location.span.start <= offset && offset <= func.location.span.end
+        })?;
+
+        self.mdir.as_ref()?.functions().get(name)
+    }
+
+    // Locals and then parameters of `function`, with where they're defined.
+    fn locals(function: &Function) -> Vec<(String, TypeValue, Location)> {
+        let locals = function.block.iter().filter_map(|stmt| match stmt {
+            Statement::Var(var) => Some((var.lhs.clone(), var.ty.clone(), var.location.clone())),
+            Statement::Expr(..) => None,
+        });
+        let params = function
+            .params
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.clone(), function.location.clone()));
+
+        locals.chain(params).collect()
+    }
+
+    // Wildcard imports of `module`, in the order the checker looks through them.
+    fn wildcards(module: &Module) -> Vec<&ImportTarget> {
+        let mut wildcards = module
+            .imports
+            .iter()
+            .flatten()
+            .filter_map(|(key, target)| match key {
+                ImportKey::Wildcard(_) => Some(target),
+                _ => None,
+            })
+            .collect::<Vec<&ImportTarget>>();
+        wildcards.sort_by(|a, b| a.path.cmp(&b.path));
+
+        wildcards
+    }
+
+    fn public_functions(&self, path: &str) -> Vec<String> {
+        let mut names = self
+            .modules
+            .get(path)
+            .map(|module| {
+                module
+                    .fn_decls
+                    .iter()
+                    .filter(|(_, (_, _, visibility))| *visibility == Visibility::Public)
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default();
+        names.sort();
+
+        names
+    }
+
+    /// What the name at `offset` refers to, resolved like the checker does.
+    pub fn resolve(&self, offset: usize) -> Option<Item> {
+        let module = self.module()?;
+
+        for (key, target) in module.imports.iter().flatten() {
+            let span = &target.location.span;
+            if span.start <= offset && offset <= span.end {
+                return Some(match key {
+                    ImportKey::Symbol(name) => {
+                        let symbol = target.symbol.clone().unwrap_or(name.clone());
+                        Item::Function(target.path.clone(), symbol)
+                    }
+                    ImportKey::Module(_) | ImportKey::Wildcard(_) => {
+                        Item::Module(target.path.clone())
+                    }
+                });
+            }
+        }
+
+        let name = self.identifier_at(offset)?;
+
+        let local = self.enclosing_function(offset).and_then(|function| {
+            Self::locals(function)
+                .into_iter()
+                .find(|(n, ..)| *n == name)
+        });
+        if let Some((name, ty, location)) = local {
+            return Some(Item::Local(name, ty, location));
+        }
+
+        let is_local_function = module.fn_decls.contains_key(&name)
+            || module.fn_defns.contains_key(&name)
+            || module.externs.contains_key(&name);
+        if is_local_function {
+            return Some(Item::Function(self.name.clone(), name));
+        }
+        if BUILTINS.contains(&name.as_str()) {
+            return Some(Item::Builtin(name));
+        }
+
+        let imports = module.imports.as_ref()?;
+        if let Some(target) = imports.get(&ImportKey::Symbol(name.clone())) {
+            let symbol = target.symbol.clone().unwrap_or(name);
+            return Some(Item::Function(target.path.clone(), symbol));
+        }
+
+        Self::wildcards(module)
+            .into_iter()
+            .find(|target| self.public_functions(&target.path).contains(&name))
+            .map(|target| Item::Function(target.path.clone(), name))
+    }
+
+    // `pub name : type` of a function of `module`, as it's declared.
+    fn signature(module: &Module, name: &str) -> Option<String> {
+        if let Some((ty, _, visibility)) = module.fn_decls.get(name) {
+            let public = match visibility {
+                Visibility::Public => "pub ",
+                Visibility::Private => "",
+            };
+            return Some(format!("{public}{name} : {}", ty.type_value));
+        }
+
+        let decl = module.externs.get(name)?;
+        let ty = TypeValue::ExFunc((decl.params.clone(), Box::new(decl.return_type.clone())));
+        Some(format!("{name} : {ty}"))
+    }
+
+    /// Markdown about the name at `offset`: its type and the docs of functions.
+    pub fn hover(&self, offset: usize) -> Option<String> {
+        let markdown = match self.resolve(offset)? {
+            Item::Local(name, ty, _) => format!("```gahl\n{name} : {ty}\n```"),
+            Item::Function(path, name) => {
+                let module = self.modules.get(&path)?;
+                let mut markdown = format!("```gahl\n{}\n```", Self::signature(module, &name)?);
+
+                for doc in module.docs.get(&name).into_iter().flatten() {
+                    markdown += &format!("\n\n{}", doc.md.trim());
+                }
+                if path != self.name {
+                    markdown += &format!("\n\nFrom `{}`.", module.path.join("."));
+                }
+                markdown
+            }
+            Item::Builtin(name) => {
+                let (signature, doc) = match name.as_str() {
+                    "assert" => ("assert(value)", "Fails when the integer `value` is 0."),
+                    _ => (
+                        "assert_eq(left, right)",
+                        "Fails when the integers or strings `left` and `right` differ.",
+                    ),
+                };
+                format!("```gahl\n{signature}\n```\n\nBuiltin. {doc}")
+            }
+            Item::Module(path) => {
+                let module = self.modules.get(&path)?;
+                let mut markdown = format!("```gahl\nimport {}\n```", module.path.join("."));
+
+                if let Some(doc) = module.doc_comments.first() {
+                    markdown += &format!("\n\n{}", doc.md.trim());
+                }
+                markdown
+            }
+        };
+
+        Some(markdown)
+    }
+
+    /// The module the name at `offset` is defined in and where.
+    pub fn definition(&self, offset: usize) -> Option<(String, Range)> {
+        let (path, location) = match self.resolve(offset)? {
+            Item::Local(_, _, location) => (self.name.clone(), location),
+            Item::Function(path, name) => {
+                let module = self.modules.get(&path)?;
+                let location = match module.fn_decls.get(&name) {
+                    Some((_, location, _)) => location.clone(),
+                    None => match module.fn_defns.get(&name) {
+                        Some((_, location)) => location.clone(),
+                        None => module.externs.get(&name)?.location.clone(),
+                    },
+                };
+                (path, location)
+            }
+            Item::Builtin(_) => return None,
+            Item::Module(path) => (path, Location::default()),
+        };
+
+        let range = match path == self.name {
+            true => range(&self.source, &location),
+            false => range(&fs::read_to_string(&path).ok()?, &location),
+        };
+        Some((path, range))
+    }
+
+    /// The functions and externs of the module, in the order they're written.
+    #[allow(deprecated)]
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let module = match self.module() {
+            Some(module) => module,
+            None => return vec![],
+        };
+
+        let declared = module
+            .fn_decls
+            .iter()
+            .map(|(name, (ty, location, _))| (name, ty.type_value.clone(), location));
+        let undeclared = module
+            .fn_defns
+            .iter()
+            .filter(|(name, _)| !module.fn_decls.contains_key(*name))
+            .map(|(name, (_, location))| (name, TypeValue::Undefined, location));
+        let externs = module.externs.iter().map(|(name, decl)| {
+            let ty = TypeValue::ExFunc((decl.params.clone(), Box::new(decl.return_type.clone())));
+            (name, ty, &decl.location)
+        });
+
+        let mut symbols = declared
+            .chain(undeclared)
+            .chain(externs)
+            .map(|(name, ty, location)| {
+                // From the declaration to the end of the definition.
+                let end = match module.fn_defns.get(name) {
+                    Some((func, _)) => func.location.span.end.max(location.span.end),
+                    None => location.span.end,
+                };
+                let deprecated = module
+                    .attributes
+                    .get(name)
+                    .is_some_and(|attrs| attrs.iter().any(|a| a.name == "deprecated"));
+
+                DocumentSymbol {
+                    name: name.clone(),
+                    detail: (ty != TypeValue::Undefined).then(|| ty.to_string()),
+                    kind: SymbolKind::FUNCTION,
+                    tags: deprecated.then(|| vec![SymbolTag::DEPRECATED]),
+                    deprecated: None,
+                    range: Range::new(
+                        position(&self.source, location.span.start),
+                        position(&self.source, end),
+                    ),
+                    selection_range: range(&self.source, location),
+                    children: None,
+                }
+            })
+            .collect::<Vec<DocumentSymbol>>();
+        symbols.sort_by_key(|symbol| symbol.range.start);
+
+        symbols
+    }
+
+    /// Names in scope at `offset`: locals, functions of the module and the ones it imports,
+    /// builtins and keywords.
+    pub fn completions(&self, offset: usize) -> Vec<CompletionItem> {
+        let mut items = vec![];
+        let item = |label: &str, kind, detail: Option<String>| CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            detail,
+            ..Default::default()
+        };
+
+        if let Some(function) = self.enclosing_function(offset) {
+            // Only locals declared before the cursor are in scope, not the one being written.
+            let in_scope = Self::locals(function)
+                .into_iter()
+                .filter(|(_, _, location)| location.span.end <= offset);
+
+            for (name, ty, _) in in_scope {
+                items.push(item(
+                    &name,
+                    CompletionItemKind::VARIABLE,
+                    Some(ty.to_string()),
+                ));
+            }
+        }
+
+        if let Some(module) = self.module() {
+            let mut functions = module
+                .fn_decls
+                .keys()
+                .chain(module.externs.keys())
+                .map(|name| (name.clone(), Self::signature(module, name)))
+                .collect::<Vec<(String, Option<String>)>>();
+
+            for (key, target) in module.imports.iter().flatten() {
+                let imported = match key {
+                    ImportKey::Symbol(name) => {
+                        vec![(name.clone(), target.symbol.clone().unwrap_or(name.clone()))]
+                    }
+                    ImportKey::Wildcard(_) => self
+                        .public_functions(&target.path)
+                        .into_iter()
+                        .map(|name| (name.clone(), name))
+                        .collect(),
+                    ImportKey::Module(_) => vec![],
+                };
+
+                let module = self.modules.get(&target.path);
+                for (name, symbol) in imported {
+                    let signature = module.and_then(|m| Self::signature(m, &symbol));
+                    functions.push((name, signature));
+                }
+            }
+
+            functions.sort();
+            for (name, signature) in functions {
+                items.push(item(&name, CompletionItemKind::FUNCTION, signature));
+            }
+        }
+
+        for builtin in BUILTINS {
+            items.push(item(builtin, CompletionItemKind::FUNCTION, None));
+        }
+        for keyword in KEYWORDS {
+            items.push(item(keyword, CompletionItemKind::KEYWORD, None));
+        }
+
+        // Locals shadow functions, which shadow builtins.
+        let mut seen = HashSet::new();
+        items.retain(|item| seen.insert(item.label.clone()));
+
+        items
+    }
+}
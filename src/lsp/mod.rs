@@ -0,0 +1,393 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    thread,
+};
+
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    DocumentFormattingParams, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeResult, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, ServerInfo, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::{config, formatter, interp::STACK_SIZE, parser::search::SearchPaths};
+
+use self::analysis::{analyze, offset, position, Analysis};
+
+pub mod analysis;
+
+/// Reads one message, `None` when the input ends.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without `Content-Length`",
+        )
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(io::Error::from)
+}
+
+fn write_message(out: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+// The compiler prints debug output here and there, which would end up between the messages. They
+// are written to a copy of stdout and anything else printed goes to stderr.
+#[cfg(unix)]
+fn protocol_output() -> io::Result<Box<dyn Write>> {
+    use std::os::fd::{AsFd, AsRawFd};
+
+    extern "C" {
+        fn dup2(old: i32, new: i32) -> i32;
+    }
+
+    let out = fs::File::from(io::stdout().as_fd().try_clone_to_owned()?);
+    if unsafe { dup2(io::stderr().as_raw_fd(), io::stdout().as_raw_fd()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(Box::new(out))
+}
+
+#[cfg(not(unix))]
+fn protocol_output() -> io::Result<Box<dyn Write>> {
+    Ok(Box::new(io::stdout()))
+}
+
+// Search paths of the project `root` is in, which becomes the current directory like it does
+// for every other command.
+fn enter_project(root: &Path) -> SearchPaths {
+    let project = root
+        .ancestors()
+        .find(|dir| dir.join("config.toml").is_file());
+
+    let config = project.and_then(|project| {
+        env::set_current_dir(project).ok()?;
+        config::parse_config().ok()
+    });
+
+    match config {
+        Some(config) => SearchPaths::new(
+            config
+                .project
+                .search_paths
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+            config.project.std_path.as_ref().map(PathBuf::from),
+        ),
+        None => SearchPaths::new(vec![], None),
+    }
+}
+
+// The project root of the workspace the client opened, if it opened one.
+fn workspace_root(params: &Value) -> Option<PathBuf> {
+    let uri = params["workspaceFolders"][0]["uri"]
+        .as_str()
+        .or_else(|| params["rootUri"].as_str())?;
+
+    Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+fn params<T: DeserializeOwned>(message: &Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(message["params"].clone()).map_err(|err| (-32602, err.to_string()))
+}
+
+struct Server {
+    out: Box<dyn Write>,
+    search_paths: SearchPaths,
+    /// Open documents, by their URI.
+    documents: HashMap<Url, Analysis>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn send(&mut self, message: Value) {
+        if let Err(err) = write_message(&mut self.out, &message) {
+            eprintln!("Error writing to the client: {}", err);
+            exit(1);
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    // Module name of the document at `uri`: its path from the project root, like imports.
+    fn module_name(uri: &Url) -> String {
+        let path = uri
+            .to_file_path()
+            .unwrap_or_else(|_| PathBuf::from(uri.path()));
+
+        match env::current_dir().map(|cwd| path.strip_prefix(cwd).map(Path::to_path_buf)) {
+            Ok(Ok(relative)) => relative.to_string_lossy().into_owned(),
+            _ => path.to_string_lossy().into_owned(),
+        }
+    }
+
+    // URI of the module `name`, the open document at `uri` itself or a file on disk.
+    fn module_uri(uri: &Url, analysis: &Analysis, name: &str) -> Option<Url> {
+        if name == analysis.name {
+            return Some(uri.clone());
+        }
+
+        let path = fs::canonicalize(name).ok()?;
+        Url::from_file_path(path).ok()
+    }
+
+    fn update(&mut self, uri: Url, source: String) {
+        let name = Self::module_name(&uri);
+        let analysis = analyze(&name, source, self.search_paths.clone());
+
+        let diagnostics =
+            PublishDiagnosticsParams::new(uri.clone(), analysis.diagnostics.clone(), None);
+        self.documents.insert(uri, analysis);
+        self.notify("textDocument/publishDiagnostics", json!(diagnostics));
+    }
+
+    fn document(&self, uri: &Url) -> Result<&Analysis, (i64, String)> {
+        self.documents
+            .get(uri)
+            .ok_or_else(|| (-32602, format!("`{uri}` isn't open")))
+    }
+
+    fn request(&mut self, method: &str, message: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => {
+                if let Some(root) = workspace_root(&message["params"]) {
+                    self.search_paths = enter_project(&root);
+                }
+
+                let result = InitializeResult {
+                    capabilities: capabilities(),
+                    server_info: Some(ServerInfo {
+                        name: "gahl".to_string(),
+                        version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                    }),
+                };
+                Ok(json!(result))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => {
+                let params: HoverParams = params(message)?;
+                let at = params.text_document_position_params;
+                let analysis = self.document(&at.text_document.uri)?;
+
+                let hover = analysis
+                    .hover(offset(&analysis.source, at.position))
+                    .map(|markdown| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: markdown,
+                        }),
+                        range: None,
+                    });
+                Ok(json!(hover))
+            }
+            "textDocument/definition" => {
+                let params: GotoDefinitionParams = params(message)?;
+                let at = params.text_document_position_params;
+                let uri = at.text_document.uri;
+                let analysis = self.document(&uri)?;
+
+                let location = analysis
+                    .definition(offset(&analysis.source, at.position))
+                    .and_then(|(name, range)| {
+                        let uri = Self::module_uri(&uri, analysis, &name)?;
+                        Some(GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+                    });
+                Ok(json!(location))
+            }
+            "textDocument/documentSymbol" => {
+                let params: DocumentSymbolParams = params(message)?;
+                let analysis = self.document(&params.text_document.uri)?;
+
+                Ok(json!(DocumentSymbolResponse::Nested(analysis.symbols())))
+            }
+            "textDocument/completion" => {
+                let params: CompletionParams = params(message)?;
+                let at = params.text_document_position;
+                let analysis = self.document(&at.text_document.uri)?;
+
+                let items = analysis.completions(offset(&analysis.source, at.position));
+                Ok(json!(CompletionResponse::Array(items)))
+            }
+            "textDocument/formatting" => {
+                let params: DocumentFormattingParams = params(message)?;
+                let source = &self.document(&params.text_document.uri)?.source;
+
+                // The whole document is replaced, unless it's formatted already.
+                let edits = match formatter::format(source) {
+                    Ok(formatted) if formatted != *source => {
+                        let end = position(source, source.chars().count());
+                        let range = Range::new(Position::new(0, 0), end);
+                        vec![TextEdit::new(range, formatted)]
+                    }
+                    Ok(_) => vec![],
+                    Err(err) => return Err((-32603, err)),
+                };
+                Ok(json!(edits))
+            }
+            _ => Err((-32601, format!("`{method}` isn't supported"))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, message: &Value) -> Result<(), (i64, String)> {
+        match method {
+            "exit" => exit(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let params: DidOpenTextDocumentParams = params(message)?;
+                self.update(params.text_document.uri, params.text_document.text);
+            }
+            "textDocument/didChange" => {
+                let params: DidChangeTextDocumentParams = params(message)?;
+                // Changes are always the whole text, see `capabilities`.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update(params.text_document.uri, change.text);
+                }
+            }
+            // Modules it imports may have changed on disk.
+            "textDocument/didSave" => {
+                let params: DidSaveTextDocumentParams = params(message)?;
+                let uri = params.text_document.uri;
+                if let Some(analysis) = self.documents.get(&uri) {
+                    let source = analysis.source.clone();
+                    self.update(uri, source);
+                }
+            }
+            "textDocument/didClose" => {
+                let params: DidCloseTextDocumentParams = params(message)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+
+                let diagnostics = PublishDiagnosticsParams::new(uri, vec![], None);
+                self.notify("textDocument/publishDiagnostics", json!(diagnostics));
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, message: Value) {
+        let method = match message["method"].as_str() {
+            Some(method) => method.to_string(),
+            // Responses to requests of the server, which doesn't send any.
+            None => return,
+        };
+
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                if let Err((_, err)) = self.notification(&method, &message) {
+                    eprintln!("Error in `{method}`: {err}");
+                }
+                return;
+            }
+        };
+
+        let response = match self.request(&method, &message) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, err)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": err },
+            }),
+        };
+        self.send(response);
+    }
+}
+
+fn serve() {
+    let out = match protocol_output() {
+        Ok(out) => out,
+        Err(err) => {
+            eprintln!("Error setting up stdout: {}", err);
+            exit(1);
+        }
+    };
+    let mut server = Server {
+        out,
+        search_paths: SearchPaths::new(vec![], None),
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    loop {
+        match read_message(&mut input) {
+            Ok(Some(message)) => server.handle(message),
+            Ok(None) => exit(if server.shutdown { 0 } else { 1 }),
+            Err(err) => {
+                eprintln!("Error reading from the client: {}", err);
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Runs a language server on stdin and stdout until the client exits.
+pub fn run() {
+    let handle = thread::Builder::new().stack_size(STACK_SIZE).spawn(serve);
+
+    match handle {
+        Ok(handle) => {
+            let _ = handle.join();
+        }
+        Err(err) => {
+            eprintln!("Error starting the language server: {}", err);
+            exit(1);
+        }
+    }
+}
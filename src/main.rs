@@ -1,18 +1,24 @@
 use std::{
     collections::HashMap,
-    env::args,
+    env::set_current_dir,
     fs::{self, DirBuilder, File},
     io::Write,
+    path::PathBuf,
     process::{exit, Command},
     sync::Arc,
 };
 
 use ast::Module;
-use checker::analyzer::Analyzer;
+use checker::{
+    analyzer::Analyzer,
+    mdir::{pretty::mdir_to_string, MiddleIR},
+};
 use clap::Parser;
-use cli::{Args, SubCommand};
-use codegen::compile;
-use parser::Parser as GahlParser;
+use cli::{Args, Backend, Emit, ProfileArgs, SubCommand};
+use codegen::{artifact_path, clang::default_jobs, compile, target::Target, BuildOptions};
+use config::{Config, MemoryMode, Profile};
+use lexer::Lexer;
+use parser::{search::SearchPaths, Parser as GahlParser};
 
 pub mod ast;
 pub mod checker;
@@ -20,20 +26,167 @@ mod cli;
 pub mod codegen;
 pub mod config;
 pub mod docgen;
+pub mod formatter;
+pub mod interp;
 pub mod lexer;
+pub mod lsp;
 pub mod parser;
+pub mod repl;
+pub mod testing;
 
-fn main() {
-    let args = Args::parse();
+/// Enters the project root and parses its `config.toml`, exiting on failure.
+fn load_config() -> Config {
+    // Every path from here on is relative to the project root, wherever `gahl` was run from.
+    if let Ok(root) = config::find_project_root() {
+        if let Err(err) = set_current_dir(&root) {
+            eprintln!("Error entering project root `{}`: {}", root.display(), err);
+            exit(1);
+        }
+    }
 
-    let config = match config::parse_config() {
+    match config::parse_config() {
         Ok(c) => c,
         Err(err) => {
             eprintln!("Error parsing `config.toml` file: {}", err);
             exit(1);
         }
+    }
+}
+
+/// Parses every module of the project without checking it, exiting on failure.
+fn parse_project(config: &Config) -> (HashMap<String, Module>, SearchPaths) {
+    let entry_file = &config.project.exec_entry;
+
+    let search_paths = SearchPaths::new(
+        config.project.search_paths.iter().map(PathBuf::from).collect(),
+        config.project.std_path.as_ref().map(PathBuf::from),
+    );
+    let mut parser = GahlParser::new(entry_file, search_paths.clone());
+
+    match parser.parse(entry_file) {
+        Ok(modules) => (modules, search_paths),
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    }
+}
+
+/// Writes the output of a stage for `module` next to the other build artifacts.
+fn write_artifact(module: &str, extension: &str, contents: &str) {
+    let path = artifact_path(module, extension);
+
+    if let Err(err) = fs::write(&path, contents) {
+        eprintln!("Error writing `{}`: {}", path, err);
+        exit(1);
+    }
+}
+
+/// Parses `entry_file` and everything it imports, then checks all of it. The stages in `emit` up
+/// to the MIR are written to `build/`. Exits when there are errors.
+fn analyze(entry_file: &str, search_paths: SearchPaths, emit: &[Emit]) -> HashMap<String, MiddleIR> {
+    let mut parser = GahlParser::new(entry_file, search_paths);
+
+    let modules = match parser.parse(entry_file) {
+        Ok(m) => m,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
     };
 
+    if !emit.is_empty() {
+        if let Err(err) = fs::create_dir_all("build") {
+            eprintln!("Error creating `build` directory: {}", err);
+            exit(1);
+        }
+    }
+
+    for (name, module) in &modules {
+        if emit.contains(&Emit::Tokens) {
+            let source = fs::read_to_string(name).unwrap_or_default();
+            let tokens = Lexer::new(&source)
+                .lex()
+                .iter()
+                .map(|t| format!("{t}\n"))
+                .collect::<String>();
+            write_artifact(name, "tokens", &tokens);
+        }
+        if emit.contains(&Emit::Ast) {
+            write_artifact(name, "ast", &ast::pretty::module_to_string(module));
+        }
+    }
+
+    let modules = Arc::new(
+        modules
+            .into_iter()
+            .map(|(n, m)| (n.clone(), Arc::new(m)))
+            .collect::<HashMap<String, Arc<Module>>>(),
+    );
+
+    let mut analyzer = Analyzer::new();
+    let order = parser.graph().topological_order();
+    let modules = match analyzer.analyze(modules.clone(), &order) {
+        Ok(m) => {
+            println!("Analyzer finished!");
+            m
+        }
+        Err(_) => {
+            eprintln!("Analyzer finished with errors!");
+            exit(1);
+        }
+    };
+
+    if emit.contains(&Emit::Mir) {
+        for (name, mdir) in &modules {
+            write_artifact(name, "mir", &mdir_to_string(name, mdir));
+        }
+    }
+
+    modules
+}
+
+/// Runs `main` of the checked program in the interpreter and exits with its exit code.
+fn run_interpreter(modules: HashMap<String, MiddleIR>, profile: &Profile, args: Vec<String>) -> ! {
+    match interp::run(modules, profile.overflow_checks, args) {
+        Ok(code) => exit(code),
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(101);
+        }
+    }
+}
+
+/// Profile and search paths for code outside of a project. A `config.toml` in the current
+/// directory still sets them.
+fn standalone_config(profile_args: &ProfileArgs) -> (Profile, SearchPaths) {
+    let config = config::parse_config().ok();
+
+    let profile = match &config {
+        Some(config) => match Profile::resolve(config, profile_args.release) {
+            Ok(p) => p,
+            Err(err) => {
+                eprintln!("Error in `config.toml`: {}", err);
+                exit(1);
+            }
+        },
+        None if profile_args.release => Profile::release(MemoryMode::default()),
+        None => Profile::dev(MemoryMode::default()),
+    };
+    let search_paths = match &config {
+        Some(config) => SearchPaths::new(
+            config.project.search_paths.iter().map(PathBuf::from).collect(),
+            config.project.std_path.as_ref().map(PathBuf::from),
+        ),
+        None => SearchPaths::new(vec![], None),
+    };
+
+    (profile, search_paths)
+}
+
+fn main() {
+    let args = Args::parse();
+
     match args.subcmd {
         SubCommand::New { project_name } => {
             match DirBuilder::new().create(&project_name) {
@@ -52,7 +205,7 @@ fn main() {
                 }
             };
 
-            let main_contents = "import {}\n\nmain : fn() void\nmain = fn() {}\n";
+            let main_contents = "import {\n  std.io.println\n}\n\nmain : fn() void\nmain = fn() {\n  println(\"Hello, world!\")\n}\n";
 
             match main_file.write_all(main_contents.as_bytes()) {
                 Ok(_) => (),
@@ -70,7 +223,9 @@ fn main() {
                 }
             };
 
-            let config_contents = "[project]\nname=\"\"\nauthor=\"\"\nexec_entry=\"\"\n";
+            let config_contents = format!(
+                "[project]\nname=\"{project_name}\"\nauthor=\"\"\nexec_entry=\"main.gh\"\n\n[build]\nmemory=\"gc\"\n"
+            );
 
             match config_file.write_all(config_contents.as_bytes()) {
                 Ok(_) => (),
@@ -82,30 +237,94 @@ fn main() {
 
             println!("Project \x1b[1m\x1b[33m`{project_name}`\x1b[0m created successfully!");
         }
-        SubCommand::Build | SubCommand::Run => {
-            let entry_file = &config.project.exec_entry;
+        SubCommand::Build { .. } | SubCommand::Run { .. } => {
+            let config = load_config();
 
-            let mut parser = GahlParser::new(&entry_file);
+            let (target, emit, profile_args, codegen_args, interp) = match &args.subcmd {
+                SubCommand::Build {
+                    target,
+                    emit,
+                    profile,
+                    codegen,
+                } => (target.clone(), emit.clone(), profile.clone(), codegen.clone(), false),
+                // The interpreter needs nothing written to `build/`.
+                SubCommand::Run {
+                    profile,
+                    codegen,
+                    interp,
+                } => {
+                    let emit = if *interp { vec![] } else { vec![Emit::Exe] };
+                    (None, emit, profile.clone(), codegen.clone(), *interp)
+                }
+                _ => unreachable!(),
+            };
 
-            let modules = parser.parse(&entry_file);
-            let modules = Arc::new(
-                modules
-                    .into_iter()
-                    .map(|(n, m)| (n.clone(), Arc::new(m)))
-                    .collect::<HashMap<String, Arc<Module>>>(),
-            );
+            if codegen_args.backend == Backend::Llvm && !cfg!(feature = "llvm") {
+                eprintln!("`--backend llvm` needs gahl built with `--features llvm`.");
+                exit(1);
+            }
+            let target = target.or(config.build.target.clone());
+            let target = match target {
+                Some(triple) => match Target::from_triple(&triple) {
+                    Ok(t) => t,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        exit(1);
+                    }
+                },
+                None => Target::host(),
+            };
+            if target.is_wasm() && !interp {
+                let error = if codegen_args.backend != Backend::Clang {
+                    Some("`--target wasm32` has a backend of its own, drop `--backend`.")
+                } else if emit.contains(&Emit::LlvmIr) {
+                    Some("`--emit=llvm-ir` isn't available for wasm32, `asm` writes the text format.")
+                } else if let SubCommand::Run { .. } = args.subcmd {
+                    Some("`gahl run` can't execute wasm32, load `build/<project>.wasm` in a wasm host.")
+                } else {
+                    None
+                };
 
-            let mut analyzer = Analyzer::new();
-            let modules = match analyzer.analyze(modules.clone()) {
-                Ok(m) => {
-                    println!("Analyzer finished!");
-                    m
+                if let Some(error) = error {
+                    eprintln!("{error}");
+                    exit(1);
                 }
-                Err(_) => {
-                    eprintln!("Analyzer finished with errors!");
+            }
+
+            let mut profile = match Profile::resolve(&config, profile_args.release) {
+                Ok(p) => p,
+                Err(err) => {
+                    eprintln!("Error in `config.toml`: {}", err);
                     exit(1);
                 }
             };
+            if let Some(opt_level) = profile_args.opt_level {
+                profile.opt_level = opt_level;
+            }
+
+            let entry_file = &config.project.exec_entry;
+
+            let search_paths = SearchPaths::new(
+                config.project.search_paths.iter().map(PathBuf::from).collect(),
+                config.project.std_path.as_ref().map(PathBuf::from),
+            );
+            let memory = profile.memory;
+            let runtimes = [
+                Some(search_paths.std.join("runtime.c")).filter(|p| p.is_file()),
+                memory.runtime(&search_paths.std),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<PathBuf>>();
+            let modules = analyze(entry_file, search_paths, &emit);
+
+            if interp {
+                run_interpreter(modules, &profile, vec![config.project.name.clone()]);
+            }
+
+            if !emit.iter().any(|e| e.needs_codegen()) {
+                return;
+            }
 
             let mut libs: Vec<String> = vec![];
             if let Some(clibs) = config.clibs {
@@ -114,15 +333,116 @@ fn main() {
                 }
             }
 
-            compile(modules, libs, &config.project.name);
-            
-            if let SubCommand::Run = args.subcmd {
+            let options = BuildOptions {
+                profile,
+                target,
+                emit,
+                jobs: codegen_args.jobs.unwrap_or_else(default_jobs),
+                backend: codegen_args.backend,
+            };
+
+            if let Err(errors) = compile(modules, libs, runtimes, options, &config.project.name) {
+                for error in errors {
+                    eprintln!("{}", error);
+                }
+                eprintln!("\nBuild failed!");
+                exit(1);
+            }
+
+            if let SubCommand::Run { .. } = args.subcmd {
                 let path = format!("./build/{}", config.project.name);
                 println!("\nRunning: {path}\n");
                 Command::new(path).status().unwrap();
             }
         }
-        _ => todo!(),
+        SubCommand::Check => {
+            let config = load_config();
+
+            let search_paths = SearchPaths::new(
+                config.project.search_paths.iter().map(PathBuf::from).collect(),
+                config.project.std_path.as_ref().map(PathBuf::from),
+            );
+
+            // Stops after the checker, so neither clang nor the C libraries are needed.
+            analyze(&config.project.exec_entry, search_paths, &[]);
+        }
+        SubCommand::Doc => {
+            let config = load_config();
+            let (modules, _) = parse_project(&config);
+
+            if let Err(err) = fs::create_dir_all("build/doc") {
+                eprintln!("Error creating `build/doc` directory: {}", err);
+                exit(1);
+            }
+            for page in docgen::generate(&modules) {
+                let path = format!("build/doc/{}", page.file);
+
+                if let Err(err) = fs::write(&path, page.contents) {
+                    eprintln!("Error writing `{}`: {}", path, err);
+                    exit(1);
+                }
+            }
+
+            println!("Documentation written to `build/doc/index.html`.");
+        }
+        SubCommand::Test {
+            filter,
+            doc,
+            harness,
+            profile: profile_args,
+        } => {
+            let config = load_config();
+            let profile = match Profile::resolve(&config, profile_args.release) {
+                Ok(p) => p,
+                Err(err) => {
+                    eprintln!("Error in `config.toml`: {}", err);
+                    exit(1);
+                }
+            };
+            let (modules, search_paths) = parse_project(&config);
+
+            if let Some(name) = harness {
+                let code = testing::harness(&name, modules, search_paths, profile.overflow_checks);
+                exit(code);
+            }
+
+            if !testing::run(modules, filter.as_deref(), doc, profile_args.release) {
+                exit(1);
+            }
+        }
+        SubCommand::Eval {
+            file,
+            profile: profile_args,
+            args: program_args,
+        } => {
+            let (profile, search_paths) = standalone_config(&profile_args);
+            let modules = analyze(&file, search_paths, &[]);
+
+            let mut args = vec![file.clone()];
+            args.extend(program_args.iter().cloned());
+            run_interpreter(modules, &profile, args);
+        }
+        SubCommand::Repl {
+            profile: profile_args,
+        } => {
+            let (profile, search_paths) = standalone_config(&profile_args);
+            repl::run(search_paths, profile.overflow_checks);
+        }
+        SubCommand::Fmt { files, check } => {
+            let ok = match files.as_slice() {
+                [stdin] if stdin == "-" => formatter::format_stdin(check),
+                [] => {
+                    load_config();
+                    formatter::format_files(&formatter::project_files(), check)
+                }
+                files => formatter::format_files(files, check),
+            };
+
+            if !ok {
+                exit(1);
+            }
+        }
+        SubCommand::Lsp => lsp::run(),
     };
 
     //     let mut args: Vec<String> = vec!["-o", "out", "out.o"]
@@ -135,10 +455,6 @@ fn main() {
     //     let mut command = Command::new("clang");
     //     command.args(args);
     //     command.status().unwrap();
-    // } else if subcommand == "doc" || subcommand == "d" {
-    //     let docs = gen_docs(mdir);
-
-    //     println!("Docs:\n{}", docs);
     // } else {
     //     println!("Invalid subcommand: `{subcommand}`");
     //     exit(1);
@@ -60,6 +60,10 @@ pub fn _type(input: &mut Input) -> ParseResult<Type> {
             input.eat();
             TypeValue::I128
         }
+        TK::Tisize => {
+            input.eat();
+            TypeValue::Isize
+        }
         TK::Tu8 => {
             input.eat();
             TypeValue::U8
@@ -80,6 +84,10 @@ pub fn _type(input: &mut Input) -> ParseResult<Type> {
             input.eat();
             TypeValue::U128
         }
+        TK::Tusize => {
+            input.eat();
+            TypeValue::Usize
+        }
         TK::Tf32 => {
             input.eat();
             TypeValue::F32
@@ -0,0 +1,100 @@
+use crate::{
+    ast::{Attribute, Location},
+    lexer::token::TokenKind as TK,
+};
+
+use super::{
+    error::{ParseError, ParseResult},
+    Input,
+};
+
+// Parses the `@name` or `@name("arg", ...)` attributes in front of a declaration.
+pub fn attributes(input: &mut Input) -> ParseResult<Vec<Attribute>> {
+    let mut product: Vec<Attribute> = vec![];
+    let mut errors: Vec<ParseError> = vec![];
+
+    while let Some(t) = input.peek() {
+        if t.kind() != TK::At {
+            break;
+        }
+
+        let (attribute, mut attribute_errors, is_eof) = attribute(input);
+
+        errors.append(&mut attribute_errors);
+        product.extend(attribute);
+
+        if is_eof {
+            return (product, errors, true);
+        }
+    }
+
+    (product, errors, input.peek().is_none())
+}
+
+fn attribute(input: &mut Input) -> ParseResult<Option<Attribute>> {
+    let (start, start_row) = {
+        let tok = input.eat().unwrap();
+        (tok.pos(), tok.row_col().0)
+    };
+
+    let name = match input.peek() {
+        Some(t) if t.kind() == TK::Identifier => input.eat().unwrap().literal(),
+        Some(t) => {
+            let location = Location::new(t.pos(), (t.row_col().0, t.row_col().0));
+            let message = format!(
+                "Expected an attribute name after `@` but found `{:?}`.",
+                t.kind()
+            );
+            return (None, vec![ParseError::new(message, location)], false);
+        }
+        None => {
+            let location = Location::new(start, (start_row, start_row));
+            let message = "Expected an attribute name after `@`.".to_string();
+            return (None, vec![ParseError::new(message, location)], true);
+        }
+    };
+
+    let mut args: Vec<String> = vec![];
+    let mut errors: Vec<ParseError> = vec![];
+
+    if let Some(t) = input.peek() {
+        if t.kind() == TK::OpenParen {
+            input.eat();
+
+            loop {
+                match input.peek() {
+                    Some(t) if t.kind() == TK::ClosedParen => {
+                        input.eat();
+                        break;
+                    }
+                    Some(t) if t.kind() == TK::Comma => {
+                        input.eat();
+                    }
+                    Some(t) if t.kind() == TK::String => {
+                        let literal = input.eat().unwrap().literal();
+                        args.push(literal.trim_matches('"').to_string());
+                    }
+                    Some(t) => {
+                        let location = Location::new(t.pos(), (t.row_col().0, t.row_col().0));
+                        let message = format!(
+                            "Attribute arguments must be strings, found `{:?}`.",
+                            t.kind()
+                        );
+                        errors.push(ParseError::new(message, location));
+                        input.eat();
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    let location = Location::new(start.start..input.prev_pos.end, (start_row, input.prev_row));
+    let attribute = Attribute {
+        name,
+        args,
+        location,
+    };
+
+    (Some(attribute), errors, input.peek().is_none())
+}
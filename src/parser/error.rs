@@ -1,3 +1,5 @@
+use core::fmt;
+
 use crate::ast::Location;
 
 #[derive(Debug)]
@@ -14,3 +16,50 @@ impl ParseError {
 
 /// (the result of the parse, the errors generated, if we have readched eof (for cascading error)).
 pub type ParseResult<Product> = (Product, Vec<ParseError>, bool);
+
+/// Failures while discovering the modules of a project, before any single file is parsed.
+#[derive(Debug)]
+pub enum ModuleError {
+    /// An imported (or the entry) file could not be read.
+    Unreadable {
+        path: String,
+        reason: String,
+        imported_from: Option<(String, Location)>,
+    },
+    /// Modules import each other, `chain` starts and ends with the same module.
+    Cycle {
+        chain: Vec<String>,
+        imported_from: (String, Location),
+    },
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::Unreadable {
+                path,
+                reason,
+                imported_from,
+            } => {
+                write!(f, "\x1b[31mCould not read module `{path}`: {reason}\x1b[0m")?;
+
+                if let Some((module, location)) = imported_from {
+                    write!(f, "\n  imported from {module}:{}", location.rows.0 + 1)?;
+                }
+
+                Ok(())
+            }
+            ModuleError::Cycle {
+                chain,
+                imported_from: (module, location),
+            } => {
+                write!(
+                    f,
+                    "\x1b[31mImport cycle detected: {}\x1b[0m\n  closed by the import in {module}:{}",
+                    chain.join(" -> "),
+                    location.rows.0 + 1
+                )
+            }
+        }
+    }
+}
@@ -258,7 +258,10 @@ fn factor(input: &mut Input) -> ParseResult<Expr> {
 }
 
 pub fn function_expr(input: &mut Input) -> ParseResult<FuncNode> {
-    input.eat().unwrap();
+    let (start, start_row) = {
+        let tok = input.eat().unwrap();
+        (tok.pos().start, tok.row_col().0)
+    };
 
     let mut product = FuncNode::default();
 
@@ -303,10 +306,10 @@ pub fn function_expr(input: &mut Input) -> ParseResult<FuncNode> {
 
     match input.peek() {
         Some(t) if t.kind() == TK::OpenCurly => {
-            let (block, errors, is_eof) = block(input);
+            let (block, mut errors, is_eof) = block(input);
             product.block = block;
+            product.errors.append(&mut errors);
 
-            // Add errors.
             if is_eof {
                 return (product, vec![], is_eof);
             }
@@ -315,6 +318,9 @@ pub fn function_expr(input: &mut Input) -> ParseResult<FuncNode> {
         None => return (product, vec![], true),
     };
 
+    // From `fn` to the end of the body.
+    product.location = Location::new(start..input.prev_pos.end, (start_row, input.prev_row));
+
     (product, vec![], false)
 }
 
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::ast::Location;
+
+/// The modules reachable from the entry file and the imports between them.
+///
+/// Nodes are keyed by canonical path so that a file is only ever parsed once, no matter how
+/// the imports spell it. Each node remembers the name its module was registered under.
+#[derive(Debug, Default)]
+pub struct ModuleGraph {
+    root: Option<PathBuf>,
+    nodes: HashMap<PathBuf, ModuleNode>,
+}
+
+#[derive(Debug)]
+pub struct ModuleNode {
+    pub name: String,
+    /// (canonical path of the dependency, location of the import in this module).
+    pub deps: Vec<(PathBuf, Location)>,
+}
+
+impl ModuleGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn canonical(path: &str) -> Option<PathBuf> {
+        Path::new(path).canonicalize().ok()
+    }
+
+    pub fn set_root(&mut self, root: PathBuf) {
+        self.root = Some(root);
+    }
+
+    pub fn contains(&self, key: &Path) -> bool {
+        self.nodes.contains_key(key)
+    }
+
+    pub fn name_of(&self, key: &Path) -> Option<&String> {
+        self.nodes.get(key).map(|n| &n.name)
+    }
+
+    pub fn insert(&mut self, key: PathBuf, name: String) {
+        self.nodes
+            .entry(key)
+            .or_insert(ModuleNode { name, deps: vec![] });
+    }
+
+    pub fn add_edge(&mut self, from: &Path, to: PathBuf, location: Location) {
+        if let Some(node) = self.nodes.get_mut(from) {
+            node.deps.push((to, location));
+        }
+    }
+
+    pub fn nodes(&self) -> &HashMap<PathBuf, ModuleNode> {
+        &self.nodes
+    }
+
+    /// Returns the first import cycle found, as the chain of module names that closes it
+    /// (`a -> b -> a`) together with the location of the import that closes the loop.
+    pub fn find_cycle(&self) -> Option<(Vec<String>, Location)> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Visiting,
+            Done,
+        }
+
+        fn visit(
+            graph: &ModuleGraph,
+            key: &PathBuf,
+            marks: &mut HashMap<PathBuf, Mark>,
+            stack: &mut Vec<PathBuf>,
+        ) -> Option<(Vec<String>, Location)> {
+            marks.insert(key.clone(), Mark::Visiting);
+            stack.push(key.clone());
+
+            for (dep, location) in &graph.nodes[key].deps {
+                match marks.get(dep) {
+                    Some(Mark::Visiting) => {
+                        let start = stack.iter().position(|k| k == dep).unwrap();
+                        let mut chain = stack[start..]
+                            .iter()
+                            .map(|k| graph.nodes[k].name.clone())
+                            .collect::<Vec<String>>();
+                        chain.push(graph.nodes[dep].name.clone());
+
+                        return Some((chain, location.clone()));
+                    }
+                    Some(Mark::Done) => (),
+                    None if graph.nodes.contains_key(dep) => {
+                        if let Some(cycle) = visit(graph, dep, marks, stack) {
+                            return Some(cycle);
+                        }
+                    }
+                    None => (),
+                }
+            }
+
+            stack.pop();
+            marks.insert(key.clone(), Mark::Done);
+            None
+        }
+
+        let mut marks = HashMap::new();
+        let mut stack = vec![];
+
+        let mut keys = self.root.iter().cloned().collect::<Vec<PathBuf>>();
+        let mut rest = self.nodes.keys().cloned().collect::<Vec<PathBuf>>();
+        rest.sort();
+        keys.append(&mut rest);
+
+        for key in keys {
+            if !marks.contains_key(&key) {
+                if let Some(cycle) = visit(self, &key, &mut marks, &mut stack) {
+                    return Some(cycle);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Module names ordered so that every module comes after the modules it imports.
+    /// Only meaningful on an acyclic graph, modules taking part in a cycle are appended last.
+    pub fn topological_order(&self) -> Vec<String> {
+        let mut in_degree = self
+            .nodes
+            .keys()
+            .map(|k| (k.clone(), 0usize))
+            .collect::<HashMap<PathBuf, usize>>();
+        let mut dependents: HashMap<&PathBuf, Vec<&PathBuf>> = HashMap::new();
+
+        for (key, node) in &self.nodes {
+            for (dep, _) in &node.deps {
+                if self.nodes.contains_key(dep) {
+                    *in_degree.get_mut(key).unwrap() += 1;
+                    dependents.entry(dep).or_default().push(key);
+                }
+            }
+        }
+
+        let mut ready = in_degree
+            .iter()
+            .filter(|(_, d)| **d == 0)
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<PathBuf>>();
+        ready.sort();
+
+        let mut order = vec![];
+        while let Some(key) = ready.pop() {
+            if let Some(users) = dependents.get(&key) {
+                for user in users {
+                    let degree = in_degree.get_mut(*user).unwrap();
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.push((*user).clone());
+                    }
+                }
+            }
+
+            order.push(key);
+        }
+
+        let mut remaining = in_degree
+            .into_iter()
+            .filter(|(k, _)| !order.contains(k))
+            .map(|(k, _)| k)
+            .collect::<Vec<PathBuf>>();
+        remaining.sort();
+        order.append(&mut remaining);
+
+        order.iter().map(|k| self.nodes[k].name.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A graph of `(name, imports)`, keyed by the module names themselves.
+    fn graph(modules: &[(&str, &[&str])]) -> ModuleGraph {
+        let mut graph = ModuleGraph::new();
+        graph.set_root(PathBuf::from(modules[0].0));
+
+        for (name, _) in modules {
+            graph.insert(PathBuf::from(name), name.to_string());
+        }
+        for (name, deps) in modules {
+            for dep in *deps {
+                graph.add_edge(Path::new(name), PathBuf::from(dep), Location::default());
+            }
+        }
+
+        graph
+    }
+
+    fn position(order: &[String], name: &str) -> usize {
+        order.iter().position(|n| n == name).unwrap()
+    }
+
+    #[test]
+    fn diamond_has_no_cycle() {
+        let graph = graph(&[
+            ("main", &["left", "right"]),
+            ("left", &["base"]),
+            ("right", &["base"]),
+            ("base", &[]),
+        ]);
+
+        assert!(graph.find_cycle().is_none());
+
+        let order = graph.topological_order();
+        assert_eq!(order.len(), 4);
+        assert!(position(&order, "base") < position(&order, "left"));
+        assert!(position(&order, "base") < position(&order, "right"));
+        assert!(position(&order, "left") < position(&order, "main"));
+        assert!(position(&order, "right") < position(&order, "main"));
+    }
+
+    #[test]
+    fn three_module_cycle() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+
+        let (chain, _) = graph.find_cycle().unwrap();
+        assert_eq!(chain, ["a", "b", "c", "a"]);
+
+        // Modules of a cycle are still all in the order, after the rest.
+        assert_eq!(graph.topological_order().len(), 3);
+    }
+
+    #[test]
+    fn self_import() {
+        let graph = graph(&[("main", &["util"]), ("util", &["util"])]);
+
+        let (chain, _) = graph.find_cycle().unwrap();
+        assert_eq!(chain, ["util", "util"]);
+        assert_eq!(
+            graph.topological_order(),
+            ["main", "util"].map(String::from)
+        );
+    }
+}
@@ -1,35 +1,27 @@
-use std::{
-    collections::HashMap,
-    sync::{
-        atomic::Ordering::SeqCst,
-        mpsc::{channel, TryRecvError},
-    },
-    thread,
-};
+use std::collections::HashMap;
 
 use crate::{
-    ast::{Import, ImportKey, Imports, Location},
-    lexer::token::TokenKind as TK,
+    ast::{Import, ImportKey, ImportTarget, Imports, Location, Name},
+    lexer::{token::TokenKind as TK, Lexer},
 };
 
 use super::{
     error::{ParseError, ParseResult},
     name::name,
-    seek_file, Input,
+    search::seek_file,
+    Input,
 };
 
 // Parses the import block atop a file.
 pub fn imports(
     input: &mut Input,
     is_first_import: bool,
-) -> ParseResult<HashMap<ImportKey, Option<String>>> {
-    let mut imports = Imports::default();
-    let mut product = HashMap::<ImportKey, Option<String>>::new();
-    let mut errors: Vec<ParseError> = vec![];
+) -> ParseResult<HashMap<ImportKey, ImportTarget>> {
+    let mut product = HashMap::<ImportKey, ImportTarget>::new();
 
-    let (first_kind, _first_pos, _first_row) = match input.peek() {
-        Some(t) => (t.kind(), t.pos(), t.row_col().0),
-        None => return (product, errors, true),
+    let first_kind = match input.peek() {
+        Some(t) => t.kind(),
+        None => return (product, vec![], true),
     };
 
     if is_first_import {
@@ -44,6 +36,57 @@ pub fn imports(
         }
     }
 
+    let (imports, mut errors, is_eof) = import_group(input);
+
+    let root = Name::new(vec![], Location::default());
+    for import in &imports.imports {
+        resolve_import(input, &root, import, &mut product, &mut errors);
+    }
+
+    (product, errors, is_eof)
+}
+
+/// Splits a leading import block off `source`, returns what's between its braces and the rest.
+pub fn split_import_block(source: &str) -> (Vec<String>, String) {
+    let tokens = Lexer::new(source).lex();
+
+    if tokens.first().map(|t| t.kind()) != Some(TK::KwImport) {
+        return (vec![], source.to_string());
+    }
+
+    let mut depth = 0;
+    let mut open = None;
+    for token in &tokens {
+        match token.kind() {
+            TK::OpenCurly => {
+                depth += 1;
+                open.get_or_insert(token.pos().end);
+            }
+            TK::ClosedCurly if depth == 1 => {
+                let chars = source.chars().collect::<Vec<char>>();
+                let start = open.unwrap_or_default();
+                let inner = chars[start..token.pos().start].iter().collect::<String>();
+                let rest = chars[token.pos().end..].iter().collect::<String>();
+
+                let imports = match inner.trim() {
+                    "" => vec![],
+                    inner => vec![inner.to_string()],
+                };
+                return (imports, rest);
+            }
+            TK::ClosedCurly => depth -= 1,
+            _ => (),
+        }
+    }
+
+    (vec![], source.to_string())
+}
+
+// Parses `{ ... }`, used both for the whole block and for nested groups.
+fn import_group(input: &mut Input) -> ParseResult<Imports> {
+    let mut imports = Imports::default();
+    let mut errors: Vec<ParseError> = vec![];
+
     match input.peek() {
         Some(t) if t.kind() == TK::OpenCurly => {
             input.eat();
@@ -58,7 +101,7 @@ pub fn imports(
 
             errors.push(error);
         }
-        None => return (product, errors, true),
+        None => return (imports, errors, true),
     }
 
     loop {
@@ -67,7 +110,16 @@ pub fn imports(
                 input.eat();
                 break;
             }
-            Some(t) if t.kind() == TK::Identifier => {}
+            Some(t) if t.kind() == TK::Identifier => {
+                let (import, mut import_errors, is_eof) = import_item(input);
+
+                errors.append(&mut import_errors);
+                imports.imports.push(import);
+
+                if is_eof {
+                    return (imports, errors, true);
+                }
+            }
             Some(t) => {
                 let rows = (t.row_col().0, t.row_col().0);
                 let location = Location::new(t.pos(), rows);
@@ -80,83 +132,149 @@ pub fn imports(
                 );
 
                 errors.push(error);
+                input.eat();
             }
-            None => return (product, errors, true),
+            None => return (imports, errors, true),
         }
+    }
 
-        match input.peek() {
-            Some(t) if t.kind() == TK::Identifier => {
-                let (name, mut name_errors, is_eof) = name(input);
+    (imports, errors, false)
+}
 
-                errors.append(&mut name_errors);
+// Parses a single entry of an import group: `a.b`, `a.b as c`, `a.b.*` or `a.b { ... }`.
+fn import_item(input: &mut Input) -> ParseResult<Import> {
+    let (name, mut errors, is_eof) = name(input);
 
-                let (path, import) = seek_file(name.clone());
+    if is_eof {
+        return (Import::ImportSingle(name, None), errors, true);
+    }
 
-                match import {
-                    Some(i) => {
-                        product.insert(ImportKey::Symbol(i), Some(path));
-                    }
-                    None => {
-                        product.insert(ImportKey::Module(path), None);
-                    }
-                };
+    if input.match_pattern(vec![TK::Dot, TK::Mul]) {
+        input.eat();
+        input.eat();
 
-                if is_eof {
-                    return (product, errors, is_eof);
-                }
+        return (Import::ImportAll(name), errors, false);
+    }
 
-                match input.peek() {
-                    Some(t) if t.kind() == TK::OpenCurly => {
-                        todo!()
-                    }
-                    _ => {
-                        imports.imports.push(Import::ImportSingle(name));
-                    }
+    match input.peek() {
+        Some(t) if t.kind() == TK::OpenCurly => {
+            let (group, mut group_errors, is_eof) = import_group(input);
+            errors.append(&mut group_errors);
+
+            (Import::ImportGroup(name, group), errors, is_eof)
+        }
+        Some(t) if t.kind() == TK::KwAs => {
+            input.eat();
+
+            match input.expect(TK::Identifier) {
+                Ok(Some(alias)) => {
+                    let alias = alias.literal();
+                    (Import::ImportSingle(name, Some(alias)), errors, false)
+                }
+                Ok(None) => (Import::ImportSingle(name, None), errors, true),
+                Err((error, _)) => {
+                    errors.push(error);
+                    (Import::ImportSingle(name, None), errors, false)
                 }
             }
-            Some(t) => {
-                println!("Unhandled token: {:?}", t.kind());
-                todo!()
-            }
-            None => return (product, errors, true),
         }
+        Some(_) => (Import::ImportSingle(name, None), errors, false),
+        None => (Import::ImportSingle(name, None), errors, true),
     }
+}
 
-    if imports.imports.is_empty() {
-        // Done with nothing else to do!
-        input.initiator_sender.send(()).unwrap();
-    } else {
-        imports.imports.iter().for_each(|import| {
-            input.block_counter.clone().fetch_add(1, SeqCst);
-
-            let (callback_sender, callback_reciever) = channel::<()>();
-
-            let block_coutner_clone = input.block_counter.clone();
-            let initiator_sender_clone = input.initiator_sender.clone();
-            thread::spawn(move || loop {
-                match callback_reciever.try_recv() {
-                    Ok(_) => {
-                        // You do your stuff lil buddy :3
-                        // println!("Got callback! ☑️");
-                        block_coutner_clone.fetch_sub(1, SeqCst);
-                        initiator_sender_clone.send(()).unwrap();
-                        break;
-                    }
-                    Err(TryRecvError::Empty) => {
-                        continue;
+// Flattens an import (and any group beneath it) into `product`, resolving every path to a file.
+fn resolve_import(
+    input: &Input,
+    prefix: &Name,
+    import: &Import,
+    product: &mut HashMap<ImportKey, ImportTarget>,
+    errors: &mut Vec<ParseError>,
+) {
+    let (key, target) = match import {
+        Import::ImportGroup(name, group) => {
+            let prefix = prefix.join(name);
+            for import in &group.imports {
+                resolve_import(input, &prefix, import, product, errors);
+            }
+            return;
+        }
+        Import::ImportSingle(name, alias) => {
+            let full = prefix.join(name);
+
+            match seek_file(&full, &input.search_paths) {
+                Ok((path, Some(symbol))) => {
+                    let key = ImportKey::Symbol(alias.clone().unwrap_or(symbol.clone()));
+                    let target = ImportTarget {
+                        path,
+                        symbol: Some(symbol),
+                        location: name.location.clone(),
+                    };
+
+                    (key, target)
+                }
+                Ok((path, None)) => {
+                    if alias.is_some() {
+                        let message =
+                            format!("Cannot alias the module `{}`, only its items.", path);
+                        errors.push(ParseError::new(message, name.location.clone()));
+                        return;
                     }
-                    _ => todo!(),
+
+                    let target = ImportTarget {
+                        path: path.clone(),
+                        symbol: None,
+                        location: name.location.clone(),
+                    };
+
+                    (ImportKey::Module(path), target)
+                }
+                Err(error) => {
+                    errors.push(error);
+                    return;
                 }
-            });
+            }
+        }
+        Import::ImportAll(name) => {
+            let full = prefix.join(name);
+
+            match seek_file(&full, &input.search_paths) {
+                Ok((path, None)) => {
+                    let target = ImportTarget {
+                        path: path.clone(),
+                        symbol: None,
+                        location: name.location.clone(),
+                    };
 
-            match import {
-                Import::ImportSingle(name) => {
-                    input.sender.send((name.clone(), callback_sender)).unwrap();
+                    (ImportKey::Wildcard(path), target)
+                }
+                Ok((_, Some(symbol))) => {
+                    let message =
+                        format!("`{}` is not a module, cannot import `*` from it.", symbol);
+                    errors.push(ParseError::new(
+                        message,
+                        full.segment_location(full.name.len() - 1),
+                    ));
+                    return;
+                }
+                Err(error) => {
+                    errors.push(error);
+                    return;
                 }
-                _ => todo!(),
             }
-        });
+        }
+    };
+
+    if product.contains_key(&key) {
+        let message = match &key {
+            ImportKey::Symbol(s) => format!("`{}` is imported more than once.", s),
+            ImportKey::Module(p) | ImportKey::Wildcard(p) => {
+                format!("Module `{}` is imported more than once.", p)
+            }
+        };
+        errors.push(ParseError::new(message, target.location));
+        return;
     }
 
-    (product, errors, false)
+    product.insert(key, target);
 }
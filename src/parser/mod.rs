@@ -1,23 +1,16 @@
-use std::{
-    collections::HashMap,
-    fs::File,
-    io::Read,
-    ops::Range,
-    path::Path,
-    sync::{
-        atomic::{AtomicUsize, Ordering::SeqCst},
-        mpsc::{channel, Sender, TryRecvError},
-        Arc, Mutex,
-    },
-    thread,
-};
+use std::{collections::HashMap, fs, ops::Range, path::PathBuf, sync::Arc, thread};
 
-use error::ParseError;
+use error::{ModuleError, ParseError};
+use graph::ModuleGraph;
 use import::imports as imports_parser;
+use search::SearchPaths;
 use stmt::stmt;
 
 use crate::{
-    ast::{DocComment, Expr, FuncNode, Location, Module, Name, Stmt, Type, TypeValue},
+    ast::{
+        Attribute, DocComment, Expr, ExternDecl, FuncNode, ImportKey, ImportTarget, Location, Module,
+        Stmt, Type, TypeValue, Visibility,
+    },
     lexer::{
         token::{Token, TokenKind},
         Lexer,
@@ -25,149 +18,178 @@ use crate::{
 };
 
 pub mod _type;
+pub mod attribute;
 pub mod error;
 pub mod expr;
+pub mod graph;
 pub mod import;
 pub mod name;
+pub mod search;
 pub mod stmt;
 pub mod var;
 pub mod struct_enum;
 
 pub struct Parser {
     name: String,
-}
-
-// Traverse the path to find the file, because the end can be a function or struct.
-fn seek_file(name: Name) -> (String, Option<String>) {
-    match name.name.len() {
-        1 => match name.name[0].clone() {
-            p if p.ends_with(".gh") => (p, None),
-            p => (format!("{}.gh", p), None),
-        },
-        len => {
-            let mut file_route = name.name[0..len - 1].iter().map(|x| x.clone()).collect::<Vec<String>>();
-
-            let last = file_route.last_mut().unwrap();
-            last.push_str(".gh");
-
-            let joined = file_route.join("/");
-
-            let path = Path::new(&joined);
-
-            let import = name.name[len - 1].clone();
-
-            if path.is_file() {
-                (path.to_string_lossy().to_string(), Some(import))
-            } else {
-                (name.name.join("/"), None)
-            }
-        }
-    }
+    graph: ModuleGraph,
+    search_paths: Arc<SearchPaths>,
+    /// Contents of the entry module when it doesn't come from a file.
+    source: Option<String>,
 }
 
 impl Parser {
-    pub fn new(path: &str) -> Self {
+    pub fn new(path: &str, search_paths: SearchPaths) -> Self {
         Self {
             name: path.to_string(),
+            graph: ModuleGraph::new(),
+            search_paths: Arc::new(search_paths),
+            source: None,
         }
     }
 
-    fn handle_task(
-        module_map_c: Arc<Mutex<HashMap<String, Module>>>,
-        task_sender_c: Sender<(Name, Sender<()>)>,
-        tc: Arc<AtomicUsize>,
-        name: Name,
-        sender: Sender<()>,
-        block_counter: Arc<AtomicUsize>,
-    ) {
-        tc.fetch_add(1, SeqCst);
-
-        let (path, _import) = seek_file(name);
+    /// Parses the entry module from `source` instead of reading it, `path` only names it.
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
 
-        let mut file = File::open(path.clone()).unwrap();
-        let mut contents = String::new();
+    pub fn graph(&self) -> &ModuleGraph {
+        &self.graph
+    }
 
-        file.read_to_string(&mut contents).unwrap();
+    fn parse_file(
+        path: &str,
+        source: Option<&str>,
+        search_paths: Arc<SearchPaths>,
+    ) -> Result<Module, String> {
+        let contents = match source {
+            Some(source) => source.to_string(),
+            None => fs::read_to_string(path).map_err(|err| err.to_string())?,
+        };
 
         let mut lexer = Lexer::new(&contents);
         let tokens = lexer.lex();
 
-        let mut input = Input::new(tokens, task_sender_c.clone(), sender, block_counter);
-
-        let module = module(&mut input, path.clone());
+        let mut input = Input::new(tokens).with_search_paths(search_paths.clone());
 
-        module_map_c.clone().lock().unwrap().insert(path, module);
+        let mut module = module(&mut input, path.to_string());
+        module.path = search_paths.module_path(path);
 
-        tc.fetch_sub(1, SeqCst);
+        Ok(module)
     }
 
-    pub fn parse(&mut self, path: &str) -> HashMap<String, Module> {
-        let (task_sender, task_reciever) = channel::<(Name, Sender<()>)>();
-
-        let task_counter = Arc::new(AtomicUsize::new(0));
-        // Increment by 1 so that we account for the main module.
-        let block_counter = Arc::new(AtomicUsize::new(1));
-
-        // Clone for ownership's sake.
-        let task_sender_c = task_sender.clone();
-        let block_counter_c = block_counter.clone();
-        let task_counter_c = task_counter.clone();
-        let modules = thread::spawn(move || {
-            let module_map = Arc::new(Mutex::new(HashMap::<String, Module>::new()));
-
-            loop {
-                match task_reciever.try_recv() {
-                    Ok((name, sender)) => {
-                        // println!("Parsing: {}", &name);
-                        let module_map_c = module_map.clone();
-                        let task_sender_c = task_sender_c.clone();
-
-                        let tc = task_counter_c.clone();
-                        let bc = block_counter_c.clone();
-                        thread::spawn(move || {
-                            Self::handle_task(module_map_c, task_sender_c, tc, name, sender, bc);
-                        });
-                    }
-                    Err(TryRecvError::Empty) => {
-                        if (task_counter_c.clone().load(SeqCst) == 0)
-                            && (block_counter_c.clone().load(SeqCst) == 0)
-                        {
-                            break;
-                        }
-                    }
-                    _ => todo!(),
+    /// Parses the entry module and everything it transitively imports, each file exactly once.
+    /// Files discovered at the same depth are parsed in parallel.
+    pub fn parse(&mut self, path: &str) -> Result<HashMap<String, Module>, ModuleError> {
+        let mut modules = HashMap::<String, Module>::new();
+
+        let in_memory = self.source.is_some().then(|| PathBuf::from(path));
+        let root = ModuleGraph::canonical(path)
+            .or(in_memory)
+            .ok_or_else(|| ModuleError::Unreadable {
+                path: path.to_string(),
+                reason: "No such file".to_string(),
+                imported_from: None,
+            })?;
+        self.graph.insert(root.clone(), path.to_string());
+        self.graph.set_root(root);
+
+        // (path of the module, the module and location that imported it)
+        let mut pending: Vec<(String, Option<(String, Location)>)> = vec![(path.to_string(), None)];
+
+        while !pending.is_empty() {
+            let wave = std::mem::take(&mut pending);
+
+            let parsed = thread::scope(|s| {
+                wave.iter()
+                    .map(|(path, _)| {
+                        let search_paths = self.search_paths.clone();
+                        let source = self.source.as_deref().filter(|_| *path == self.name);
+                        s.spawn(move || Self::parse_file(path, source, search_paths))
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Vec<Result<Module, String>>>()
+            });
+
+            for ((path, imported_from), result) in wave.into_iter().zip(parsed) {
+                // Only the entry module isn't imported by anything.
+                let entry = imported_from.is_none();
+                let mut module = result.map_err(|reason| ModuleError::Unreadable {
+                    path: path.clone(),
+                    reason,
+                    imported_from,
+                })?;
+
+                module.entry = entry;
+
+                if let Some(imports) = module.imports.take() {
+                    module.imports = Some(self.resolve_imports(&path, imports, &mut pending)?);
                 }
+
+                modules.insert(path, module);
             }
+        }
 
-            module_map
-        });
+        if let Some((chain, location)) = self.graph.find_cycle() {
+            let module = chain[chain.len() - 2].clone();
 
-        let (main_task_sender, main_task_receiver) = channel::<()>();
+            return Err(ModuleError::Cycle {
+                chain,
+                imported_from: (module, location),
+            });
+        }
 
-        let name = Name::from_path(path);
-        task_sender
-            .clone()
-            .send((name, main_task_sender.clone()))
-            .unwrap();
+        Ok(modules)
+    }
 
-        let bc_clone = block_counter.clone();
-        thread::spawn(move || loop {
-            match main_task_receiver.try_recv() {
-                Ok(_) => {
-                    bc_clone.fetch_sub(1, SeqCst);
-                    break;
-                }
-                Err(TryRecvError::Empty) => continue,
-                Err(err) => {
-                    println!("Error: {}", err.to_string());
-                    panic!()
+    /// Records the imports of `path` in the graph, queueing modules that were not seen yet, and
+    /// rewrites every import to the name its module is registered under.
+    fn resolve_imports(
+        &mut self,
+        path: &str,
+        imports: HashMap<ImportKey, ImportTarget>,
+        pending: &mut Vec<(String, Option<(String, Location)>)>,
+    ) -> Result<HashMap<ImportKey, ImportTarget>, ModuleError> {
+        // Only an entry module given as source has no file.
+        let key = ModuleGraph::canonical(path).unwrap_or_else(|| PathBuf::from(path));
+        let mut resolved = HashMap::new();
+
+        for (import_key, mut target) in imports {
+            let dep = target.path.clone();
+            let location = target.location.clone();
+
+            let dep_key = match ModuleGraph::canonical(&dep) {
+                Some(k) => k,
+                None => {
+                    return Err(ModuleError::Unreadable {
+                        path: dep,
+                        reason: "No such file".to_string(),
+                        imported_from: Some((path.to_string(), location)),
+                    })
                 }
+            };
+
+            if !self.graph.contains(&dep_key) {
+                self.graph.insert(dep_key.clone(), dep.clone());
+                pending.push((dep.clone(), Some((path.to_string(), location.clone()))));
             }
-        });
 
-        let modules = modules.join().unwrap();
+            let name = self.graph.name_of(&dep_key).unwrap().clone();
+            self.graph.add_edge(&key, dep_key, location);
+
+            let import_key = match import_key {
+                ImportKey::Module(_) => ImportKey::Module(name.clone()),
+                ImportKey::Wildcard(_) => ImportKey::Wildcard(name.clone()),
+                symbol => symbol,
+            };
+            target.path = name;
+
+            resolved.insert(import_key, target);
+        }
 
-        Arc::try_unwrap(modules).unwrap().into_inner().unwrap()
+        Ok(resolved)
     }
 }
 
@@ -177,30 +199,25 @@ pub struct Input {
     prev_pos: Range<usize>,
     prev_row: usize,
 
-    // Multithreading / Multimodule stuff
-    sender: Sender<(Name, Sender<()>)>,
-    initiator_sender: Sender<()>,
-    block_counter: Arc<AtomicUsize>,
+    search_paths: Arc<SearchPaths>,
 }
 
 impl Input {
-    pub fn new(
-        tokens: Vec<Token>,
-        sender: Sender<(Name, Sender<()>)>,
-        initiator_sender: Sender<()>,
-        block_counter: Arc<AtomicUsize>,
-    ) -> Self {
+    pub fn new(tokens: Vec<Token>) -> Self {
         Self {
             stream: tokens,
             pos: 0,
             prev_pos: 0..0,
             prev_row: 0,
-            sender,
-            initiator_sender,
-            block_counter,
+            search_paths: Arc::new(SearchPaths::default()),
         }
     }
 
+    pub fn with_search_paths(mut self, search_paths: Arc<SearchPaths>) -> Self {
+        self.search_paths = search_paths;
+        self
+    }
+
     pub fn eat(&mut self) -> Option<&Token> {
         let result = if self.pos > self.stream.len() - 1 {
             None
@@ -342,28 +359,40 @@ impl Input {
 }
 
 pub fn module(input: &mut Input, name: String) -> Module {
-    let mut fn_decls = HashMap::<String, (Type, Location)>::new();
+    let mut fn_decls = HashMap::<String, (Type, Location, Visibility)>::new();
     let mut fn_defns = HashMap::<String, (FuncNode, Location)>::new();
-    let mut externs = HashMap::<String, (Vec<(String, TypeValue)>, TypeValue)>::new();
+    let mut externs = HashMap::<String, ExternDecl>::new();
+    let mut attributes = HashMap::<String, Vec<Attribute>>::new();
     let mut imports = None;
+    let mut errors: Vec<ParseError> = vec![];
 
+    let mut docs = HashMap::<String, Vec<DocComment>>::new();
     let mut doc_comments: Vec<DocComment> = vec![];
 
+    // Doc comments since the last item and the row the last of them ends on.
+    let mut pending_docs: Vec<DocComment> = vec![];
+    let mut docs_end_row = 0;
+
     match input.peek() {
         Some(t) if t.kind() == TokenKind::KwImport => {
-            let (_imports, imports_errors, is_eof) = imports_parser(input, true);
+            let (_imports, mut imports_errors, is_eof) = imports_parser(input, true);
 
             imports = Some(_imports);
-
-            // TODO: Report the errors
+            errors.append(&mut imports_errors);
 
             if is_eof {
                 return Module {
                     name,
-                    imports: None,
+                    path: vec![],
+                    entry: false,
+                    imports,
                     fn_decls,
                     fn_defns,
                     externs,
+                    attributes,
+                    docs,
+                    doc_comments,
+                    errors,
                 };
             }
         }
@@ -371,6 +400,15 @@ pub fn module(input: &mut Input, name: String) -> Module {
     }
 
     loop {
+        // Doc comments document the item right below them, anything else the module.
+        let blank_line = input.peek().is_none_or(|t| {
+            let start_row = t.row_col().0 - t.literal().matches('\n').count();
+            start_row > docs_end_row + 1
+        });
+        if blank_line {
+            doc_comments.append(&mut pending_docs);
+        }
+
         let (stmt, is_eof) = match stmt(input) {
             Some(res) => res,
             None => break,
@@ -396,30 +434,59 @@ pub fn module(input: &mut Input, name: String) -> Module {
 
         match stmt {
             Stmt::Var(var) if is_func_def(&var.rhs) => {
-                if let Expr::Func(fn_node) = var.rhs {
+                if let Expr::Func(mut fn_node) = var.rhs {
                     if var.lhs.name.len() != 1 {
                         // TODO: Raise error.
                         continue;
                     }
+                    attributes
+                        .entry(var.lhs.name[0].clone())
+                        .or_default()
+                        .extend(var.attributes);
+                    fn_node.doc_comments = std::mem::take(&mut pending_docs);
+                    docs.entry(var.lhs.name[0].clone())
+                        .or_default()
+                        .extend(fn_node.doc_comments.iter().cloned());
                     fn_defns.insert(var.lhs.name[0].clone(), (fn_node, var.lhs.location));
                 }
             }
-            Stmt::Var(var) if is_func_decl(&var._type) => {
+            Stmt::Var(mut var) if is_func_decl(&var._type) => {
                 if var.lhs.name.len() != 1 {
                     // TODO: Raise error.
                     continue;
                 }
 
+                attributes
+                    .entry(var.lhs.name[0].clone())
+                    .or_default()
+                    .extend(var.attributes);
+                var.doc_comments = std::mem::take(&mut pending_docs);
+                docs.entry(var.lhs.name[0].clone())
+                    .or_default()
+                    .extend(var.doc_comments);
+
                 if let TypeValue::ExFunc((params, return_type)) = var._type.type_value {
-                    externs.insert(var.lhs.name[0].clone(), (params, *return_type));
+                    let decl = ExternDecl {
+                        params,
+                        return_type: *return_type,
+                        location: var.lhs.location,
+                    };
+                    externs.insert(var.lhs.name[0].clone(), decl);
                 } else {
-                    fn_decls.insert(var.lhs.name[0].clone(), (var._type, var.lhs.location));
+                    fn_decls.insert(
+                        var.lhs.name[0].clone(),
+                        (var._type, var.lhs.location, var.visibility),
+                    );
                 }
             }
             Stmt::DocComment(md) => {
-                doc_comments.push(md);
+                pending_docs.push(md);
+                docs_end_row = input.prev_row;
+            }
+            _ => {
+                pending_docs.clear();
+                continue;
             }
-            _ => continue,
         };
 
         if is_eof {
@@ -428,11 +495,19 @@ pub fn module(input: &mut Input, name: String) -> Module {
         }
     }
 
+    doc_comments.append(&mut pending_docs);
+
     Module {
         name,
+        path: vec![],
+        entry: false,
         imports,
         fn_decls,
         fn_defns,
         externs,
+        attributes,
+        docs,
+        doc_comments,
+        errors,
     }
 }
@@ -9,6 +9,7 @@ use super::{error::ParseResult, Input};
 pub fn name(input: &mut Input) -> ParseResult<Name> {
     let mut errors: Vec<ParseError> = vec![];
     let mut names: Vec<String> = vec![];
+    let mut segments: Vec<Location> = vec![];
 
     let (first_namespace, start_loc, start_row) = {
         let tok = input
@@ -19,6 +20,7 @@ pub fn name(input: &mut Input) -> ParseResult<Name> {
     };
 
     names.push(first_namespace);
+    segments.push(Location::new(input.prev_pos.clone(), (start_row, start_row)));
 
     loop {
         let kind = match input.peek() {
@@ -27,10 +29,16 @@ pub fn name(input: &mut Input) -> ParseResult<Name> {
         };
 
         match kind {
-            t if input.match_pattern(vec![TK::Dot, TK::Identifier]) => {
+            _ if input.match_pattern(vec![TK::Dot, TK::Identifier]) => {
                 input.eat().unwrap();
                 names.push(input.eat().unwrap().literal());
+                segments.push(Location::new(
+                    input.prev_pos.clone(),
+                    (input.prev_row, input.prev_row),
+                ));
             }
+            // Left for the caller, e.g. wildcard imports.
+            _ if input.match_pattern(vec![TK::Dot, TK::Mul]) => break,
             TK::Dot => {
                 input.eat().unwrap();
 
@@ -50,7 +58,7 @@ pub fn name(input: &mut Input) -> ParseResult<Name> {
     let span = start_loc..input.prev_pos.end;
     let rows = (start_row, input.prev_row);
     let location = Location::new(span, rows);
-    (Name::new(names, location), errors, false)
+    (Name::with_segments(names, location, segments), errors, false)
 }
 
 // #[test]
@@ -0,0 +1,173 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::ast::Name;
+
+use super::error::ParseError;
+
+/// Where imports are looked up. Relative paths are relative to the project root.
+#[derive(Debug, Clone)]
+pub struct SearchPaths {
+    /// Searched in order after the project root itself.
+    pub extra: Vec<PathBuf>,
+    /// Root of the standard library, `std.*` imports are only looked up here.
+    pub std: PathBuf,
+}
+
+impl SearchPaths {
+    pub fn new(extra: Vec<PathBuf>, std: Option<PathBuf>) -> Self {
+        Self {
+            extra,
+            std: std.unwrap_or_else(Self::bundled_std),
+        }
+    }
+
+    /// `$GAHL_STD` if set, otherwise the `std` directory next to the `gahl` executable or in a
+    /// directory above it, like the repository's for `target/debug/gahl`.
+    pub fn bundled_std() -> PathBuf {
+        if let Ok(path) = std::env::var("GAHL_STD") {
+            return PathBuf::from(path);
+        }
+
+        let exe_dir = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+
+        exe_dir
+            .ancestors()
+            .map(|dir| dir.join("std"))
+            .find(|std| std.join("io.gh").is_file())
+            // Imports from `std` fail, saying where it was looked for.
+            .unwrap_or_else(|| exe_dir.join("std"))
+    }
+
+    /// Import path of the module in `file`, the inverse of the lookup: `<std>/io.gh` is
+    /// `std.io` and `utils/mod.gh` is `utils`.
+    pub fn module_path(&self, file: &str) -> Vec<String> {
+        let file = Path::new(file).with_extension("");
+
+        let (prefix, relative) = match file.strip_prefix(&self.std) {
+            Ok(relative) => (Some("std".to_string()), relative),
+            Err(_) => {
+                let relative = self
+                    .extra
+                    .iter()
+                    .find_map(|base| file.strip_prefix(base).ok())
+                    .unwrap_or(&file);
+                (None, relative)
+            }
+        };
+
+        let mut path = prefix
+            .into_iter()
+            .chain(relative.components().filter_map(|c| match c {
+                Component::Normal(segment) => Some(segment.to_string_lossy().to_string()),
+                _ => None,
+            }))
+            .collect::<Vec<String>>();
+
+        if path.len() > 1 && path.last().is_some_and(|last| last == "mod") {
+            path.pop();
+        }
+
+        path
+    }
+
+    /// The directories to search for `name`, paired with the segments relative to them.
+    fn bases(&self, name: &Name) -> Vec<(PathBuf, usize)> {
+        match name.name.first() {
+            Some(first) if first == "std" => vec![(self.std.clone(), 1)],
+            _ => {
+                let mut bases = vec![(PathBuf::new(), 0)];
+                bases.extend(self.extra.iter().map(|p| (p.clone(), 0)));
+                bases
+            }
+        }
+    }
+}
+
+impl Default for SearchPaths {
+    fn default() -> Self {
+        Self::new(vec![], None)
+    }
+}
+
+enum Seek {
+    Found(String, Option<String>),
+    /// Gave up at the segment with this index.
+    Missing(usize),
+    Error(ParseError),
+}
+
+// Walks the segments of `name` below `base`, a segment is either a directory, a `<segment>.gh`
+// file or a directory with a `mod.gh` file, whatever follows the module is the imported item.
+fn seek_in(base: &Path, skip: usize, name: &Name, searched: &mut Vec<String>) -> Seek {
+    let segments = &name.name;
+    let mut dir = base.to_path_buf();
+
+    for i in skip..segments.len() {
+        dir.push(&segments[i]);
+
+        let candidates = [dir.with_extension("gh"), dir.join("mod.gh")];
+        let remaining = segments.len() - i - 1;
+
+        let found = candidates.iter().find(|c| c.is_file()).cloned();
+        searched.extend(
+            candidates
+                .iter()
+                .take_while(|c| Some(*c) != found.as_ref())
+                .map(|c| c.to_string_lossy().to_string()),
+        );
+
+        match found {
+            Some(file) if remaining <= 1 || !dir.is_dir() => {
+                let file = file.to_string_lossy().to_string();
+
+                return match remaining {
+                    0 => Seek::Found(file, None),
+                    1 => Seek::Found(file, Some(segments[i + 1].clone())),
+                    _ => {
+                        let message = format!(
+                            "`{}` is an item of `{}`, it has no members to import.",
+                            segments[i + 1],
+                            file
+                        );
+                        Seek::Error(ParseError::new(message, name.segment_location(i + 2)))
+                    }
+                };
+            }
+            _ if dir.is_dir() => continue,
+            _ => return Seek::Missing(i),
+        }
+    }
+
+    Seek::Missing(segments.len() - 1)
+}
+
+// Traverse the path to find the file, because the end can be a function or struct.
+// Returns the module file and the item named inside it, if any.
+pub fn seek_file(name: &Name, paths: &SearchPaths) -> Result<(String, Option<String>), ParseError> {
+    let mut searched: Vec<String> = vec![];
+    let mut deepest = 0;
+
+    for (base, skip) in paths.bases(name) {
+        match seek_in(&base, skip, name, &mut searched) {
+            Seek::Found(path, import) => return Ok((path, import)),
+            Seek::Error(error) => return Err(error),
+            Seek::Missing(i) => deepest = deepest.max(i),
+        }
+    }
+
+    let mut message = format!(
+        "No module named `{}` found, searched:\n  {}",
+        name.name[..=deepest].join("."),
+        searched.join("\n  ")
+    );
+    if name.name.first().is_some_and(|first| first == "std") && !paths.std.is_dir() {
+        message += &format!(
+            "\n`std` isn't at `{}`, set `std_path` under `[project]` in `config.toml` or `GAHL_STD` to where it is.",
+            paths.std.display()
+        );
+    }
+    Err(ParseError::new(message, name.segment_location(deepest)))
+}
@@ -1,7 +1,7 @@
 use crate::{
-    ast::{DocComment, Expr, Stmt},
+    ast::{DocComment, Expr, Location, Stmt, Var, Visibility},
     lexer::{token::TokenKind as TK, Lexer},
-    parser::{expr::expression, struct_enum::parse_enum, var::var},
+    parser::{attribute::attributes, expr::expression, struct_enum::parse_enum, var::var},
 };
 
 use super::{
@@ -31,9 +31,60 @@ pub fn stmt(input: &mut Input) -> Option<(Stmt, bool)> {
             let (expr, errors, is_eof) = expression(input);
             Some((Stmt::Expr(expr, errors), is_eof))
         }
+        TK::KwPub => {
+            let (pub_pos, pub_row) = {
+                let tok = input.eat().unwrap();
+                (tok.pos(), tok.row_col().0)
+            };
+
+            if !is_var(input) {
+                let mut product = Var::default();
+                let location = Location::new(pub_pos, (pub_row, pub_row));
+                let message = "Expected a declaration after `pub`.".to_string();
+                product.errors.push(ParseError::new(message, location));
+
+                return Some((Stmt::Var(product), input.peek().is_none()));
+            }
+
+            let (mut var, is_eof) = var(input);
+            var.visibility = Visibility::Public;
+
+            Some((Stmt::Var(var), is_eof))
+        }
+        TK::At => {
+            let start = input.peek().unwrap().pos();
+            let start_row = input.peek().unwrap().row_col().0;
+            let (attributes, mut errors, is_eof) = attributes(input);
+
+            let is_pub = input.peek().is_some_and(|t| t.kind() == TK::KwPub);
+            let declaration = match !is_eof && (is_pub || is_var(input)) {
+                true => stmt(input),
+                false => None,
+            };
+
+            match declaration {
+                Some((Stmt::Var(mut var), is_eof)) => {
+                    var.attributes = attributes;
+                    var.errors.append(&mut errors);
+                    Some((Stmt::Var(var), is_eof))
+                }
+                _ => {
+                    let mut product = Var::default();
+                    let location = Location::new(start, (start_row, input.prev_row));
+                    let message = "Expected a declaration after the attributes.".to_string();
+                    product.errors.append(&mut errors);
+                    product.errors.push(ParseError::new(message, location));
+
+                    Some((Stmt::Var(product), input.peek().is_none()))
+                }
+            }
+        }
         TK::DocComment => {
-            let doc_comment = input.eat().unwrap().literal();
-            Some((Stmt::DocComment(DocComment { md: doc_comment }), false))
+            let token = input.eat().unwrap();
+            let literal = token.literal();
+            // Tokens know the row they end on.
+            let row = token.row_col().0 - literal.matches('\n').count();
+            Some((Stmt::DocComment(DocComment::new(&literal, row)), false))
         }
         TK::KwEnum => {
             let (_enum, error, is_eof) = parse_enum(input);
@@ -59,19 +110,29 @@ pub fn block(input: &mut Input) -> ParseResult<Vec<Stmt>> {
                 input.eat();
                 break;
             }
-            None => return (product, vec![], true),
+            None => return (product, errors, true),
             _ => (),
         }
 
-        let (stmt, is_eof) = match stmt(input) {
+        let (mut stmt, is_eof) = match stmt(input) {
             Some(res) => res,
-            None => return (product, vec![], true),
+            None => return (product, errors, true),
         };
 
+        if let Stmt::Var(var) = &mut stmt {
+            errors.append(&mut var.errors);
+
+            // Nothing reads the attributes of locals.
+            for attribute in &var.attributes {
+                let message = "Attributes only apply to top-level declarations.".to_string();
+                errors.push(ParseError::new(message, attribute.location.clone()));
+            }
+        }
+
         product.push(stmt);
 
         if is_eof {
-            return (product, vec![], is_eof);
+            return (product, errors, is_eof);
         }
     }
 
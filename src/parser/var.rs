@@ -22,7 +22,7 @@ pub fn var(input: &mut Input) -> (Var, bool) {
     product.errors.append(&mut errors_lhs);
     product.lhs = lhs;
 
-    match input.peek() {
+    let (mut product, is_eof) = match input.peek() {
         Some(t) if t.kind() == TK::Column => {
             input.eat();
             product.is_decl = true;
@@ -57,7 +57,13 @@ pub fn var(input: &mut Input) -> (Var, bool) {
         }
         Some(_) => todo!(),
         _ => todo!(),
-    }
+    };
+
+    let span = product.lhs.location.span.start..input.prev_pos.end;
+    let rows = (product.lhs.location.rows.0, input.prev_row);
+    product.location = Location::new(span, rows);
+
+    (product, is_eof)
 
     // (product, false)
 }
@@ -0,0 +1,350 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    process::exit,
+    sync::Arc,
+    thread,
+};
+
+use crate::{
+    ast::{pretty::stmt_tree, Expr, Module, Stmt, TypeValue, Var},
+    checker::{
+        analyzer::{analyze_source, Modules},
+        mdir::{shunting_yard_this_mf, Function, Statement},
+        Checker,
+    },
+    interp::{host::Host, Interpreter, RuntimeError, Value, STACK_SIZE},
+    lexer::{token::TokenKind, Lexer},
+    parser::{import::split_import_block, search::SearchPaths, stmt::stmt, Input},
+};
+
+/// Name of the module holding everything declared in the session.
+const SESSION: &str = "<repl>";
+
+const HELP: &str = "\
+Declarations and imports are added to the session, `x := expr` keeps a local and anything else
+is evaluated and printed with its type. Functions have to be declared before they are defined.
+
+  :type <expr>    Type of the expression, without running it
+  :ast <expr>     Parse tree of the input
+  :load <file>    Adds the imports and declarations of a file
+  :quit           Leaves the REPL, as does end of input";
+
+struct Session {
+    search_paths: SearchPaths,
+    /// Contents of the import block of the session module, one entry per import.
+    imports: Vec<String>,
+    /// Declarations in the order they were entered.
+    items: String,
+    module: Arc<Module>,
+    modules: Modules,
+    /// Locals with their types, the checker's scope for every input. Their values are in `frame`.
+    locals: Vec<(String, TypeValue)>,
+    frame: HashMap<String, Value>,
+    interpreter: Interpreter,
+}
+
+impl Session {
+    fn new(search_paths: SearchPaths, overflow_checks: bool) -> Self {
+        Self {
+            search_paths,
+            imports: vec![],
+            items: String::new(),
+            module: Arc::new(Module::default()),
+            modules: Arc::new(HashMap::new()),
+            locals: vec![],
+            frame: HashMap::new(),
+            interpreter: Interpreter::new(Host::new(vec![SESSION.to_string()]), overflow_checks),
+        }
+    }
+
+    fn handle(&mut self, input: &str) {
+        if let Some(command) = input.trim().strip_prefix(':') {
+            let (command, arg) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            return self.command(command, arg.trim());
+        }
+
+        let (imports, items) = split_import_block(input);
+        if !imports.is_empty() {
+            self.declare(imports, &items);
+            return;
+        }
+
+        let stmt = match parse(input) {
+            Some(stmt) => stmt,
+            None => return,
+        };
+
+        match &stmt {
+            Stmt::Var(var) if is_declaration(var) => {
+                self.declare(vec![], input);
+            }
+            Stmt::DocComment(_) | Stmt::Enum(_) => {
+                self.declare(vec![], input);
+            }
+            _ => self.evaluate(&stmt, input),
+        }
+    }
+
+    fn command(&mut self, command: &str, arg: &str) {
+        match command {
+            "type" => {
+                if let Some(function) = parse(arg).and_then(|stmt| self.check_expr(&stmt, arg)) {
+                    println!("{}", function.return_type);
+                }
+            }
+            "ast" => {
+                if let Some(stmt) = parse(arg) {
+                    print!("{}", stmt_tree(&stmt));
+                }
+            }
+            "load" => match fs::read_to_string(arg) {
+                Ok(source) => {
+                    let (imports, items) = split_import_block(&source);
+                    if self.declare(imports, &items) {
+                        println!("Loaded `{arg}`.");
+                    }
+                }
+                Err(err) => eprintln!("\x1b[31mError:\x1b[0m could not read `{arg}`: {err}"),
+            },
+            "help" => println!("{HELP}"),
+            "quit" | "q" => exit(0),
+            other => eprintln!("Unknown command `:{other}`, `:help` lists the commands."),
+        }
+    }
+
+    /// Adds `imports` and `items` to the session module, unless they don't check. Every module is
+    /// checked again and loaded into the interpreter, so redefined functions replace the old ones.
+    fn declare(&mut self, imports: Vec<String>, items: &str) -> bool {
+        let mut all_imports = self.imports.clone();
+        for import in imports {
+            if !all_imports.contains(&import) {
+                all_imports.push(import);
+            }
+        }
+        let all_items = format!("{}{}\n", self.items, items.trim());
+
+        let source = format!("import {{\n{}\n}}\n{}", all_imports.join("\n"), all_items);
+        let (modules, mdirs) = match analyze_source(SESSION, &source, self.search_paths.clone()) {
+            Ok(analyzed) => analyzed,
+            Err(errors) => {
+                print!("{errors}");
+                return false;
+            }
+        };
+
+        for (_, mdir) in mdirs {
+            self.interpreter.load(mdir);
+        }
+        self.module = modules[SESSION].clone();
+        self.modules = modules;
+        self.imports = all_imports;
+        self.items = all_items;
+
+        true
+    }
+
+    fn evaluate(&mut self, stmt: &Stmt, source: &str) {
+        let function = match stmt {
+            Stmt::Expr(..) => self.check_expr(stmt, source),
+            Stmt::Var(var) if var.is_decl && !var.rhs.is_void() => self.check_var(stmt, source),
+            _ => {
+                eprintln!("\x1b[31mError:\x1b[0m only `name := expr` declares a local here.");
+                None
+            }
+        };
+        let function = match function {
+            Some(f) => f,
+            None => return,
+        };
+
+        let result = self.interpreter.block(&function, &mut self.frame);
+        let _ = io::stdout().flush();
+
+        match result {
+            Ok(value) => {
+                if let Some((name, ty)) = function.vars.first() {
+                    self.locals.retain(|(local, _)| local != name);
+                    self.locals.push((name.clone(), ty.clone()));
+                } else if value != Value::Void {
+                    println!("{value} : {}", function.return_type);
+                }
+            }
+            Err(RuntimeError::Exit(code)) => exit(code),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+
+    // A checker for input outside of any function, with the locals of the session in scope.
+    fn checker<'a>(&'a self) -> Checker<'a> {
+        let mut checker = Checker::new(&self.module, self.modules.clone());
+        checker.push_stack();
+
+        for (name, ty) in &self.locals {
+            checker.define(name, ty.clone());
+        }
+
+        checker
+    }
+
+    // The input runs as the body of a function. All locals of the session live in heap cells, so
+    // passing one by address works in any later input.
+    fn function(&self, block: Statement, return_type: TypeValue) -> Function {
+        let mut function = Function::default();
+        function.name = SESSION.to_string();
+        function.symbol = SESSION.to_string();
+        function.return_type = return_type;
+        function.escapes = self.locals.iter().map(|(name, _)| name.clone()).collect();
+
+        if let Statement::Var(var) = &block {
+            function.vars.push((var.lhs.clone(), var.ty.clone()));
+            function.escapes.insert(var.lhs.clone());
+        }
+        function.block.push(block);
+
+        function
+    }
+
+    fn check_expr(&self, stmt: &Stmt, source: &str) -> Option<Function> {
+        let (expr, errors) = match stmt {
+            Stmt::Expr(expr, errors) => (expr, errors),
+            _ => {
+                eprintln!("\x1b[31mError:\x1b[0m expected an expression.");
+                return None;
+            }
+        };
+
+        let mut checker = self.checker();
+        checker.push_parse_errors(errors);
+        let (out, ty) = checker.expr_ty(expr);
+
+        if !checker.errors().is_empty() || !checker.warnings().is_empty() {
+            checker.print_interrupts_in(source);
+        }
+        if !checker.errors().is_empty() {
+            return None;
+        }
+
+        let block = Statement::Expr(shunting_yard_this_mf(out), expr.get_location());
+        Some(self.function(block, ty))
+    }
+
+    fn check_var(&self, stmt: &Stmt, source: &str) -> Option<Function> {
+        let mut checker = self.checker();
+        if let Stmt::Var(var) = stmt {
+            checker.push_parse_errors(&var.errors);
+        }
+        let block = checker.stmt_ty(stmt);
+
+        if !checker.errors().is_empty() || !checker.warnings().is_empty() {
+            checker.print_interrupts_in(source);
+        }
+        if !checker.errors().is_empty() {
+            return None;
+        }
+
+        Some(self.function(block, TypeValue::Void))
+    }
+}
+
+fn is_declaration(var: &Var) -> bool {
+    let is_func_type = matches!(
+        var._type.type_value,
+        TypeValue::Func(..) | TypeValue::ExFunc(..)
+    );
+
+    is_func_type || matches!(var.rhs, Expr::Func(_)) || !var.attributes.is_empty()
+}
+
+fn parse(source: &str) -> Option<Stmt> {
+    let tokens = Lexer::new(source).lex();
+    let mut input = Input::new(tokens);
+
+    match input.peek() {
+        Some(t) if t.kind() != TokenKind::EOF => stmt(&mut input).map(|(stmt, _)| stmt),
+        _ => None,
+    }
+}
+
+// Unclosed braces and parentheses continue the input on the next line.
+fn is_complete(source: &str) -> bool {
+    let depth = Lexer::new(source)
+        .lex()
+        .iter()
+        .map(|t| match t.kind() {
+            TokenKind::OpenCurly | TokenKind::OpenParen => 1,
+            TokenKind::ClosedCurly | TokenKind::ClosedParen => -1,
+            _ => 0,
+        })
+        .sum::<i32>();
+
+    depth <= 0
+}
+
+fn session(search_paths: SearchPaths, overflow_checks: bool) {
+    let mut session = Session::new(search_paths, overflow_checks);
+    session.declare(vec![], "");
+
+    // Inputs the compiler can't handle yet abort that input, not the session.
+    panic::set_hook(Box::new(|info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        eprintln!("\x1b[31mError:\x1b[0m the compiler can't handle this input: {message}");
+    }));
+
+    println!(
+        "Gahl {} REPL, `:help` lists the commands.",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        let _ = io::stdout().flush();
+
+        // Not holding the lock, programs read from stdin too.
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                println!();
+                return;
+            }
+            Ok(_) => input.push_str(&line),
+        }
+
+        if !is_complete(&input) {
+            continue;
+        }
+
+        let entered = std::mem::take(&mut input);
+        if entered.trim().is_empty() {
+            continue;
+        }
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| session.handle(&entered)));
+    }
+}
+
+/// Runs the REPL on stdin until it ends or `:quit`.
+pub fn run(search_paths: SearchPaths, overflow_checks: bool) {
+    let handle = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || session(search_paths, overflow_checks));
+
+    match handle {
+        Ok(handle) => {
+            let _ = handle.join();
+        }
+        Err(err) => {
+            eprintln!("Error starting the REPL: {}", err);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{DocComment, Module},
+    checker::{analyzer::analyze_source, mdir::MiddleIR},
+    parser::{import::split_import_block, search::SearchPaths},
+};
+
+/// Name of the module a doc-test is checked as.
+const DOC_TEST: &str = "<doc-test>";
+
+/// A ```` ```gahl ```` block of a doc comment.
+pub struct DocTest {
+    /// File of the module the comment is in.
+    pub file: String,
+    /// Line of the opening fence.
+    pub line: usize,
+    pub code: String,
+    /// Import path of the documented module.
+    module_path: Vec<String>,
+}
+
+impl DocTest {
+    pub fn name(&self) -> String {
+        format!("{}:{}", self.file, self.line)
+    }
+
+    /// The block as a program of its own. Everything public of the documented module is
+    /// imported, and unless the block has a `main` it becomes the body of one.
+    pub fn program(&self) -> String {
+        self.wrap().0
+    }
+
+    // The program, with how many more lines come before a line of the block in it than in the
+    // block.
+    fn wrap(&self) -> (String, isize) {
+        let (mut imports, rest) = split_import_block(&self.code);
+        imports.push(format!("{}.*", self.module_path.join(".")));
+
+        let mut program = format!("import {{\n{}\n}}\n\n", imports.join("\n"));
+
+        let has_main = rest.lines().any(|line| {
+            let line = line.trim_start();
+            line.strip_prefix("main")
+                .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+        });
+
+        if !has_main {
+            program += "main : fn() void\nmain = fn() {\n";
+        }
+        // `rest` starts on the line the import block ends on.
+        let rest_row = self.code[..self.code.len() - rest.len()]
+            .matches('\n')
+            .count();
+        let offset = program.matches('\n').count() as isize - rest_row as isize;
+
+        if has_main {
+            program += &rest;
+        } else {
+            // Blank lines are kept for the lines to stay where they are.
+            for line in rest.lines() {
+                match line.trim().is_empty() {
+                    true => program += "\n",
+                    false => program += &format!("  {line}\n"),
+                }
+            }
+            program += "}\n";
+        }
+
+        (program, offset)
+    }
+
+    /// `text` with the positions in the program, like `<doc-test>:8`, turned into the ones in the
+    /// file the block is in.
+    pub fn relocate(&self, text: &str) -> String {
+        let (_, offset) = self.wrap();
+        let prefix = format!("{DOC_TEST}:");
+
+        let mut relocated = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(&prefix) {
+            relocated += &rest[..start];
+            rest = &rest[start + prefix.len()..];
+
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            // The code starts on the line after the opening fence.
+            let line = rest[..digits]
+                .parse::<isize>()
+                .ok()
+                .map(|row| row - offset + self.line as isize)
+                .filter(|line| *line > self.line as isize);
+            match line {
+                Some(line) => {
+                    relocated += &format!("{}:{line}", self.file);
+                    rest = &rest[digits..];
+                }
+                None => relocated += &prefix,
+            }
+        }
+
+        relocated + rest
+    }
+}
+
+// The code blocks of `doc`, with the line each starts on.
+fn code_blocks(doc: &DocComment) -> Vec<(usize, String)> {
+    let mut blocks = vec![];
+    let mut current: Option<(usize, String)> = None;
+
+    for (i, line) in doc.md.lines().enumerate() {
+        match &mut current {
+            Some((start, code)) if line.trim() == "```" => {
+                blocks.push((doc.row + *start + 1, std::mem::take(code)));
+                current = None;
+            }
+            Some((_, code)) => {
+                code.push_str(line);
+                code.push('\n');
+            }
+            None if line.trim() == "```gahl" => current = Some((i, String::new())),
+            None => (),
+        }
+    }
+
+    blocks
+}
+
+/// Every doc-test of the project's modules, `std` has no say in whether a project's tests pass.
+pub fn collect(modules: &HashMap<String, Module>) -> Vec<DocTest> {
+    let mut tests = vec![];
+
+    for module in modules.values() {
+        if module.path.first().is_some_and(|segment| segment == "std") {
+            continue;
+        }
+
+        let docs = module
+            .doc_comments
+            .iter()
+            .chain(module.docs.values().flatten());
+
+        for doc in docs {
+            for (line, code) in code_blocks(doc) {
+                tests.push(DocTest {
+                    file: module.name.clone(),
+                    line,
+                    code,
+                    module_path: module.path.clone(),
+                });
+            }
+        }
+    }
+
+    tests.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    tests
+}
+
+/// Checks the doc-test, returning the modules it uses and its own module.
+pub fn check(
+    test: &DocTest,
+    search_paths: SearchPaths,
+) -> Result<(HashMap<String, MiddleIR>, MiddleIR), String> {
+    let (_, mut mdirs) = analyze_source(DOC_TEST, &test.program(), search_paths)?;
+    let program = mdirs.remove(DOC_TEST).unwrap_or_else(MiddleIR::new);
+
+    Ok((mdirs, program))
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env, fs,
+    io::{self, Write},
+    process::Command,
+    thread,
+};
+
+use crate::{
+    ast::{Location, Module, TypeValue},
+    checker::{
+        analyzer::check_quietly,
+        mangle,
+        mdir::{Expression, Function, Literal, MiddleIR, Statement},
+    },
+    interp::{host::Host, Interpreter, RuntimeError, Value, STACK_SIZE},
+    parser::search::SearchPaths,
+};
+
+pub mod doc;
+
+/// Results of a test run, printed as the tests finish.
+#[derive(Default)]
+pub struct Report {
+    passed: usize,
+    /// Names of the failed tests with why they failed.
+    failures: Vec<(String, String)>,
+}
+
+impl Report {
+    pub fn record(&mut self, name: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                println!("test {name} ... \x1b[32mok\x1b[0m");
+                self.passed += 1;
+            }
+            Err(why) => {
+                println!("test {name} ... \x1b[31mFAILED\x1b[0m");
+                self.failures.push((name.to_string(), why));
+            }
+        }
+        let _ = io::stdout().flush();
+    }
+
+    /// Prints why the failed tests failed and the totals, returns whether everything passed.
+    pub fn finish(self) -> bool {
+        if !self.failures.is_empty() {
+            println!("\nfailures:");
+
+            for (name, why) in &self.failures {
+                println!("\n---- {name} ----\n{}", why.trim_end());
+            }
+        }
+
+        let result = match self.failures.is_empty() {
+            true => "\x1b[32mok\x1b[0m",
+            false => "\x1b[31mFAILED\x1b[0m",
+        };
+        println!(
+            "\ntest result: {result}. {} passed; {} failed\n",
+            self.passed,
+            self.failures.len()
+        );
+
+        self.failures.is_empty()
+    }
+}
+
+/// A function marked `@test`.
+pub struct TestFunction {
+    /// Module path and function name, `utils.adds_up`.
+    pub name: String,
+    pub symbol: String,
+}
+
+/// The `@test` functions of the project's modules, by name.
+pub fn functions(modules: &HashMap<String, Module>) -> Vec<TestFunction> {
+    let mut tests = vec![];
+
+    for module in modules.values() {
+        if module.path.first().is_some_and(|segment| segment == "std") {
+            continue;
+        }
+
+        for (function, attributes) in &module.attributes {
+            if attributes.iter().any(|attribute| attribute.name == "test") {
+                tests.push(TestFunction {
+                    name: format!("{}.{}", module.path.join("."), function),
+                    symbol: mangle::symbol(module, function),
+                });
+            }
+        }
+    }
+
+    tests.sort_by(|a, b| a.name.cmp(&b.name));
+    tests
+}
+
+// A `main` that only calls the test `symbol`.
+fn harness_main(symbol: &str) -> MiddleIR {
+    let call = Literal::Call(TypeValue::Void, symbol.to_string(), vec![]);
+
+    let mut function = Function::default();
+    function.name = "main".to_string();
+    function.symbol = "main".to_string();
+    function.return_type = TypeValue::Void;
+    function.block.push(Statement::Expr(
+        VecDeque::from([Expression::Literal(call)]),
+        Location::default(),
+    ));
+
+    let mut mdir = MiddleIR::new();
+    mdir.insert_function(function);
+    mdir
+}
+
+// Runs `main` of `program` in the interpreter, after loading `modules`. Returns the exit code, or
+// the runtime error it stopped on.
+fn run_program(
+    modules: HashMap<String, MiddleIR>,
+    program: MiddleIR,
+    args: Vec<String>,
+    overflow_checks: bool,
+) -> Result<i32, RuntimeError> {
+    let mut interpreter = Interpreter::new(Host::new(args), overflow_checks);
+    for (_, mdir) in modules {
+        interpreter.load(mdir);
+    }
+    // Loaded last, so its `main` replaces the one of the project.
+    interpreter.load(program);
+
+    let result = interpreter.call("main", vec![]);
+    let _ = io::stdout().flush();
+
+    match result {
+        Ok(Value::Int(_, code)) => Ok(code as i32),
+        Ok(_) => Ok(0),
+        Err(RuntimeError::Exit(code)) => Ok(code),
+        Err(error) => Err(error),
+    }
+}
+
+/// Runs the test `name` in this process, as `gahl test` does in a subprocess for every test.
+/// Returns the exit code, 0 when it passed.
+pub fn harness(
+    name: &str,
+    modules: HashMap<String, Module>,
+    search_paths: SearchPaths,
+    overflow_checks: bool,
+) -> i32 {
+    let name = name.to_string();
+
+    let handle = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            if let Some(test) = doc::collect(&modules)
+                .into_iter()
+                .find(|t| t.name() == name)
+            {
+                // Errors point at the program the block became, not at the block.
+                return match doc::check(&test, search_paths) {
+                    Ok((modules, program)) => {
+                        match run_program(modules, program, vec![name], overflow_checks) {
+                            Ok(code) => code,
+                            Err(error) => {
+                                eprintln!("{}", test.relocate(&error.to_string()));
+                                101
+                            }
+                        }
+                    }
+                    Err(errors) => {
+                        eprint!("doesn't compile:\n{}", test.relocate(&errors));
+                        1
+                    }
+                };
+            }
+
+            let symbol = match functions(&modules).into_iter().find(|t| t.name == name) {
+                Some(test) => test.symbol,
+                None => {
+                    eprintln!("\x1b[31mError:\x1b[0m there is no test `{name}`.");
+                    return 1;
+                }
+            };
+
+            let checked = check_quietly(modules, |module| {
+                fs::read_to_string(module).unwrap_or_default()
+            });
+            match checked {
+                Ok((_, mdirs)) => {
+                    match run_program(mdirs, harness_main(&symbol), vec![name], overflow_checks) {
+                        Ok(code) => code,
+                        Err(error) => {
+                            eprintln!("{}", error);
+                            101
+                        }
+                    }
+                }
+                Err(errors) => {
+                    eprint!("{errors}");
+                    1
+                }
+            }
+        });
+
+    match handle.map(|handle| handle.join()) {
+        Ok(Ok(code)) => code,
+        Ok(Err(_)) => 101,
+        Err(err) => {
+            eprintln!("Error starting the test: {}", err);
+            1
+        }
+    }
+}
+
+// Runs the test `name` in a `gahl test --harness` process of its own, so that a crash only fails
+// that test.
+fn spawn(name: &str, release: bool) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|err| format!("can't find `gahl`: {err}"))?;
+
+    let mut command = Command::new(exe);
+    command.args(["test", "--harness", name]);
+    if release {
+        command.arg("--release");
+    }
+    let output = command
+        .output()
+        .map_err(|err| format!("can't start the test: {err}"))?;
+
+    let mut why = String::from_utf8_lossy(&output.stdout).into_owned();
+    why += &String::from_utf8_lossy(&output.stderr);
+
+    let status = match output.status.code() {
+        Some(0) => return Ok(()),
+        // Runtime errors, failed assertions included, explain themselves.
+        Some(101) => return Err(why),
+        Some(code) => format!("exited with {code}"),
+        None => "terminated by a signal".to_string(),
+    };
+
+    match why.trim().is_empty() {
+        true => Err(status),
+        false => Err(format!("{}\n{status}", why.trim_end())),
+    }
+}
+
+fn run_all(kind: &str, names: Vec<String>, release: bool) -> bool {
+    println!("running {} {kind}", names.len());
+
+    let mut report = Report::default();
+    for name in names {
+        report.record(&name, spawn(&name, release));
+    }
+
+    report.finish()
+}
+
+/// Runs the tests whose name contains `filter` and prints how they went, the `@test` functions
+/// first and then the doc-tests. Returns whether the project checks and all of them passed.
+pub fn run(
+    modules: HashMap<String, Module>,
+    filter: Option<&str>,
+    doc_only: bool,
+    release: bool,
+) -> bool {
+    let selected = |name: &String| filter.is_none_or(|filter| name.contains(filter));
+
+    let tests = match doc_only {
+        true => vec![],
+        false => functions(&modules)
+            .into_iter()
+            .map(|t| t.name)
+            .filter(selected)
+            .collect(),
+    };
+    let doc_tests = doc::collect(&modules)
+        .into_iter()
+        .map(|t| t.name())
+        .filter(selected)
+        .collect();
+
+    // Errors in the project would fail every test the same way.
+    if let Err(errors) = check_quietly(modules, |module| {
+        fs::read_to_string(module).unwrap_or_default()
+    }) {
+        eprint!("{errors}");
+        return false;
+    }
+
+    let mut passed = true;
+    if !doc_only {
+        passed &= run_all("tests", tests, release);
+    }
+    passed &= run_all("doc-tests", doc_tests, release);
+
+    passed
+}
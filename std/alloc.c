@@ -0,0 +1,63 @@
+// Allocators for the `arena` and `manual` memory modes, linked instead of Boehm GC.
+// The compiler only ever allocates through `gahl_arena_alloc` or `gahl_heap_alloc`.
+
+#include <stdint.h>
+#include <stdlib.h>
+
+#define GAHL_ARENA_CHUNK (64 * 1024)
+
+typedef struct gahl_chunk {
+    struct gahl_chunk *prev;
+    size_t used;
+    size_t size;
+    _Alignas(16) unsigned char data[];
+} gahl_chunk;
+
+static gahl_chunk *gahl_arena = NULL;
+
+static void gahl_arena_release(void) {
+    while (gahl_arena != NULL) {
+        gahl_chunk *prev = gahl_arena->prev;
+        free(gahl_arena);
+        gahl_arena = prev;
+    }
+}
+
+void gahl_arena_init(void) {
+    atexit(gahl_arena_release);
+}
+
+// Bump allocates out of the current chunk, starting a new one once it is full.
+void *gahl_arena_alloc(int64_t size) {
+    size_t aligned = ((size_t)size + 15) & ~(size_t)15;
+
+    if (gahl_arena == NULL || gahl_arena->used + aligned > gahl_arena->size) {
+        size_t chunk_size = aligned > GAHL_ARENA_CHUNK ? aligned : GAHL_ARENA_CHUNK;
+        gahl_chunk *chunk = malloc(sizeof(gahl_chunk) + chunk_size);
+
+        if (chunk == NULL) {
+            abort();
+        }
+
+        chunk->prev = gahl_arena;
+        chunk->used = 0;
+        chunk->size = chunk_size;
+        gahl_arena = chunk;
+    }
+
+    void *ptr = gahl_arena->data + gahl_arena->used;
+    gahl_arena->used += aligned;
+
+    return ptr;
+}
+
+// Zeroed like the other allocators, released with `std.mem.release`.
+void *gahl_heap_alloc(int64_t size) {
+    void *ptr = calloc(1, (size_t)size);
+
+    if (ptr == NULL) {
+        abort();
+    }
+
+    return ptr;
+}
@@ -0,0 +1,202 @@
+// Runtime support for the Gahl standard library.
+// Compiled and linked into every Gahl binary by `gahl build`, the `std` modules call into it
+// through `extern` declarations.
+
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+static int gahl_argc = 0;
+static char **gahl_argv = NULL;
+
+// glibc hands the program arguments to initializers too, Gahl's `main` takes none.
+__attribute__((constructor)) static void gahl_rt_init(int argc, char **argv, char **envp) {
+    (void)envp;
+    gahl_argc = argc;
+    gahl_argv = argv;
+}
+
+// std.io
+
+void gahl_rt_print_str(const char *s) {
+    fputs(s, stdout);
+}
+
+void gahl_rt_println_str(const char *s) {
+    puts(s);
+}
+
+void gahl_rt_print_i32(int n) {
+    printf("%d", n);
+}
+
+void gahl_rt_println_i32(int n) {
+    printf("%d\n", n);
+}
+
+// Reads a line from stdin without the trailing newline, an empty string at EOF.
+char *gahl_rt_read_line(void) {
+    char *line = NULL;
+    size_t cap = 0;
+    ssize_t len = getline(&line, &cap, stdin);
+
+    if (len < 0) {
+        free(line);
+        return calloc(1, 1);
+    }
+
+    if (len > 0 && line[len - 1] == '\n') {
+        line[len - 1] = '\0';
+    }
+
+    return line;
+}
+
+// std.fs, files are handed out to Gahl code as small integer handles.
+
+#define GAHL_MAX_FILES 64
+
+static FILE *gahl_files[GAHL_MAX_FILES];
+
+int gahl_rt_fs_open(const char *path, const char *mode) {
+    for (int i = 0; i < GAHL_MAX_FILES; i++) {
+        if (gahl_files[i] == NULL) {
+            FILE *f = fopen(path, mode);
+            if (f == NULL) {
+                return -1;
+            }
+
+            gahl_files[i] = f;
+            return i;
+        }
+    }
+
+    return -1;
+}
+
+static FILE *gahl_rt_fs_get(int handle) {
+    if (handle < 0 || handle >= GAHL_MAX_FILES) {
+        return NULL;
+    }
+
+    return gahl_files[handle];
+}
+
+// Reads everything left in the file, an empty string on error.
+char *gahl_rt_fs_read(int handle) {
+    FILE *f = gahl_rt_fs_get(handle);
+    size_t len = 0;
+    size_t cap = 256;
+    char *buf = malloc(cap);
+
+    if (f != NULL) {
+        size_t n;
+        while ((n = fread(buf + len, 1, cap - len - 1, f)) > 0) {
+            len += n;
+            if (cap - len - 1 == 0) {
+                cap *= 2;
+                buf = realloc(buf, cap);
+            }
+        }
+    }
+
+    buf[len] = '\0';
+    return buf;
+}
+
+int gahl_rt_fs_write(int handle, const char *s) {
+    FILE *f = gahl_rt_fs_get(handle);
+    if (f == NULL) {
+        return -1;
+    }
+
+    return (int)fwrite(s, 1, strlen(s), f);
+}
+
+int gahl_rt_fs_close(int handle) {
+    FILE *f = gahl_rt_fs_get(handle);
+    if (f == NULL) {
+        return -1;
+    }
+
+    gahl_files[handle] = NULL;
+    return fclose(f);
+}
+
+// std.mem
+
+void *gahl_rt_mem_alloc(int size) {
+    return calloc(1, (size_t)size);
+}
+
+int gahl_rt_mem_release(void *ptr) {
+    free(ptr);
+    return 0;
+}
+
+int gahl_rt_mem_copy(void *dst, const void *src, int size) {
+    memcpy(dst, src, (size_t)size);
+    return size;
+}
+
+// std.process
+
+void gahl_rt_exit(int code) {
+    fflush(NULL);
+    _exit(code);
+}
+
+int gahl_rt_arg_count(void) {
+    return gahl_argc;
+}
+
+const char *gahl_rt_arg(int i) {
+    if (i < 0 || i >= gahl_argc) {
+        return "";
+    }
+
+    return gahl_argv[i];
+}
+
+// Builtins: `assert` and `assert_eq`, the checker calls the one for the type of the values.
+
+#define GAHL_RT_ASSERT(suffix, type, format)                                                   \
+    void gahl_rt_assert_##suffix(type value, const char *location) {                            \
+        if (value == 0) {                                                                      \
+            fflush(stdout);                                                                    \
+            fprintf(stderr, "assertion failed at %s\n", location);                             \
+            exit(101);                                                                         \
+        }                                                                                      \
+    }                                                                                          \
+                                                                                               \
+    void gahl_rt_assert_eq_##suffix(type left, type right, const char *location) {              \
+        if (left != right) {                                                                   \
+            fflush(stdout);                                                                    \
+            fprintf(stderr, "assertion failed at %s: `left == right`\n  left: %" format        \
+                            "\n right: %" format "\n", location, left, right);                  \
+            exit(101);                                                                         \
+        }                                                                                      \
+    }
+
+GAHL_RT_ASSERT(i8, int8_t, PRId8)
+GAHL_RT_ASSERT(i16, int16_t, PRId16)
+GAHL_RT_ASSERT(i32, int32_t, PRId32)
+GAHL_RT_ASSERT(i64, int64_t, PRId64)
+GAHL_RT_ASSERT(isize, intptr_t, PRIdPTR)
+GAHL_RT_ASSERT(u8, uint8_t, PRIu8)
+GAHL_RT_ASSERT(u16, uint16_t, PRIu16)
+GAHL_RT_ASSERT(u32, uint32_t, PRIu32)
+GAHL_RT_ASSERT(u64, uint64_t, PRIu64)
+GAHL_RT_ASSERT(usize, uintptr_t, PRIuPTR)
+
+void gahl_rt_assert_eq_str(const char *left, const char *right, const char *location) {
+    if (strcmp(left, right) != 0) {
+        fflush(stdout);
+        fprintf(stderr, "assertion failed at %s: `left == right`\n  left: \"%s\"\n right: \"%s\"\n",
+                location, left, right);
+        exit(101);
+    }
+}